@@ -0,0 +1,553 @@
+//! Level editor of dev builds: paints the `main layer`, places the objects of the
+//! `logic` and `decorations` layers, plays the level offline and saves it back as
+//! Tiled json.
+
+use macroquad::{
+    experimental::{
+        collections::storage,
+        scene::{self, Handle},
+    },
+    prelude::*,
+    ui::{root_ui, widgets},
+};
+use nanoserde::DeJson;
+
+use crate::{
+    gui::Scene,
+    maps::{self, MapId, TiledMap, TiledObject},
+    nodes::{Camera, Decoration, Fxses, LevelBackground, NakamaRollbackGame},
+    GameType, Resources,
+};
+
+/// Buttons of the side panel leaving the editing.
+enum Action {
+    Play,
+    Back,
+}
+
+/// What a left click puts into the map, a right click takes it out again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tool {
+    /// The `main layer` tile of that gid
+    Tile(u32),
+    /// A `logic` object, dragged out over tiles unless it is one of `POINTS`
+    Object(&'static str),
+    /// A `decorations` object of that gid
+    Decoration(u32),
+}
+
+// `logic` objects standing on a single point, see `simulation::Map::add_object`
+const POINTS: [&str; 3] = ["spawn", "flag_blue", "flag_green"];
+const RECTS: [&str; 7] = [
+    "hill",
+    "water",
+    "pickup_zone",
+    "no_pickups",
+    "moving_platform",
+    "crusher",
+    "spike_trap",
+];
+// grass and fish bowls, see `Decoration::new`
+const DECORATIONS: [(&str, u32); 2] = [("grass", 50), ("bowls", 55)];
+const DECORATION_SIZE: (f32, f32) = (48., 51.);
+
+const PANEL_WIDTH: f32 = 250.;
+const PAN_SPEED: f32 = 600.;
+
+struct Editor {
+    map: MapId,
+    tiled: TiledMap,
+    tool: Tool,
+    // tile grid corner a rectangle object is dragged out from
+    drag_from: Option<Vec2>,
+    target: Vec2,
+    zoom: f32,
+    // edited since the last save
+    modified: bool,
+    // outcome of the last save or play, at the bottom of the panel
+    status: Vec<String>,
+}
+
+impl Editor {
+    fn open(map: MapId) -> Editor {
+        let mut resources = storage::get_mut::<Resources>();
+        resources.load_map(map);
+
+        Editor {
+            map,
+            tiled: DeJson::deserialize_json(&resources.map_jsons[&map]).unwrap(),
+            tool: Tool::Tile(1),
+            drag_from: None,
+            target: resources.bounds().center(),
+            zoom: 1.,
+            modified: false,
+            status: vec![],
+        }
+    }
+
+    fn camera(&self) -> Camera2D {
+        Camera2D {
+            target: self.target,
+            zoom: vec2(2. / screen_width(), -2. / screen_height()) * self.zoom,
+            ..Default::default()
+        }
+    }
+
+    fn tile_size(&self) -> Vec2 {
+        vec2(self.tiled.tilewidth as f32, self.tiled.tileheight as f32)
+    }
+
+    /// Top left corner of the tile under `pos`.
+    fn snap(&self, pos: Vec2) -> Vec2 {
+        let tile = self.tile_size();
+        vec2(
+            (pos.x / tile.x).floor() * tile.x,
+            (pos.y / tile.y).floor() * tile.y,
+        )
+    }
+
+    fn objects(&mut self, layer: &str) -> &mut Vec<TiledObject> {
+        let layer = self.tiled.layer_mut(layer).unwrap();
+        layer.objects.get_or_insert_with(Vec::new)
+    }
+
+    fn update(&mut self) {
+        let pan = vec2(
+            (is_key_down(KeyCode::Right) as i32 - is_key_down(KeyCode::Left) as i32) as f32,
+            (is_key_down(KeyCode::Down) as i32 - is_key_down(KeyCode::Up) as i32) as f32,
+        );
+        self.target += pan * PAN_SPEED / self.zoom * get_frame_time();
+        match mouse_wheel().1 {
+            wheel if wheel > 0. => self.zoom = (self.zoom * 1.25).min(4.),
+            wheel if wheel < 0. => self.zoom = (self.zoom / 1.25).max(0.25),
+            _ => {}
+        }
+
+        let (mouse_x, mouse_y) = mouse_position();
+        if mouse_x > screen_width() - PANEL_WIDTH {
+            self.drag_from = None;
+            return;
+        }
+        let pos = self.camera().screen_to_world(vec2(mouse_x, mouse_y));
+        let tile = self.tile_size();
+        let (x, y) = ((pos.x / tile.x).floor(), (pos.y / tile.y).floor());
+
+        match self.tool {
+            Tool::Tile(gid) => {
+                let gid = match (
+                    is_mouse_button_down(MouseButton::Left),
+                    is_mouse_button_down(MouseButton::Right),
+                ) {
+                    (true, _) => gid,
+                    (_, true) => 0,
+                    _ => return,
+                };
+                if x < 0. || y < 0. || self.tiled.tile(x as u32, y as u32) == gid {
+                    return;
+                }
+                self.tiled.set_tile(x as u32, y as u32, gid);
+                self.modified = true;
+            }
+            Tool::Object(name) if POINTS.contains(&name) => {
+                if is_mouse_button_pressed(MouseButton::Left) {
+                    let point = Rect::new(pos.x.round(), pos.y.round(), 0., 0.);
+                    self.objects("logic").push(TiledObject::new(name, point));
+                    self.modified = true;
+                }
+            }
+            Tool::Object(name) => {
+                if is_mouse_button_pressed(MouseButton::Left) {
+                    self.drag_from = Some(self.snap(pos));
+                }
+                if is_mouse_button_released(MouseButton::Left) {
+                    if let Some(from) = self.drag_from.take() {
+                        let to = self.snap(pos);
+                        let corner = from.min(to);
+                        let size = (from - to).abs() + tile;
+                        let rect = Rect::new(corner.x, corner.y, size.x, size.y);
+                        self.objects("logic").push(logic_object(name, rect));
+                        self.modified = true;
+                    }
+                }
+            }
+            Tool::Decoration(gid) => {
+                if is_mouse_button_pressed(MouseButton::Left) {
+                    // tile objects stand on their bottom left corner
+                    let (width, height) = DECORATION_SIZE;
+                    let corner = vec2(pos.x - width / 2., pos.y).round();
+                    let rect = Rect::new(corner.x, corner.y, width, height);
+                    let mut decoration = TiledObject::new("", rect);
+                    decoration.gid = Some(gid);
+                    self.objects("decorations").push(decoration);
+                    self.modified = true;
+                }
+            }
+        }
+
+        if let (true, Tool::Object(_)) = (is_mouse_button_pressed(MouseButton::Right), self.tool) {
+            let objects = self.objects("logic");
+            if let Some(n) = objects.iter().rposition(|object| picks(object.rect(), pos)) {
+                objects.remove(n);
+                self.modified = true;
+            }
+        }
+        if let (true, Tool::Decoration(_)) =
+            (is_mouse_button_pressed(MouseButton::Right), self.tool)
+        {
+            let objects = self.objects("decorations");
+            if let Some(n) = objects
+                .iter()
+                .rposition(|object| decoration_rect(object).contains(pos))
+            {
+                objects.remove(n);
+                self.modified = true;
+            }
+        }
+    }
+
+    fn draw_map(&self) {
+        let resources = storage::get::<Resources>();
+        let tile = self.tile_size();
+        let bounds = Rect::new(
+            0.,
+            0.,
+            self.tiled.width as f32 * tile.x,
+            self.tiled.height as f32 * tile.y,
+        );
+        draw_rectangle(
+            bounds.x,
+            bounds.y,
+            bounds.w,
+            bounds.h,
+            Color::new(0.1, 0.15, 0.3, 1.),
+        );
+
+        for y in 0..self.tiled.height {
+            for x in 0..self.tiled.width {
+                let dest = Rect::new(x as f32 * tile.x, y as f32 * tile.y, tile.x, tile.y);
+                draw_gid(&resources, &self.tiled, self.tiled.tile(x, y), dest);
+            }
+        }
+        for x in 0..=self.tiled.width {
+            let x = x as f32 * tile.x;
+            draw_line(x, 0., x, bounds.h, 1., Color::new(1., 1., 1., 0.1));
+        }
+        for y in 0..=self.tiled.height {
+            let y = y as f32 * tile.y;
+            draw_line(0., y, bounds.w, y, 1., Color::new(1., 1., 1., 0.1));
+        }
+
+        let layer = |name| {
+            self.tiled
+                .layer(name)
+                .and_then(|layer| layer.objects.as_ref())
+        };
+        for decoration in layer("decorations").into_iter().flatten() {
+            draw_gid(
+                &resources,
+                &self.tiled,
+                decoration.gid.unwrap_or(0),
+                decoration_rect(decoration),
+            );
+        }
+        for object in layer("logic").into_iter().flatten() {
+            let color = object_color(&object.name);
+            let rect = object.rect();
+            match rect.w == 0. && rect.h == 0. {
+                true => draw_circle(rect.x, rect.y, 5., color),
+                false => draw_rectangle_lines(rect.x, rect.y, rect.w, rect.h, 2., color),
+            }
+            draw_text(&object.name, rect.x + 4., rect.y - 4., 16., color);
+        }
+
+        if let Some(from) = self.drag_from {
+            let pos = self.camera().screen_to_world(mouse_position().into());
+            let to = self.snap(pos);
+            let corner = from.min(to);
+            let size = (from - to).abs() + tile;
+            draw_rectangle_lines(corner.x, corner.y, size.x, size.y, 2., YELLOW);
+        }
+    }
+
+    /// The tileset and the buttons of the side panel.
+    fn draw_panel(&mut self) -> Option<Action> {
+        let left = screen_width() - PANEL_WIDTH;
+        draw_rectangle(
+            left,
+            0.,
+            PANEL_WIDTH,
+            screen_height(),
+            Color::new(0., 0., 0., 0.8),
+        );
+
+        let resources = storage::get::<Resources>();
+        let name = resources
+            .maps
+            .get(self.map)
+            .map_or("", |map| map.name.as_str());
+        let title = format!("{}{}", name, if self.modified { " *" } else { "" });
+        draw_text(&title, left + 8., 24., 24., WHITE);
+
+        // the tileset, clicking a tile paints with it
+        let palette = Rect::new(
+            left + 8.,
+            40.,
+            resources.tileset.width(),
+            resources.tileset.height(),
+        );
+        draw_texture(resources.tileset, palette.x, palette.y, WHITE);
+        let tile = self.tile_size();
+        if let Some((firstgid, columns)) = palette_tileset(&self.tiled) {
+            let (mouse_x, mouse_y) = mouse_position();
+            if is_mouse_button_pressed(MouseButton::Left)
+                && palette.contains(vec2(mouse_x, mouse_y))
+            {
+                let column = ((mouse_x - palette.x) / tile.x) as u32;
+                let row = ((mouse_y - palette.y) / tile.y) as u32;
+                self.tool = Tool::Tile(firstgid + row * columns + column.min(columns - 1));
+            }
+            if let Tool::Tile(gid) = self.tool {
+                let id = gid - firstgid;
+                let x = palette.x + (id % columns) as f32 * tile.x;
+                let y = palette.y + (id / columns) as f32 * tile.y;
+                draw_rectangle_lines(x, y, tile.x, tile.y, 2., YELLOW);
+            }
+        }
+        drop(resources);
+
+        let mut y = palette.bottom() + 8.;
+        let mut button = |label: &str, column: u32, selected: bool| {
+            let width = (PANEL_WIDTH - 24.) / 2.;
+            let pos = vec2(left + 8. + column as f32 * (width + 8.), y);
+            if column == 1 {
+                y += 24.;
+            }
+            let label = match selected {
+                true => format!("> {}", label),
+                false => label.to_string(),
+            };
+            widgets::Button::new(label.as_str())
+                .position(pos)
+                .size(vec2(width, 20.))
+                .ui(&mut *root_ui())
+        };
+
+        let tools = POINTS
+            .iter()
+            .chain(&RECTS)
+            .map(|name| (*name, Tool::Object(name)))
+            .chain(
+                DECORATIONS
+                    .iter()
+                    .map(|(name, gid)| (*name, Tool::Decoration(*gid))),
+            )
+            .collect::<Vec<_>>();
+        for (n, (label, tool)) in tools.iter().enumerate() {
+            if button(label, n as u32 % 2, self.tool == *tool) {
+                self.tool = *tool;
+            }
+        }
+        if tools.len() % 2 == 1 {
+            y += 24.;
+        }
+        y += 16.;
+
+        let mut button = |label: &str, column: u32| {
+            let width = (PANEL_WIDTH - 24.) / 2.;
+            let pos = vec2(left + 8. + column as f32 * (width + 8.), y);
+            if column == 1 {
+                y += 32.;
+            }
+            widgets::Button::new(label)
+                .position(pos)
+                .size(vec2(width, 28.))
+                .ui(&mut *root_ui())
+        };
+        if button("Play", 0) {
+            return Some(Action::Play);
+        }
+        if button("Save", 1) {
+            self.save();
+        }
+        if button("Next map", 0) {
+            let resources = storage::get::<Resources>();
+            let next = resources.maps.next(Some(self.map), 0).id;
+            drop(resources);
+            *self = Editor::open(next);
+        }
+        if button("Back", 1) {
+            return Some(Action::Back);
+        }
+
+        let help = [
+            "left click: place",
+            "right click: remove",
+            "arrows, wheel: move, zoom",
+        ];
+        let status = self.status.iter().map(String::as_str);
+        for line in help.iter().copied().chain(status) {
+            y += 18.;
+            draw_text(line, left + 8., y, 18., WHITE);
+        }
+
+        None
+    }
+
+    /// Write the map back to its file, it is used for the next matches if it is playable.
+    fn save(&mut self) {
+        let mut resources = storage::get_mut::<Resources>();
+        let path = match resources.maps.get(self.map) {
+            Some(map) => map.path.clone(),
+            None => return,
+        };
+        let json = self.tiled.to_json();
+
+        if let Err(error) = std::fs::write(&path, &json) {
+            self.status = vec![format!("Not saved: {}", error)];
+            return;
+        }
+        self.modified = false;
+        self.status = vec![format!("Saved {}", path)];
+
+        let errors = maps::validate(&json);
+        if errors.is_empty() {
+            resources
+                .levels
+                .insert(self.map, maps::load_level(&json).unwrap());
+            resources.map_jsons.insert(self.map, json);
+        }
+        self.status.extend(errors.iter().map(ToString::to_string));
+    }
+
+    /// Try the map out right away, as edited. Only playable maps are played.
+    async fn play(&mut self) {
+        let json = self.tiled.to_json();
+        let errors = maps::validate(&json);
+        if errors.is_empty() == false {
+            self.status = errors.iter().map(ToString::to_string).collect();
+            return;
+        }
+        self.status = vec![];
+
+        {
+            let mut resources = storage::get_mut::<Resources>();
+            resources
+                .levels
+                .insert(self.map, maps::load_level(&json).unwrap());
+            resources.map_jsons.insert(self.map, json);
+            resources.load_map(self.map);
+        }
+        play_offline().await;
+    }
+}
+
+/// A `logic` object over `rect`, moving platforms go back and forth over their own width.
+fn logic_object(name: &str, rect: Rect) -> TiledObject {
+    let mut object = TiledObject::new(name, rect);
+    if name == "moving_platform" {
+        object.properties = Some(vec![maps::TiledProperty {
+            name: "dx".to_string(),
+            value: maps::PropertyValue::Number(rect.w as f64),
+        }]);
+    }
+    object
+}
+
+/// Points are picked within a few pixels.
+fn picks(rect: Rect, pos: Vec2) -> bool {
+    match rect.w == 0. && rect.h == 0. {
+        true => rect.point().distance(pos) < 8.,
+        false => rect.contains(pos),
+    }
+}
+
+fn decoration_rect(decoration: &TiledObject) -> Rect {
+    let rect = decoration.rect();
+    Rect::new(rect.x, rect.y - rect.h, rect.w, rect.h)
+}
+
+fn object_color(name: &str) -> Color {
+    match name {
+        "spawn" => GREEN,
+        "flag_blue" => BLUE,
+        "flag_green" => LIME,
+        "water" => SKYBLUE,
+        "pickup_zone" => GOLD,
+        "no_pickups" => ORANGE,
+        "moving_platform" | "crusher" | "spike_trap" => RED,
+        _ => WHITE,
+    }
+}
+
+/// First gid and columns of the tileset of `main layer` tiles.
+fn palette_tileset(tiled: &TiledMap) -> Option<(u32, u32)> {
+    let tileset = tiled
+        .tilesets
+        .iter()
+        .find(|tileset| tileset.image.as_deref() == Some("tileset.png"))?;
+    let columns = tileset
+        .columns
+        .or_else(|| Some(tileset.imagewidth? / tiled.tilewidth))?;
+    Some((tileset.firstgid, columns.max(1)))
+}
+
+fn draw_gid(resources: &Resources, tiled: &TiledMap, gid: u32, dest: Rect) {
+    let tileset = tiled.tilesets.iter().find(|tileset| tileset.contains(gid));
+    if let Some(tileset) = tileset {
+        resources
+            .tiled_map
+            .spr(&tileset.name, gid - tileset.firstgid, dest);
+    }
+}
+
+/// Run the level loaded into `Resources` with only the local fish, until Escape.
+async fn play_offline() {
+    let resources = storage::get::<Resources>();
+    let bounds = resources.bounds();
+
+    let level_background = scene::add_node(LevelBackground::new());
+
+    Decoration::add_all(&resources.tiled_map);
+    drop(resources);
+
+    scene::add_node(NakamaRollbackGame::offline(GameType::Deathmatch));
+
+    let camera = scene::add_node(Camera::new(bounds, 400.0, Handle::null()));
+    scene::get_node(level_background).camera = camera;
+    scene::add_node(Fxses { camera });
+
+    loop {
+        clear_background(BLACK);
+
+        if root_ui().button(None, "back to the editor") || is_key_pressed(KeyCode::Escape) {
+            break;
+        }
+
+        next_frame().await;
+    }
+    scene::clear();
+}
+
+pub async fn level_editor() -> Scene {
+    crate::load_resources().await;
+
+    let map = storage::get::<Resources>().map_id;
+    let mut editor = Editor::open(map);
+
+    loop {
+        clear_background(BLACK);
+
+        set_camera(&editor.camera());
+        editor.update();
+        editor.draw_map();
+        set_default_camera();
+
+        match editor.draw_panel() {
+            Some(Action::Play) => editor.play().await,
+            Some(Action::Back) => return Scene::MainMenu,
+            None => {}
+        }
+
+        next_frame().await;
+    }
+}
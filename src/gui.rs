@@ -25,6 +25,7 @@ pub enum Scene {
     MainMenu,
     MatchmakingLobby,
     Credits,
+    LevelEditor,
     Login,
     QuickGame,
     MatchmakingGame { private: bool },
@@ -8,7 +8,8 @@ use macroquad::{
 
 use super::{GuiResources, Scene};
 
-pub async fn main_menu() -> Scene {
+/// `dev` adds the level editor to the menu.
+pub async fn main_menu(dev: bool) -> Scene {
     loop {
         clear_background(BLACK);
 
@@ -58,6 +59,19 @@ pub async fn main_menu() -> Scene {
             return Scene::Credits;
         }
 
+        if dev
+            && widgets::Button::new(" Level editor")
+                .size(vec2(button_width, 150.))
+                .position(vec2(
+                    screen_width() / 2. - button_width / 2.,
+                    label_pos.y + label_size.y + 360.,
+                ))
+                .ui(&mut *root_ui())
+        {
+            root_ui().pop_skin();
+            return Scene::LevelEditor;
+        }
+
         root_ui().pop_skin();
 
         next_frame().await;
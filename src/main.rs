@@ -1,5 +1,7 @@
 use macroquad::prelude::*;
 
+use std::{collections::BTreeMap, rc::Rc};
+
 use macroquad_particles as particles;
 use macroquad_tiled as tiled;
 
@@ -15,7 +17,6 @@ use macroquad::{
 use nanoserde::DeJson;
 
 use particles::EmittersCache;
-use macroquad_platformer::World as CollisionWorld;
 
 mod credentials {
     include!(concat!(env!("OUT_DIR"), "/nakama_credentials.rs"));
@@ -25,6 +26,16 @@ mod nodes;
 
 mod gui;
 
+mod simulation;
+
+mod maps;
+
+mod editor;
+
+mod timestep;
+
+mod weapons;
+
 use gui::Scene;
 
 pub mod consts {
@@ -32,10 +43,20 @@ pub mod consts {
     pub const JUMP_SPEED: f32 = 480.0;
     pub const RUN_SPEED: f32 = 250.0;
     pub const PLAYER_SPRITE: u32 = 120;
-    pub const BULLET_SPEED: f32 = 500.0;
     pub const JUMP_GRACE_TIME: f32 = 0.15;
+    pub const DOUBLE_JUMP_SPEED: f32 = 400.0;
+    pub const WALL_SLIDE_SPEED: f32 = 100.0;
+    pub const WALL_JUMP_SPEED: f32 = 300.0;
+    pub const WALL_JUMP_TIME: f32 = 0.2;
+    pub const DASH_SPEED: f32 = 750.0;
+    pub const DASH_TIME: f32 = 0.15;
+    pub const DASH_COOLDOWN: f32 = 0.8;
+    pub const CLIMB_SPEED: f32 = 150.0;
+    pub const WATER_SINK_SPEED: f32 = 120.0;
+    pub const WATER_GRAVITY: f32 = 0.3;
+    pub const SWIM_SPEED: f32 = 240.0;
     pub const NETWORK_FPS: f32 = 15.0;
-    pub const GUN_THROWBACK: f32 = 700.0;
+    pub const SIMULATION_FPS: f32 = 60.0;
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -49,25 +70,94 @@ pub enum GameType {
         // not as a matchmaking match
         private: bool,
     },
+    // Teams score by bringing the enemy flag to their own base
+    CaptureTheFlag {
+        private: bool,
+    },
+    // Teams score by holding the hill with nobody else on it
+    KingOfTheHill {
+        private: bool,
+    },
+}
+
+impl GameType {
+    /// Modes the host of a matchmaking match may pick in the lobby.
+    pub const MODES: [&'static str; 3] =
+        ["Last fish standing", "Capture the flag", "King of the hill"];
+
+    pub fn is_private(self) -> bool {
+        match self {
+            GameType::Deathmatch => false,
+            GameType::LastFishStanding { private }
+            | GameType::CaptureTheFlag { private }
+            | GameType::KingOfTheHill { private } => private,
+        }
+    }
+
+    /// Fish are eliminated once out of lives, in the other modes they respawn forever.
+    pub fn has_lives(self) -> bool {
+        matches!(self, GameType::LastFishStanding { .. })
+    }
+
+    /// Won by the score of a team instead of by the last ones alive.
+    pub fn is_objective(self) -> bool {
+        matches!(
+            self,
+            GameType::CaptureTheFlag { .. } | GameType::KingOfTheHill { .. }
+        )
+    }
+
+    /// Index into `GameType::MODES`, deathmatch has no lobby and counts as the first one.
+    pub fn mode(self) -> u8 {
+        match self {
+            GameType::Deathmatch | GameType::LastFishStanding { .. } => 0,
+            GameType::CaptureTheFlag { .. } => 1,
+            GameType::KingOfTheHill { .. } => 2,
+        }
+    }
+
+    pub fn with_mode(self, mode: u8) -> GameType {
+        let private = self.is_private();
+        match mode {
+            1 => GameType::CaptureTheFlag { private },
+            2 => GameType::KingOfTheHill { private },
+            _ => GameType::LastFishStanding { private },
+        }
+    }
 }
 
 struct Resources {
     hit_fxses: EmittersCache,
     explosion_fxses: EmittersCache,
     disarm_fxses: EmittersCache,
+    splash_fxses: EmittersCache,
+    tile_break_fxses: EmittersCache,
     tiled_map: tiled::Map,
-    collision_world: CollisionWorld,
+    // collision grid and spawns for the engine independent game rules
+    level: simulation::Map,
+    // the map `tiled_map` and `level` are of
+    map_id: maps::MapId,
+    maps: maps::Maps,
+    // Tiled json of every map, parsed again when switching to it
+    map_jsons: BTreeMap<maps::MapId, String>,
+    map_thumbnails: BTreeMap<maps::MapId, Texture2D>,
+    // levels of every map, for the lobby to know which modes they can host
+    levels: BTreeMap<maps::MapId, simulation::Map>,
+    tileset: Texture2D,
     whale: Texture2D,
-    gun: Texture2D,
-    sword: Texture2D,
+    // `simulation::Team::index` to the team colored whale
+    team_whales: [Texture2D; 2],
+    weapons: Rc<weapons::Weapons>,
+    weapon_textures: BTreeMap<weapons::WeaponId, Texture2D>,
+    weapon_sounds: BTreeMap<weapons::WeaponId, Sound>,
     background_01: Texture2D,
     background_02: Texture2D,
     background_03: Texture2D,
     background_04: Texture2D,
     decorations: Texture2D,
+    // every texture above with the file it was loaded from
+    texture_files: Vec<(String, Texture2D)>,
     jump_sound: Sound,
-    shoot_sound: Sound,
-    sword_sound: Sound,
     pickup_sound: Sound,
 }
 
@@ -80,88 +170,208 @@ pub const EXPLOSION_FX: &'static str = r#"{"local_coords":false,"emission_shape"
 pub const WEAPON_DISARM_FX: &'static str = r#"{"local_coords":false,"emission_shape":{"Sphere":{"radius":0.6}},"one_shot":true,"lifetime":0.1,"lifetime_randomness":0,"explosiveness":1,"amount":100,"shape":{"Circle":{"subdivisions":10}},"emitting":false,"initial_direction":{"x":0,"y":-1},"initial_direction_spread":6.2831855,"initial_velocity":359.6,"initial_velocity_randomness":0.8,"linear_accel":-2.400001,"size":2.5,"size_randomness":0,"size_curve":{"points":[[0,0.92971194],[0.295,1.1297119],[1,0.46995974]],"interpolation":{"Linear":[]},"resolution":30},"blend_mode":{"Additive":[]},"colors_curve":{"start":{"r":0.99999994,"g":0.9699999,"b":0.37000006,"a":1},"mid":{"r":0.81000006,"g":0.6074995,"b":0,"a":1},"end":{"r":0.72,"g":0.54,"b":0,"a":1}},"gravity":{"x":0,"y":-300},"post_processing":{}}
 "#;
 
+pub const SPLASH_FX: &'static str = r#"{"local_coords":false,"emission_shape":{"Sphere":{"radius":12}},"one_shot":true,"lifetime":0.5,"lifetime_randomness":0.3,"explosiveness":0.9,"amount":40,"shape":{"Circle":{"subdivisions":8}},"emitting":false,"initial_direction":{"x":0,"y":-1},"initial_direction_spread":1.2,"initial_velocity":220,"initial_velocity_randomness":0.5,"linear_accel":0,"size":3.5,"size_randomness":0.5,"blend_mode":{"Alpha":[]},"colors_curve":{"start":{"r":0.85,"g":0.95,"b":1,"a":1},"mid":{"r":0.45,"g":0.7,"b":0.95,"a":0.9},"end":{"r":0.2,"g":0.45,"b":0.8,"a":0.6}},"gravity":{"x":0,"y":900},"post_processing":{}}
+"#;
+
+pub const TILE_BREAK_FX: &'static str = r#"{"local_coords":false,"emission_shape":{"Sphere":{"radius":14}},"one_shot":true,"lifetime":0.7,"lifetime_randomness":0.3,"explosiveness":0.95,"amount":30,"shape":{"Circle":{"subdivisions":4}},"emitting":false,"initial_direction":{"x":0,"y":-1},"initial_direction_spread":2.4,"initial_velocity":180,"initial_velocity_randomness":0.6,"linear_accel":0,"size":4,"size_randomness":0.5,"blend_mode":{"Alpha":[]},"colors_curve":{"start":{"r":0.62,"g":0.52,"b":0.4,"a":1},"mid":{"r":0.45,"g":0.37,"b":0.3,"a":1},"end":{"r":0.3,"g":0.25,"b":0.2,"a":0.5}},"gravity":{"x":0,"y":800},"post_processing":{}}
+"#;
+
 impl Resources {
     // TODO: fix macroquad error type here
     async fn new() -> Result<Resources, macroquad::prelude::FileError> {
-        let tileset = load_texture("assets/tileset.png").await?;
-        tileset.set_filter(FilterMode::Nearest);
+        let mut texture_files = vec![];
+
+        let tileset = load_pixel_texture("assets/tileset.png", &mut texture_files).await?;
+
+        let decorations = load_pixel_texture("assets/decorations1.png", &mut texture_files).await?;
 
-        let decorations = load_texture("assets/decorations1.png").await?;
-        decorations.set_filter(FilterMode::Nearest);
+        let whale =
+            load_pixel_texture("assets/Whale/Whale(76x66)(Orange).png", &mut texture_files).await?;
 
-        let whale = load_texture("assets/Whale/Whale(76x66)(Orange).png").await?;
-        whale.set_filter(FilterMode::Nearest);
+        let whale_blue =
+            load_pixel_texture("assets/Whale/Whale(76x66)(Blue).png", &mut texture_files).await?;
 
-        let gun = load_texture("assets/Whale/Gun(92x32).png").await?;
-        gun.set_filter(FilterMode::Nearest);
+        let whale_green =
+            load_pixel_texture("assets/Whale/Whale(76x66)(Green).png", &mut texture_files).await?;
 
-        let sword = load_texture("assets/Whale/Sword(65x93).png").await?;
-        sword.set_filter(FilterMode::Nearest);
+        let weapons_json = load_string("assets/weapons.json").await?;
+        let weapons = weapons::Weapons::from_json(&weapons_json)
+            .unwrap_or_else(|error| panic!("assets/weapons.json: {}", error));
 
-        let background_01 = load_texture("assets/Background/01.png").await?;
-        background_01.set_filter(FilterMode::Nearest);
+        let mut weapon_textures = BTreeMap::new();
+        let mut weapon_sounds = BTreeMap::new();
+        for weapon in &weapons.weapons {
+            let texture = load_pixel_texture(&weapon.sprite.texture, &mut texture_files).await?;
+            weapon_textures.insert(weapon.id, texture);
+
+            weapon_sounds.insert(weapon.id, load_sound(&weapon.sound).await?);
+        }
 
-        let background_02 = load_texture("assets/Background/02.png").await?;
-        background_02.set_filter(FilterMode::Nearest);
+        let background_01 =
+            load_pixel_texture("assets/Background/01.png", &mut texture_files).await?;
 
-        let background_03 = load_texture("assets/Background/03.png").await?;
-        background_03.set_filter(FilterMode::Nearest);
+        let background_02 =
+            load_pixel_texture("assets/Background/02.png", &mut texture_files).await?;
 
-        let background_04 = load_texture("assets/Background/04.png").await?;
-        background_04.set_filter(FilterMode::Nearest);
+        let background_03 =
+            load_pixel_texture("assets/Background/03.png", &mut texture_files).await?;
+
+        let background_04 =
+            load_pixel_texture("assets/Background/04.png", &mut texture_files).await?;
 
         let jump_sound = load_sound("assets/sounds/jump.wav").await?;
-        let shoot_sound = load_sound("assets/sounds/shoot.ogg").await?;
-        let sword_sound = load_sound("assets/sounds/sword.wav").await?;
         let pickup_sound = load_sound("assets/sounds/pickup.wav").await?;
 
-        let tiled_map_json = load_string("assets/map.json").await.unwrap();
-        let tiled_map = tiled::load_map(
-            &tiled_map_json,
-            &[("tileset.png", tileset), ("decorations1.png", decorations)],
-            &[],
-        )
-        .unwrap();
+        let maps_json = load_string("assets/maps/maps.json").await?;
+        let mut maps = maps::Maps::from_json(&maps_json)
+            .unwrap_or_else(|error| panic!("assets/maps/maps.json: {}", error));
+
+        let mut map_jsons = BTreeMap::new();
+        let mut map_thumbnails = BTreeMap::new();
+        let mut levels = BTreeMap::new();
+        for map in &maps.maps {
+            let json = match load_string(&map.path).await {
+                Ok(json) => json,
+                Err(error) => {
+                    warn!("Skipping {}: {}", map.path, error);
+                    continue;
+                }
+            };
+            let errors = maps::validate(&json);
+            if errors.is_empty() == false {
+                for error in errors {
+                    warn!("Skipping {}: {}", map.path, error);
+                }
+                continue;
+            }
+            levels.insert(map.id, maps::load_level(&json).unwrap());
+            map_jsons.insert(map.id, json);
 
-        let mut static_colliders = vec![];
-        for (_x, _y, tile) in tiled_map.tiles("main layer", None) {
-            static_colliders.push(tile.is_some());
+            let thumbnail = load_pixel_texture(&map.thumbnail, &mut texture_files).await?;
+            map_thumbnails.insert(map.id, thumbnail);
+        }
+        maps.maps.retain(|map| levels.contains_key(&map.id));
+        if maps.maps.is_empty() {
+            panic!("assets/maps/maps.json: none of the maps listed can be played");
         }
-        let mut collision_world = CollisionWorld::new();
-        collision_world.add_static_tiled_layer(
-            static_colliders,
-            32.,
-            32.,
-            tiled_map.raw_tiled_map.width as _,
-            1,
-        );
+
+        let map_id = maps.first().id;
+        let tiled_map = load_tiled_map(&map_jsons[&map_id], tileset, decorations);
+        let level = levels[&map_id].clone();
 
         let hit_fxses = EmittersCache::new(nanoserde::DeJson::deserialize_json(HIT_FX).unwrap());
         let explosion_fxses =
             EmittersCache::new(nanoserde::DeJson::deserialize_json(EXPLOSION_FX).unwrap());
         let disarm_fxses =
             EmittersCache::new(nanoserde::DeJson::deserialize_json(WEAPON_DISARM_FX).unwrap());
+        let splash_fxses =
+            EmittersCache::new(nanoserde::DeJson::deserialize_json(SPLASH_FX).unwrap());
+        let tile_break_fxses =
+            EmittersCache::new(nanoserde::DeJson::deserialize_json(TILE_BREAK_FX).unwrap());
 
         Ok(Resources {
             hit_fxses,
             explosion_fxses,
             disarm_fxses,
+            splash_fxses,
+            tile_break_fxses,
             tiled_map,
-            collision_world,
+            level,
+            map_id,
+            maps,
+            map_jsons,
+            map_thumbnails,
+            levels,
+            tileset,
             whale,
-            gun,
-            sword,
+            team_whales: [whale_blue, whale_green],
+            weapons: Rc::new(weapons),
+            weapon_textures,
+            weapon_sounds,
             background_01,
             background_02,
             background_03,
             background_04,
             decorations,
+            texture_files,
             jump_sound,
-            shoot_sound,
-            sword_sound,
             pickup_sound,
         })
     }
+
+    /// Switch to the map `id`, the nodes standing on the previous one are moved by the caller.
+    fn load_map(&mut self, id: maps::MapId) {
+        self.tiled_map = load_tiled_map(&self.map_jsons[&id], self.tileset, self.decorations);
+        self.level = self.levels[&id].clone();
+        self.map_id = id;
+    }
+
+    /// Particle fxses with the file a tweaked version of them is picked up from
+    /// in dev mode, and the json they are built from otherwise.
+    fn fxses_mut(&mut self) -> [(&'static str, &'static str, &mut EmittersCache); 5] {
+        [
+            ("assets/fx/hit.json", HIT_FX, &mut self.hit_fxses),
+            (
+                "assets/fx/explosion.json",
+                EXPLOSION_FX,
+                &mut self.explosion_fxses,
+            ),
+            (
+                "assets/fx/disarm.json",
+                WEAPON_DISARM_FX,
+                &mut self.disarm_fxses,
+            ),
+            ("assets/fx/splash.json", SPLASH_FX, &mut self.splash_fxses),
+            (
+                "assets/fx/tile_break.json",
+                TILE_BREAK_FX,
+                &mut self.tile_break_fxses,
+            ),
+        ]
+    }
+
+    /// Take the broken tiles out of the level and of the drawn `main layer`.
+    fn break_tiles(&mut self, tiles: &[usize]) {
+        for n in tiles {
+            self.level.break_tile(*n);
+            let layer = self.tiled_map.layers.get_mut("main layer");
+            if let Some(tile) = layer.and_then(|layer| layer.data.get_mut(*n)) {
+                *tile = None;
+            }
+        }
+    }
+
+    /// Size of the current map in pixels.
+    fn bounds(&self) -> Rect {
+        let map = &self.tiled_map.raw_tiled_map;
+
+        Rect::new(
+            0.,
+            0.,
+            (map.tilewidth * map.width) as f32,
+            (map.tileheight * map.height) as f32,
+        )
+    }
+}
+
+/// Texture at `path` with pixel art filtering, remembered in `files`.
+async fn load_pixel_texture(
+    path: &str,
+    files: &mut Vec<(String, Texture2D)>,
+) -> Result<Texture2D, macroquad::prelude::FileError> {
+    let texture = load_texture(path).await?;
+    texture.set_filter(FilterMode::Nearest);
+    files.push((path.to_string(), texture));
+    Ok(texture)
+}
+
+fn load_tiled_map(json: &str, tileset: Texture2D, decorations: Texture2D) -> tiled::Map {
+    tiled::load_map(
+        json,
+        &[("tileset.png", tileset), ("decorations1.png", decorations)],
+        &[],
+    )
+    .unwrap()
 }
 
 async fn join_quick_match(nakama: Handle<nodes::Nakama>) {
@@ -225,12 +435,8 @@ async fn join_quick_match(nakama: Handle<nodes::Nakama>) {
     }
 }
 
-async fn network_game(nakama: Handle<nodes::Nakama>, game_type: GameType, network_id: String) {
-    use nodes::{
-        Bullets, Camera, Decoration, Fxses, GlobalEvents, LevelBackground, NakamaRealtimeGame,
-        Player,
-    };
-
+/// Load `Resources` into the storage, with a loading screen meanwhile.
+async fn load_resources() {
     let resources_loading = start_coroutine(async move {
         let resources = Resources::new().await.unwrap();
         storage::store(resources);
@@ -251,6 +457,20 @@ async fn network_game(nakama: Handle<nodes::Nakama>, game_type: GameType, networ
 
         next_frame().await;
     }
+}
+
+async fn network_game(
+    nakama: Handle<nodes::Nakama>,
+    game_type: GameType,
+    network_id: String,
+    dev: bool,
+) {
+    use nodes::{
+        Bullets, Camera, Decoration, Fxses, GlobalEvents, HotReload, LevelBackground, Movers,
+        NakamaRealtimeGame, NakamaRollbackGame, Player, ThrownWeapons, TouchControls,
+    };
+
+    load_resources().await;
 
     let battle_music = load_sound("assets/music/across the pond.ogg")
         .await
@@ -265,37 +485,35 @@ async fn network_game(nakama: Handle<nodes::Nakama>, game_type: GameType, networ
     );
 
     let resources = storage::get::<Resources>();
-    let w = resources.tiled_map.raw_tiled_map.tilewidth * resources.tiled_map.raw_tiled_map.width;
-    let h = resources.tiled_map.raw_tiled_map.tileheight * resources.tiled_map.raw_tiled_map.height;
+    let bounds = resources.bounds();
 
     let level_background = scene::add_node(LevelBackground::new());
 
-    for object in &resources.tiled_map.layers["decorations"].objects {
-        scene::add_node(Decoration::new(
-            vec2(object.world_x, object.world_y),
-            object.gid.unwrap(),
-        ));
-    }
+    Decoration::add_all(&resources.tiled_map);
     drop(resources);
 
     let nakama_realtime = scene::add_node(NakamaRealtimeGame::new(nakama, game_type, network_id));
 
-    let player = scene::add_node(Player::new(
-        game_type == GameType::Deathmatch,
-        nakama,
-        nakama_realtime,
-    ));
+    // should be updated before the player to feed this frame's input
+    let touch_controls = scene::add_node(TouchControls::new());
+
+    // ticks the movers before the player is carried by them
+    scene::add_node(Movers::new(nakama_realtime));
+
+    let player = scene::add_node(Player::new(game_type, nakama, nakama_realtime));
 
     scene::add_node(Bullets::new(player));
+    scene::add_node(ThrownWeapons::new(player, nakama_realtime));
     scene::add_node(GlobalEvents::new(player, nakama_realtime));
 
-    let camera = scene::add_node(Camera::new(
-        Rect::new(0.0, 0.0, w as f32, h as f32),
-        400.0,
-        player,
-    ));
+    let camera = scene::add_node(Camera::new(bounds, 400.0, player));
     scene::get_node(level_background).camera = camera;
     scene::add_node(Fxses { camera });
+    scene::get_node(touch_controls).camera = camera;
+
+    if dev {
+        scene::add_node(HotReload::new());
+    }
 
     loop {
         clear_background(BLACK);
@@ -305,9 +523,13 @@ async fn network_game(nakama: Handle<nodes::Nakama>, game_type: GameType, networ
 
             ui::root_ui().push_skin(&resources.login_skin);
 
-            if ui::root_ui().button(None, "back")
-                || scene::find_node_by_type::<Player>().unwrap().want_quit
-            {
+            let want_quit = match scene::find_node_by_type::<Player>() {
+                Some(player) => player.want_quit,
+                None => scene::find_node_by_type::<NakamaRollbackGame>()
+                    .map_or(false, |game| game.want_quit),
+            };
+
+            if ui::root_ui().button(None, "back") || want_quit {
                 ui::root_ui().pop_skin();
                 stop_sound(battle_music);
                 return;
@@ -323,8 +545,20 @@ async fn network_game(nakama: Handle<nodes::Nakama>, game_type: GameType, networ
     }
 }
 
-#[macroquad::main("Fishgame")]
-async fn main() {
+fn main() {
+    // `fishgame --validate-map <map.json>...` checks levels without opening a window
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().map(String::as_str) == Some("--validate-map") {
+        std::process::exit(maps::validate_files(&args[1..]));
+    }
+    // `fishgame --dev` reloads maps, textures and particles as they change on disk
+    // and has the level editor in the main menu
+    let dev = args.iter().any(|arg| arg == "--dev");
+
+    macroquad::Window::new("Fishgame", game(dev));
+}
+
+async fn game(dev: bool) {
     let nakama = scene::add_node(nodes::Nakama::new(
         credentials::NAKAMA_KEY,
         credentials::NAKAMA_SERVER,
@@ -337,6 +571,7 @@ async fn main() {
 
     let gui_resources = gui::GuiResources::new();
     storage::store(gui_resources);
+    storage::store(maps::Rotation::default());
 
     //let mut next_scene = gui::matchmaking_lobby().await;
     let mut next_scene = Scene::MainMenu;
@@ -350,7 +585,7 @@ async fn main() {
                         volume: 0.6,
                     },
                 );
-                next_scene = gui::main_menu().await;
+                next_scene = gui::main_menu(dev).await;
             }
             Scene::QuickGame => {
                 stop_sound(whale_theme);
@@ -362,7 +597,7 @@ async fn main() {
                     .clone()
                     .unwrap();
 
-                network_game(nakama, GameType::Deathmatch, network_id).await;
+                network_game(nakama, GameType::Deathmatch, network_id, dev).await;
 
                 let match_leave = {
                     let nakama = &mut scene::get_node(nakama).api_client;
@@ -390,7 +625,13 @@ async fn main() {
                     .clone()
                     .unwrap();
 
-                network_game(nakama, GameType::LastFishStanding { private }, network_id).await;
+                network_game(
+                    nakama,
+                    GameType::LastFishStanding { private },
+                    network_id,
+                    dev,
+                )
+                .await;
                 scene::clear();
 
                 next_scene = Scene::MatchmakingLobby;
@@ -416,6 +657,12 @@ async fn main() {
             Scene::Credits => {
                 next_scene = gui::credits().await;
             }
+            Scene::LevelEditor => {
+                stop_sound(whale_theme);
+
+                next_scene = editor::level_editor().await;
+                scene::clear();
+            }
         }
     }
 }
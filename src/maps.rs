@@ -0,0 +1,110 @@
+//! Levels to play on, listed in `assets/maps/maps.json`.
+
+use nanoserde::{DeJson, DeJsonErr};
+use std::fmt;
+
+use crate::simulation;
+
+mod tiled;
+mod validate;
+
+pub use tiled::{PropertyValue, TiledMap, TiledObject, TiledProperty};
+pub use validate::{validate, validate_files};
+
+/// What the lobby and the network protocol refer to a map by.
+pub type MapId = u8;
+
+#[derive(Debug, Clone, PartialEq, DeJson)]
+pub struct MapDef {
+    pub id: MapId,
+    pub name: String,
+    /// Tiled map with a `main layer`, a `logic` and a `decorations` layer
+    pub path: String,
+    /// Most fish the level has room for
+    pub players: u8,
+    /// Picture of the level shown in the lobby
+    pub thumbnail: String,
+}
+
+/// Something wrong with `assets/maps/maps.json` itself, the maps it lists are
+/// checked by `validate`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegistryError {
+    Json(String),
+    Empty,
+    DuplicateId { name: String, id: MapId },
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegistryError::Json(error) => write!(f, "not a map list: {}", error),
+            RegistryError::Empty => write!(f, "no maps listed"),
+            RegistryError::DuplicateId { name, id } => {
+                write!(f, "`{}` has id {}, already taken by another map", name, id)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, DeJson)]
+pub struct Maps {
+    /// Never empty
+    pub maps: Vec<MapDef>,
+}
+
+impl Maps {
+    pub fn from_json(json: &str) -> Result<Maps, RegistryError> {
+        let maps: Maps = DeJson::deserialize_json(json)
+            .map_err(|error| RegistryError::Json(error.to_string()))?;
+
+        if maps.maps.is_empty() {
+            return Err(RegistryError::Empty);
+        }
+        for (n, map) in maps.maps.iter().enumerate() {
+            if maps.maps[..n].iter().any(|other| other.id == map.id) {
+                return Err(RegistryError::DuplicateId {
+                    name: map.name.clone(),
+                    id: map.id,
+                });
+            }
+        }
+        Ok(maps)
+    }
+
+    pub fn get(&self, id: MapId) -> Option<&MapDef> {
+        self.maps.iter().find(|map| map.id == id)
+    }
+
+    /// Played when nothing else was picked, like in quick games.
+    pub fn first(&self) -> &MapDef {
+        &self.maps[0]
+    }
+
+    /// Map after `id` in the list with room for `players`, wrapping around,
+    /// the first one for `None`. Falls back to `id` when no other map fits.
+    pub fn next(&self, id: Option<MapId>, players: usize) -> &MapDef {
+        let start = id
+            .and_then(|id| self.maps.iter().position(|map| map.id == id))
+            .map_or(0, |n| n + 1);
+        let count = self.maps.len();
+
+        (0..count)
+            .map(|n| &self.maps[(start + n) % count])
+            .find(|map| map.players as usize >= players)
+            .or_else(|| id.and_then(|id| self.get(id)))
+            .unwrap_or_else(|| self.first())
+    }
+}
+
+/// Collision grid and logic objects of the Tiled json of a map.
+pub fn load_level(json: &str) -> Result<simulation::Map, DeJsonErr> {
+    let tiled: tiled::TiledMap = DeJson::deserialize_json(json)?;
+    Ok(tiled.level())
+}
+
+/// Map of the last match, the lobby proposes the one after it.
+#[derive(Debug, Default)]
+pub struct Rotation {
+    pub last: Option<MapId>,
+}
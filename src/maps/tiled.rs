@@ -0,0 +1,494 @@
+//! The parts of the Tiled json format the game reads by itself, `macroquad_tiled`
+//! only gets the maps ready to draw. The level editor writes them back.
+
+use macroquad::math::{vec2, Rect};
+use nanoserde::{DeJson, DeJsonErr, DeJsonState, DeJsonTok};
+use std::{fmt::Write, str::Chars};
+
+use crate::simulation::{Collision, Map, Mover, PickupZone};
+
+#[derive(Debug, Clone, DeJson)]
+pub struct TiledMap {
+    pub width: u32,
+    pub height: u32,
+    pub tilewidth: u32,
+    pub tileheight: u32,
+    pub layers: Vec<TiledLayer>,
+    pub tilesets: Vec<TiledTileset>,
+}
+
+#[derive(Debug, Clone, DeJson)]
+pub struct TiledLayer {
+    pub name: String,
+    // tile layers have `data`, object layers `objects`
+    pub data: Option<Vec<u32>>,
+    pub objects: Option<Vec<TiledObject>>,
+}
+
+#[derive(Debug, Clone, DeJson)]
+pub struct TiledObject {
+    pub name: String,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub gid: Option<u32>,
+    pub properties: Option<Vec<TiledProperty>>,
+}
+
+impl TiledObject {
+    pub fn new(name: &str, rect: Rect) -> TiledObject {
+        TiledObject {
+            name: name.to_string(),
+            x: rect.x,
+            y: rect.y,
+            width: rect.w,
+            height: rect.h,
+            gid: None,
+            properties: None,
+        }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+
+    /// A number property, `default` when it is missing or not a number.
+    pub fn number(&self, name: &str, default: f32) -> f32 {
+        let property = self
+            .properties
+            .iter()
+            .flatten()
+            .find(|property| property.name == name);
+        match property.map(|property| &property.value) {
+            Some(PropertyValue::Number(value)) => *value as f32,
+            _ => default,
+        }
+    }
+
+    /// The movers of a level, with the path and timing taken from their properties.
+    pub fn mover(&self) -> Option<Mover> {
+        let rect = Rect::new(self.x, self.y, self.width, self.height);
+        let phase = self.number("phase", 0.);
+
+        match self.name.as_str() {
+            "moving_platform" => Some(Mover::platform(
+                rect,
+                vec2(self.number("dx", 0.), self.number("dy", 0.)),
+                self.number("speed", 60.),
+                phase,
+            )),
+            "crusher" => Some(Mover::crusher(
+                rect,
+                self.number("drop", rect.h),
+                self.number("period", 3.),
+                phase,
+            )),
+            "spike_trap" => Some(Mover::spike_trap(
+                rect,
+                self.number("period", 2.),
+                self.number("active", 1.),
+                phase,
+            )),
+            _ => None,
+        }
+    }
+
+    /// A `pickup_zone`, its number properties are the weights of the items they name
+    /// and `others` the weight of the rest.
+    pub fn pickup_zone(&self) -> Option<PickupZone> {
+        if self.name != "pickup_zone" {
+            return None;
+        }
+        let mut zone = PickupZone::new(Rect::new(self.x, self.y, self.width, self.height));
+        for property in self.properties.iter().flatten() {
+            if let PropertyValue::Number(weight) = property.value {
+                let weight = weight.max(0.).round() as u32;
+                match property.name.as_str() {
+                    "others" => zone.others = weight,
+                    name => zone.weights.push((name.to_string(), weight)),
+                }
+            }
+        }
+        Some(zone)
+    }
+}
+
+#[derive(Debug, Clone, DeJson)]
+pub struct TiledTileset {
+    pub firstgid: u32,
+    pub name: String,
+    pub image: Option<String>,
+    pub tilecount: u32,
+    /// Only the tiles with properties are listed
+    pub tiles: Option<Vec<TiledTile>>,
+    // only kept to be written back, the map tile size and no margins without them
+    pub columns: Option<u32>,
+    pub imagewidth: Option<u32>,
+    pub imageheight: Option<u32>,
+    pub tilewidth: Option<u32>,
+    pub tileheight: Option<u32>,
+    pub margin: Option<u32>,
+    pub spacing: Option<u32>,
+}
+
+#[derive(Debug, Clone, DeJson)]
+pub struct TiledTile {
+    pub id: u32,
+    pub properties: Option<Vec<TiledProperty>>,
+}
+
+#[derive(Debug, Clone, DeJson)]
+pub struct TiledProperty {
+    pub name: String,
+    pub value: PropertyValue,
+}
+
+/// Tiled writes the value of a property with the json type it was given.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyValue {
+    String(String),
+    Bool(bool),
+    Number(f64),
+}
+
+impl DeJson for PropertyValue {
+    fn de_json(s: &mut DeJsonState, i: &mut Chars) -> Result<PropertyValue, DeJsonErr> {
+        let value = match s.tok {
+            DeJsonTok::Str => PropertyValue::String(s.as_string()?),
+            DeJsonTok::Bool(value) => PropertyValue::Bool(value),
+            _ => PropertyValue::Number(s.as_f64()?),
+        };
+        s.next_tok(i)?;
+        Ok(value)
+    }
+}
+
+impl TiledTileset {
+    /// Damage taken by the tiles marked `destructible` with `true` rather than a number,
+    /// a grenade blast or two gun shots.
+    pub const DURABILITY: i32 = 100;
+
+    pub fn contains(&self, gid: u32) -> bool {
+        gid >= self.firstgid && gid < self.firstgid + self.tilecount
+    }
+
+    fn property(&self, id: u32, name: &str) -> Option<&PropertyValue> {
+        self.tiles
+            .iter()
+            .flatten()
+            .filter(|tile| tile.id == id)
+            .flat_map(|tile| tile.properties.iter().flatten())
+            .find(|property| property.name == name)
+            .map(|property| &property.value)
+    }
+
+    /// The `collision` property of the tile `id` of this tileset, solid without one.
+    /// The value is given back when it is not a known collision.
+    pub fn collision(&self, id: u32) -> Result<Collision, String> {
+        match self.property(id, "collision") {
+            None => Ok(Collision::Solid),
+            Some(PropertyValue::String(name)) => {
+                Collision::from_name(name).ok_or_else(|| name.clone())
+            }
+            Some(PropertyValue::Bool(value)) => Err(value.to_string()),
+            Some(PropertyValue::Number(value)) => Err(value.to_string()),
+        }
+    }
+
+    /// The `destructible` property of the tile `id`: the damage it takes before breaking,
+    /// `None` for a tile that never breaks. The value is given back when it is not
+    /// a positive number or a bool.
+    pub fn destructible(&self, id: u32) -> Result<Option<i32>, String> {
+        match self.property(id, "destructible") {
+            None | Some(PropertyValue::Bool(false)) => Ok(None),
+            Some(PropertyValue::Bool(true)) => Ok(Some(Self::DURABILITY)),
+            Some(PropertyValue::Number(value)) if *value >= 1. => Ok(Some(value.round() as i32)),
+            Some(PropertyValue::Number(value)) => Err(value.to_string()),
+            Some(PropertyValue::String(value)) => Err(value.clone()),
+        }
+    }
+}
+
+impl TiledMap {
+    pub fn layer(&self, name: &str) -> Option<&TiledLayer> {
+        self.layers.iter().find(|layer| layer.name == name)
+    }
+
+    pub fn layer_mut(&mut self, name: &str) -> Option<&mut TiledLayer> {
+        self.layers.iter_mut().find(|layer| layer.name == name)
+    }
+
+    /// Gid of the tile of the `main layer` at `x`, `y`, 0 for none.
+    pub fn tile(&self, x: u32, y: u32) -> u32 {
+        let n = (y * self.width + x) as usize;
+        self.layer("main layer")
+            .and_then(|layer| layer.data.as_ref())
+            .and_then(|data| data.get(n).copied())
+            .unwrap_or(0)
+    }
+
+    /// Put the tile `gid` in the `main layer`, 0 clears it. Nothing happens outside of the map.
+    pub fn set_tile(&mut self, x: u32, y: u32, gid: u32) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let n = (y * self.width + x) as usize;
+        let size = (self.width * self.height) as usize;
+        if let Some(data) = self
+            .layer_mut("main layer")
+            .and_then(|layer| layer.data.as_mut())
+        {
+            data.resize(size, 0);
+            data[n] = gid;
+        }
+    }
+
+    /// The map as Tiled saves it, for `macroquad_tiled` and Tiled to open.
+    /// Objects are numbered again in order.
+    pub fn to_json(&self) -> String {
+        let mut next_object = 1;
+        let layers = self
+            .layers
+            .iter()
+            .enumerate()
+            .map(|(n, layer)| layer.to_json(n as u32 + 1, self, &mut next_object))
+            .collect::<Vec<_>>();
+        let tilesets = self
+            .tilesets
+            .iter()
+            .map(|tileset| tileset.to_json(self))
+            .collect::<Vec<_>>();
+
+        format!(
+            r#"{{ "compressionlevel":-1,
+ "height":{height},
+ "infinite":false,
+ "layers":[
+        {layers}],
+ "nextlayerid":{next_layer},
+ "nextobjectid":{next_object},
+ "orientation":"orthogonal",
+ "renderorder":"right-down",
+ "tiledversion":"1.7.0",
+ "tileheight":{tileheight},
+ "tilesets":[
+        {tilesets}],
+ "tilewidth":{tilewidth},
+ "type":"map",
+ "version":"1.6",
+ "width":{width}
+}}
+"#,
+            height = self.height,
+            layers = layers.join(",\n        "),
+            next_layer = layers.len() + 1,
+            next_object = next_object,
+            tileheight = self.tileheight,
+            tilesets = tilesets.join(",\n        "),
+            tilewidth = self.tilewidth,
+            width = self.width,
+        )
+    }
+
+    /// Tiles not in any tileset and unknown collisions are taken as solid.
+    pub fn collision(&self, gid: u32) -> Collision {
+        if gid == 0 {
+            return Collision::Empty;
+        }
+        self.tilesets
+            .iter()
+            .find(|tileset| tileset.contains(gid))
+            .and_then(|tileset| tileset.collision(gid - tileset.firstgid).ok())
+            .unwrap_or(Collision::Solid)
+    }
+
+    /// Durability of the tile `gid`, `None` unless it is destructible.
+    pub fn durability(&self, gid: u32) -> Option<i32> {
+        self.tilesets
+            .iter()
+            .find(|tileset| tileset.contains(gid))
+            .and_then(|tileset| tileset.destructible(gid - tileset.firstgid).ok())
+            .flatten()
+    }
+
+    /// Collision grid of the `main layer` with the objects, movers and pickup zones of the
+    /// `logic` layer. Only solid tiles are destructible.
+    pub fn level(&self) -> Map {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let data = self
+            .layer("main layer")
+            .and_then(|layer| layer.data.as_ref())
+            .map_or(&[][..], |data| &data[..]);
+        let tiles = (0..width * height)
+            .map(|n| {
+                data.get(n)
+                    .map_or(Collision::Empty, |gid| self.collision(*gid))
+            })
+            .collect();
+
+        let mut map = Map::new(
+            width,
+            height,
+            self.tilewidth as _,
+            self.tileheight as _,
+            tiles,
+            vec![],
+        );
+        for (n, gid) in data.iter().enumerate().take(width * height) {
+            match self.durability(*gid) {
+                Some(durability) if map.tiles[n] == Collision::Solid => {
+                    map.durability.insert(n, durability);
+                }
+                _ => {}
+            }
+        }
+        let objects = self.layer("logic").and_then(|layer| layer.objects.as_ref());
+        for object in objects.into_iter().flatten() {
+            if let Some(mover) = object.mover() {
+                map.movers.push(mover);
+            } else if let Some(zone) = object.pickup_zone() {
+                map.pickup_zones.push(zone);
+            } else {
+                let rect = Rect::new(object.x, object.y, object.width, object.height);
+                map.add_object(&object.name, rect);
+            }
+        }
+        map
+    }
+}
+
+impl TiledLayer {
+    fn to_json(&self, id: u32, map: &TiledMap, next_object: &mut u32) -> String {
+        let mut json = format!(r#"{{ "id":{}, "name":{}, "#, id, json_string(&self.name));
+        match (&self.data, &self.objects) {
+            (Some(data), _) => {
+                let data = data.iter().map(u32::to_string).collect::<Vec<_>>();
+                write!(
+                    json,
+                    r#""type":"tilelayer", "width":{}, "height":{}, "data":[{}], "#,
+                    map.width,
+                    map.height,
+                    data.join(", ")
+                )
+                .unwrap();
+            }
+            (None, objects) => {
+                let objects = objects
+                    .iter()
+                    .flatten()
+                    .map(|object| {
+                        *next_object += 1;
+                        format!("\n                {}", object.to_json(*next_object - 1))
+                    })
+                    .collect::<Vec<_>>();
+                write!(
+                    json,
+                    r#""type":"objectgroup", "draworder":"topdown", "objects":[{}], "#,
+                    objects.join(",")
+                )
+                .unwrap();
+            }
+        }
+        json + r#""opacity":1, "visible":true, "x":0, "y":0 }"#
+    }
+}
+
+impl TiledObject {
+    fn to_json(&self, id: u32) -> String {
+        let mut json = format!(r#"{{ "id":{}, "name":{}, "#, id, json_string(&self.name));
+        if let Some(gid) = self.gid {
+            write!(json, r#""gid":{}, "#, gid).unwrap();
+        } else if self.width == 0. && self.height == 0. {
+            json += r#""point":true, "#;
+        }
+        if let Some(properties) = &self.properties {
+            write!(json, r#""properties":{}, "#, properties_json(properties)).unwrap();
+        }
+        write!(
+            json,
+            r#""x":{}, "y":{}, "width":{}, "height":{}, "rotation":0, "type":"", "visible":true }}"#,
+            self.x, self.y, self.width, self.height
+        )
+        .unwrap();
+        json
+    }
+}
+
+impl TiledTileset {
+    fn to_json(&self, map: &TiledMap) -> String {
+        let tilewidth = self.tilewidth.unwrap_or(map.tilewidth);
+        let mut json = format!(
+            r#"{{ "firstgid":{}, "name":{}, "tilecount":{}, "tilewidth":{}, "tileheight":{}, "margin":{}, "spacing":{}, "#,
+            self.firstgid,
+            json_string(&self.name),
+            self.tilecount,
+            tilewidth,
+            self.tileheight.unwrap_or(map.tileheight),
+            self.margin.unwrap_or(0),
+            self.spacing.unwrap_or(0)
+        );
+        if let Some(image) = &self.image {
+            let imagewidth = self.imagewidth.unwrap_or(0);
+            write!(
+                json,
+                r#""image":{}, "imagewidth":{}, "imageheight":{}, "columns":{}, "#,
+                json_string(image),
+                imagewidth,
+                self.imageheight.unwrap_or(0),
+                self.columns.unwrap_or(imagewidth / tilewidth.max(1))
+            )
+            .unwrap();
+        }
+        let tiles = self
+            .tiles
+            .iter()
+            .flatten()
+            .map(|tile| {
+                let properties = tile.properties.as_deref().unwrap_or(&[]);
+                format!(
+                    r#"{{ "id":{}, "properties":{} }}"#,
+                    tile.id,
+                    properties_json(properties)
+                )
+            })
+            .collect::<Vec<_>>();
+        write!(json, r#""tiles":[{}] }}"#, tiles.join(", ")).unwrap();
+        json
+    }
+}
+
+fn properties_json(properties: &[TiledProperty]) -> String {
+    let properties = properties
+        .iter()
+        .map(|property| {
+            let (kind, value) = match &property.value {
+                PropertyValue::String(value) => ("string", json_string(value)),
+                PropertyValue::Bool(value) => ("bool", value.to_string()),
+                PropertyValue::Number(value) if value.fract() == 0. => ("int", value.to_string()),
+                PropertyValue::Number(value) => ("float", value.to_string()),
+            };
+            format!(
+                r#"{{ "name":{}, "type":"{}", "value":{} }}"#,
+                json_string(&property.name),
+                kind,
+                value
+            )
+        })
+        .collect::<Vec<_>>();
+    format!("[{}]", properties.join(", "))
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json += "\\\"",
+            '\\' => json += "\\\\",
+            '\n' => json += "\\n",
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json + "\""
+}
@@ -0,0 +1,697 @@
+//! Checks a Tiled map has everything the game expects before it gets to panic
+//! on it, see `fishgame --validate-map`.
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fmt,
+};
+
+use macroquad::math::{vec2, Rect, Vec2};
+use nanoserde::DeJson;
+
+use super::tiled::TiledMap;
+use crate::{
+    consts,
+    simulation::{Collision, Map, FISH_HEIGHT, FISH_WIDTH},
+};
+
+/// Positions go over the network as 10 bit numbers, see `PlayerStateBits`.
+pub const NETWORK_RANGE: u32 = 1024;
+
+/// Tileset images `Resources` has a texture for.
+pub const TILESET_IMAGES: [&str; 2] = ["tileset.png", "decorations1.png"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapError {
+    Json(String),
+    MissingLayer {
+        name: &'static str,
+        kind: &'static str,
+    },
+    LayerSize {
+        name: String,
+        tiles: usize,
+        expected: usize,
+    },
+    TooLarge {
+        width: u32,
+        height: u32,
+    },
+    UnknownImage {
+        tileset: String,
+        image: String,
+    },
+    UnknownGid {
+        layer: String,
+        gid: u32,
+    },
+    UnknownCollision {
+        tileset: String,
+        tile: u32,
+        value: String,
+    },
+    BadDestructible {
+        tileset: String,
+        tile: u32,
+        value: String,
+    },
+    NoSpawn,
+    ObjectOutside {
+        name: String,
+        pos: Vec2,
+    },
+    SpawnInWall {
+        spawn: usize,
+        pos: Vec2,
+    },
+    SpawnFallsOut {
+        spawn: usize,
+        pos: Vec2,
+    },
+    Unreachable {
+        from: usize,
+        to: usize,
+    },
+    /// `None` for the whole map when it has no pickup zones
+    NoPickupSpot {
+        zone: Option<usize>,
+    },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Json(error) => write!(f, "not a Tiled json map: {}", error),
+            MapError::MissingLayer { name, kind } => {
+                write!(f, "no {} layer called `{}`", kind, name)
+            }
+            MapError::LayerSize {
+                name,
+                tiles,
+                expected,
+            } => write!(
+                f,
+                "layer `{}` has {} tiles, the map size needs {}",
+                name, tiles, expected
+            ),
+            MapError::TooLarge { width, height } => write!(
+                f,
+                "map is {}x{} pixels, positions are sent over the network as numbers below {}",
+                width, height, NETWORK_RANGE
+            ),
+            MapError::UnknownImage { tileset, image } => write!(
+                f,
+                "tileset `{}` uses `{}`, the game only has textures for {}",
+                tileset,
+                image,
+                TILESET_IMAGES.join(", ")
+            ),
+            MapError::UnknownGid { layer, gid } => {
+                write!(f, "layer `{}` uses gid {}, not in any tileset", layer, gid)
+            }
+            MapError::UnknownCollision {
+                tileset,
+                tile,
+                value,
+            } => write!(
+                f,
+                "tile {} of tileset `{}` has collision {}, expected one of solid, platform, \
+                 spikes, ladder, water or none",
+                tile, tileset, value
+            ),
+            MapError::BadDestructible {
+                tileset,
+                tile,
+                value,
+            } => write!(
+                f,
+                "tile {} of tileset `{}` has destructible {}, expected true or the damage \
+                 it takes to break",
+                tile, tileset, value
+            ),
+            MapError::NoSpawn => write!(f, "the `logic` layer has no `spawn` object"),
+            MapError::ObjectOutside { name, pos } => write!(
+                f,
+                "`{}` at ({}, {}) is outside of the map",
+                name, pos.x, pos.y
+            ),
+            MapError::SpawnInWall { spawn, pos } => write!(
+                f,
+                "spawn {} at ({}, {}) is inside solid tiles",
+                spawn, pos.x, pos.y
+            ),
+            MapError::SpawnFallsOut { spawn, pos } => write!(
+                f,
+                "spawn {} at ({}, {}) has no ground below, fish fall out of the map",
+                spawn, pos.x, pos.y
+            ),
+            MapError::Unreachable { from, to } => write!(
+                f,
+                "spawn {} can't be reached from spawn {} by running and jumping",
+                to, from
+            ),
+            MapError::NoPickupSpot { zone: None } => {
+                write!(
+                    f,
+                    "pickups have no free tile with ground below to show up on"
+                )
+            }
+            MapError::NoPickupSpot { zone: Some(zone) } => write!(
+                f,
+                "pickup zone {} has no free tile with ground below for pickups",
+                zone
+            ),
+        }
+    }
+}
+
+/// Every problem found in the Tiled json of a map, an empty list for a playable one.
+pub fn validate(json: &str) -> Vec<MapError> {
+    let tiled: TiledMap = match DeJson::deserialize_json(json) {
+        Ok(tiled) => tiled,
+        Err(error) => return vec![MapError::Json(error.to_string())],
+    };
+    let mut errors = vec![];
+
+    let layer = |name: &'static str, kind: &'static str, tiles: bool| match tiled.layer(name) {
+        Some(layer) if layer.data.is_some() == tiles && layer.objects.is_some() != tiles => {
+            Ok(layer)
+        }
+        _ => Err(MapError::MissingLayer { name, kind }),
+    };
+    let main = layer("main layer", "tile", true);
+    let logic = layer("logic", "object", false);
+    let decorations = layer("decorations", "object", false);
+    for layer in &[&main, &logic, &decorations] {
+        if let Err(error) = layer {
+            errors.push(error.clone());
+        }
+    }
+
+    let (width, height) = (
+        tiled.width * tiled.tilewidth,
+        tiled.height * tiled.tileheight,
+    );
+    if width > NETWORK_RANGE || height > NETWORK_RANGE {
+        errors.push(MapError::TooLarge { width, height });
+    }
+
+    for tileset in &tiled.tilesets {
+        let image = tileset.image.clone().unwrap_or_default();
+        if TILESET_IMAGES.contains(&image.as_str()) == false {
+            errors.push(MapError::UnknownImage {
+                tileset: tileset.name.clone(),
+                image,
+            });
+        }
+        for tile in tileset.tiles.iter().flatten() {
+            if let Err(value) = tileset.collision(tile.id) {
+                errors.push(MapError::UnknownCollision {
+                    tileset: tileset.name.clone(),
+                    tile: tile.id,
+                    value,
+                });
+            }
+            if let Err(value) = tileset.destructible(tile.id) {
+                errors.push(MapError::BadDestructible {
+                    tileset: tileset.name.clone(),
+                    tile: tile.id,
+                    value,
+                });
+            }
+        }
+    }
+    let check_gid = |layer: &str, gid: u32, errors: &mut Vec<MapError>| {
+        if tiled.tilesets.iter().any(|tileset| tileset.contains(gid)) == false {
+            errors.push(MapError::UnknownGid {
+                layer: layer.to_string(),
+                gid,
+            });
+        }
+    };
+    if let Ok(decorations) = &decorations {
+        for object in decorations.objects.as_ref().unwrap() {
+            check_gid(&decorations.name, object.gid.unwrap_or(0), &mut errors);
+        }
+    }
+
+    let main = match main {
+        Ok(main) => main,
+        Err(_) => return errors,
+    };
+    let data = main.data.as_ref().unwrap();
+    let expected = (tiled.width * tiled.height) as usize;
+    if data.len() != expected {
+        errors.push(MapError::LayerSize {
+            name: main.name.clone(),
+            tiles: data.len(),
+            expected,
+        });
+        return errors;
+    }
+    let gids = data
+        .iter()
+        .filter(|gid| **gid != 0)
+        .collect::<BTreeSet<_>>();
+    for gid in gids {
+        check_gid(&main.name, *gid, &mut errors);
+    }
+
+    let map = tiled.level();
+    let bounds = Rect::new(0., 0., width as f32, height as f32);
+    if let Ok(logic) = logic {
+        for object in logic.objects.as_ref().unwrap() {
+            let pos = vec2(object.x, object.y);
+            if bounds.contains(pos) == false {
+                errors.push(MapError::ObjectOutside {
+                    name: object.name.clone(),
+                    pos,
+                });
+            }
+        }
+        if map.spawns.is_empty() {
+            errors.push(MapError::NoSpawn);
+        }
+        errors.extend(check_spawns(&map));
+        errors.extend(check_pickups(&map));
+    }
+
+    errors
+}
+
+/// A fish standing in a tile column with its feet at the bottom of row `y`.
+type Slot = (i32, i32);
+
+fn slot_pos(map: &Map, (x, y): Slot) -> Vec2 {
+    vec2(
+        x as f32 * map.tile_width + (map.tile_width - FISH_WIDTH as f32) / 2.,
+        (y + 1) as f32 * map.tile_height - FISH_HEIGHT as f32,
+    )
+}
+
+fn free(map: &Map, slot: Slot) -> bool {
+    map.collide_solids(slot_pos(map, slot), FISH_WIDTH, FISH_HEIGHT) == false
+}
+
+/// On a ladder or in water, where a fish goes up and down as it likes.
+fn climbs(map: &Map, slot: Slot) -> bool {
+    let pos = slot_pos(map, slot);
+    map.touches(pos, FISH_WIDTH, FISH_HEIGHT, Collision::Ladder)
+        || map.in_water(pos, FISH_WIDTH, FISH_HEIGHT)
+}
+
+/// Where a fish falling from `slot` stops, `None` when it falls out of the map.
+fn landing(map: &Map, (x, mut y): Slot) -> Option<Slot> {
+    while y < map.height as i32 {
+        let on_platform = map.platform_below(slot_pos(map, (x, y)), FISH_WIDTH, FISH_HEIGHT, 0.);
+        if free(map, (x, y + 1)) == false || on_platform.is_some() {
+            return Some((x, y));
+        }
+        y += 1;
+    }
+    None
+}
+
+/// Everywhere a fish standing on `from` gets to with runs and plain jumps, without
+/// the movement abilities the host may turn off.
+fn reachable(map: &Map, from: Slot) -> BTreeSet<Slot> {
+    let jump = consts::JUMP_SPEED * consts::JUMP_SPEED / (2. * consts::GRAVITY);
+    let rise = (jump / map.tile_height) as i32;
+    // run while going up, more is possible while falling back down
+    let reach = (consts::RUN_SPEED * consts::JUMP_SPEED / consts::GRAVITY / map.tile_width) as i32;
+
+    let mut visited = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    visited.insert(from);
+    queue.push_back(from);
+
+    while let Some((x, y)) = queue.pop_front() {
+        // holds on anywhere on a ladder and swims anywhere in water, gets out at both ends
+        if climbs(map, (x, y)) {
+            for &slot in &[(x, y - 1), (x, y + 1)] {
+                let next = match free(map, slot) {
+                    true if climbs(map, slot) => Some(slot),
+                    true => landing(map, slot),
+                    false => None,
+                };
+                if let Some(next) = next {
+                    if visited.insert(next) {
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        for h in 0..=rise {
+            if free(map, (x, y - h)) == false {
+                break;
+            }
+            for &dir in &[-1, 1] {
+                for d in 1..=reach.max(1) {
+                    let slot = (x + dir * d, y - h);
+                    if free(map, slot) == false {
+                        break;
+                    }
+                    if let Some(landed) = landing(map, slot) {
+                        if visited.insert(landed) {
+                            queue.push_back(landed);
+                        }
+                    }
+                    // walking off a ledge takes a single step
+                    if h == 0 {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    visited
+}
+
+fn check_pickups(map: &Map) -> Vec<MapError> {
+    if map.pickup_zones.is_empty() && map.pickup_spots(map.pickup_area()).is_empty() {
+        return vec![MapError::NoPickupSpot { zone: None }];
+    }
+    map.pickup_zones
+        .iter()
+        .enumerate()
+        .filter(|(_, zone)| map.pickup_spots(zone.rect).is_empty())
+        .map(|(zone, _)| MapError::NoPickupSpot { zone: Some(zone) })
+        .collect()
+}
+
+fn check_spawns(map: &Map) -> Vec<MapError> {
+    let mut errors = vec![];
+    let mut landings = vec![];
+
+    for (spawn, pos) in map.spawns.iter().copied().enumerate() {
+        let feet = pos + vec2(FISH_WIDTH as f32 / 2., FISH_HEIGHT as f32 - 1.);
+        let slot = (
+            (feet.x / map.tile_width).floor() as i32,
+            (feet.y / map.tile_height).floor() as i32,
+        );
+
+        if map.collide_solids(pos, FISH_WIDTH, FISH_HEIGHT) || free(map, slot) == false {
+            errors.push(MapError::SpawnInWall { spawn, pos });
+        } else if let Some(landed) = landing(map, slot) {
+            landings.push((spawn, landed));
+        } else {
+            errors.push(MapError::SpawnFallsOut { spawn, pos });
+        }
+    }
+
+    for &(from, slot) in &landings {
+        let reachable = reachable(map, slot);
+        for &(to, other) in &landings {
+            if reachable.contains(&other) == false {
+                errors.push(MapError::Unreachable { from, to });
+            }
+        }
+    }
+    errors
+}
+
+/// Print the problems of every map file, returns the exit code of the process.
+pub fn validate_files(paths: &[String]) -> i32 {
+    if paths.is_empty() {
+        eprintln!("usage: fishgame --validate-map <map.json>...");
+        return 2;
+    }
+    let mut failed = false;
+
+    for path in paths {
+        let errors = match std::fs::read_to_string(path) {
+            Ok(json) => validate(&json),
+            Err(error) => {
+                eprintln!("{}: {}", path, error);
+                failed = true;
+                continue;
+            }
+        };
+        if errors.is_empty() {
+            println!("{}: ok", path);
+        }
+        for error in &errors {
+            eprintln!("{}: {}", path, error);
+        }
+        failed |= errors.is_empty() == false;
+    }
+    failed as i32
+}
+
+#[test]
+fn test_shipped_maps() {
+    let maps = super::Maps::from_json(include_str!("../../assets/maps/maps.json")).unwrap();
+
+    for map in &maps.maps {
+        let json = std::fs::read_to_string(&map.path).unwrap();
+        assert_eq!(validate(&json), vec![], "{}", map.path);
+    }
+}
+
+#[test]
+fn test_broken_registry() {
+    use super::{Maps, RegistryError};
+
+    let json = include_str!("../../assets/maps/maps.json");
+    assert!(matches!(Maps::from_json("{}"), Err(RegistryError::Json(_))));
+    assert_eq!(
+        Maps::from_json(r#"{"maps": []}"#),
+        Err(RegistryError::Empty)
+    );
+    assert_eq!(
+        Maps::from_json(&json.replace("\"id\": 2,", "\"id\": 1,")),
+        Err(RegistryError::DuplicateId {
+            name: "Twin reefs".to_string(),
+            id: 1
+        })
+    );
+}
+
+#[test]
+fn test_broken_maps() {
+    let json = include_str!("../../assets/maps/fish_bowl.json");
+    assert!(matches!(validate("{}")[..], [MapError::Json(_)]));
+
+    let errors = validate(&json.replace("\"logic\"", "\"logik\""));
+    assert_eq!(
+        errors,
+        vec![MapError::MissingLayer {
+            name: "logic",
+            kind: "object"
+        }]
+    );
+
+    let errors = validate(&json.replace("\"spawn\"", "\"spawn_point\""));
+    assert_eq!(errors, vec![MapError::NoSpawn]);
+
+    let errors = validate(&json.replace("\"width\":27", "\"width\":40"));
+    assert!(errors.contains(&MapError::TooLarge {
+        width: 1280,
+        height: 672
+    }));
+
+    let errors = validate(&json.replace("decorations1.png", "decorations2.png"));
+    assert!(matches!(errors[..], [MapError::UnknownImage { .. }]));
+
+    let errors = validate(&with_collision(json, "\"lava\""));
+    assert_eq!(errors.len(), 49);
+    assert_eq!(
+        errors[0],
+        MapError::UnknownCollision {
+            tileset: "tileset".to_string(),
+            tile: 0,
+            value: "lava".to_string()
+        }
+    );
+}
+
+/// `json` with every tile of the first tileset given the `collision` property
+#[cfg(test)]
+fn with_collision(json: &str, value: &str) -> String {
+    let tiles = (0..49)
+        .map(|id| {
+            format!(
+                r#"{{"id":{},"properties":[{{"name":"collision","type":"string","value":{}}},
+                   {{"name":"depth","type":"int","value":-3}},
+                   {{"name":"shiny","type":"bool","value":true}}]}}"#,
+                id, value
+            )
+        })
+        .collect::<Vec<_>>();
+    json.replace(
+        "\"tilecount\":49,",
+        &format!("\"tilecount\":49, \"tiles\":[{}],", tiles.join(",")),
+    )
+}
+
+#[test]
+fn test_movers() {
+    let json = include_str!("../../assets/maps/fish_bowl.json").replace(
+        "\"name\":\"logic\",\n         \"objects\":[",
+        r#""name":"logic",
+         "objects":[
+                {"height":16, "id":90, "name":"moving_platform", "rotation":0, "type":"",
+                 "visible":true, "width":96, "x":320, "y":256,
+                 "properties":[{"name":"dx", "type":"int", "value":-128},
+                               {"name":"speed", "type":"float", "value":40.5}]},
+                {"height":32, "id":91, "name":"spike_trap", "rotation":0, "type":"",
+                 "visible":true, "width":64, "x":96, "y":448},"#,
+    );
+    assert_eq!(validate(&json), vec![]);
+
+    let level = super::load_level(&json).unwrap();
+    let platform = level.movers[0];
+    assert_eq!(platform.kind, crate::simulation::MoverKind::Platform);
+    assert_eq!(platform.path, vec2(-128., 0.));
+    assert_eq!(platform.period, 128. * 2. / 40.5);
+    assert_eq!(level.movers[1].rect, Rect::new(96., 448., 64., 32.));
+}
+
+#[test]
+fn test_water_volumes() {
+    let json = include_str!("../../assets/maps/fish_bowl.json").replace(
+        "\"name\":\"logic\",\n         \"objects\":[",
+        r#""name":"logic",
+         "objects":[
+                {"height":96, "id":90, "name":"water", "rotation":0, "type":"",
+                 "visible":true, "width":160, "x":64, "y":384},"#,
+    );
+    assert_eq!(validate(&json), vec![]);
+
+    let level = super::load_level(&json).unwrap();
+    assert_eq!(level.water, vec![Rect::new(64., 384., 160., 96.)]);
+    assert!(level.water_at(vec2(100., 400.)));
+    assert!(level.water_at(vec2(100., 380.)) == false);
+}
+
+#[test]
+fn test_pickup_zones() {
+    let json = include_str!("../../assets/maps/fish_bowl.json").replace(
+        "\"name\":\"logic\",\n         \"objects\":[",
+        r#""name":"logic",
+         "objects":[
+                {"height":128, "id":90, "name":"pickup_zone", "rotation":0, "type":"",
+                 "visible":true, "width":256, "x":480, "y":448,
+                 "properties":[{"name":"sword", "type":"int", "value":3},
+                               {"name":"others", "type":"int", "value":0}]},
+                {"height":32, "id":91, "name":"pickup_zone", "rotation":0, "type":"",
+                 "visible":true, "width":32, "x":320, "y":32},"#,
+    );
+    assert_eq!(
+        validate(&json),
+        vec![MapError::NoPickupSpot { zone: Some(1) }]
+    );
+
+    let level = super::load_level(&json).unwrap();
+    let zone = &level.pickup_zones[0];
+    assert_eq!(zone.rect, Rect::new(480., 448., 256., 128.));
+    assert_eq!(zone.weight("sword"), 3);
+    assert_eq!(zone.weight("armor"), 0);
+    assert_eq!(level.pickup_zones[1].weight("armor"), 1);
+    assert_eq!(level.pickup_exclusions.len(), 1);
+}
+
+#[test]
+fn test_tile_collisions() {
+    let json = include_str!("../../assets/maps/fish_bowl.json");
+    let solid = super::load_level(json).unwrap();
+    let platforms = super::load_level(&with_collision(json, "\"platform\"")).unwrap();
+
+    assert!(solid.tiles.contains(&Collision::Solid));
+    assert!(platforms.tiles.contains(&Collision::Solid) == false);
+    for (solid, platform) in solid.tiles.iter().zip(&platforms.tiles) {
+        assert_eq!(*solid == Collision::Solid, *platform == Collision::Platform);
+    }
+
+    // only strings name a collision
+    let errors = validate(&with_collision(json, "1"));
+    assert!(matches!(errors[0], MapError::UnknownCollision { .. }));
+}
+
+#[test]
+fn test_destructible_tiles() {
+    let json = include_str!("../../assets/maps/fish_bowl.json");
+    let with_destructible = |value: &str| {
+        let tiles = (0..49)
+            .map(|id| {
+                format!(
+                    r#"{{"id":{},"properties":[{{"name":"destructible","value":{}}}]}}"#,
+                    id, value
+                )
+            })
+            .collect::<Vec<_>>();
+        json.replace(
+            "\"tilecount\":49,",
+            &format!("\"tilecount\":49, \"tiles\":[{}],", tiles.join(",")),
+        )
+    };
+
+    let level = super::load_level(json).unwrap();
+    assert!(level.durability.is_empty());
+
+    let level = super::load_level(&with_destructible("true")).unwrap();
+    let solid = level.tiles.iter().filter(|tile| **tile == Collision::Solid);
+    assert_eq!(level.durability.len(), solid.count());
+    assert!(level
+        .durability
+        .values()
+        .all(|durability| *durability == super::tiled::TiledTileset::DURABILITY));
+
+    let json = with_destructible("30");
+    assert!(validate(&json).is_empty());
+    let mut level = super::load_level(&json).unwrap();
+    let (&n, &durability) = level.durability.iter().next().unwrap();
+    assert_eq!(durability, 30);
+    level.break_tile(n);
+    assert_eq!(level.tiles[n], Collision::Empty);
+    assert!(level.durability.contains_key(&n) == false);
+
+    for value in &["0", "\"yes\""] {
+        let errors = validate(&with_destructible(value));
+        assert!(matches!(errors[0], MapError::BadDestructible { .. }));
+    }
+}
+
+#[test]
+fn test_written_maps() {
+    use super::TiledObject;
+
+    let json = include_str!("../../assets/maps/fish_bowl.json");
+    let mut tiled: TiledMap = DeJson::deserialize_json(json).unwrap();
+
+    let written = tiled.to_json();
+    assert_eq!(validate(&written), vec![]);
+    assert_eq!(
+        super::load_level(&written).unwrap(),
+        super::load_level(json).unwrap()
+    );
+    assert_eq!(
+        TiledMap::deserialize_json(&written).unwrap().to_json(),
+        written
+    );
+
+    tiled.set_tile(5, 3, 0);
+    tiled.set_tile(100, 3, 1);
+    let spawn = TiledObject::new("spawn", Rect::new(96., 64., 0., 0.));
+    let logic = tiled.layer_mut("logic").unwrap();
+    logic.objects.as_mut().unwrap().push(spawn);
+    logic.objects.as_mut().unwrap()[0].name = "a \"quoted\" name".to_string();
+
+    let written = tiled.to_json();
+    let level = super::load_level(&written).unwrap();
+    let original = super::load_level(json).unwrap();
+    assert_eq!(level.spawns.len(), original.spawns.len());
+    assert_eq!(level.spawns.last(), Some(&vec2(96., 64.)));
+    assert_eq!(level.tile(5, 3), Collision::Empty);
+    assert_eq!(
+        TiledMap::deserialize_json(&written)
+            .unwrap()
+            .layer("logic")
+            .unwrap()
+            .objects
+            .as_ref()
+            .unwrap()[0]
+            .name,
+        "a \"quoted\" name"
+    );
+}
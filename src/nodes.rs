@@ -3,19 +3,28 @@ mod camera;
 mod decoration;
 mod fxses;
 mod global_events;
+mod hot_reload;
 mod level_background;
+mod movers;
 mod nakama;
+mod objectives;
 mod pickup;
 mod player;
 mod remote_player;
+mod thrown_weapons;
+mod touch_controls;
 
 pub use bullets::Bullets;
 pub use camera::Camera;
 pub use decoration::Decoration;
 pub use fxses::Fxses;
 pub use global_events::GlobalEvents;
+pub use hot_reload::HotReload;
 pub use level_background::LevelBackground;
-pub use nakama::{Nakama, NakamaRealtimeGame};
+pub use movers::Movers;
+pub use nakama::{Nakama, NakamaRealtimeGame, NakamaRollbackGame};
 pub use pickup::Pickup;
 pub use player::Player;
 pub use remote_player::RemotePlayer;
+pub use thrown_weapons::ThrownWeapons;
+pub use touch_controls::TouchControls;
@@ -4,8 +4,10 @@ use macroquad::{
 };
 
 use crate::{
-    consts,
-    nodes::{Player, RemotePlayer},
+    nodes::{Movers, NakamaRealtimeGame, Player, RemotePlayer},
+    simulation::{self, Team},
+    timestep::FixedTimestep,
+    weapons::{Projectile, Tint, WeaponId},
     Resources,
 };
 
@@ -13,12 +15,18 @@ struct Bullet {
     pos: Vec2,
     speed: Vec2,
     lived: f32,
-    lifetime: f32,
+    weapon: WeaponId,
+    projectile: Projectile,
+    // of the fish firing, or deflecting it last
+    team: Option<Team>,
+    // sent back by a sword already, can't be deflected again
+    deflected: bool,
 }
 
 pub struct Bullets {
     player: scene::Handle<Player>,
     bullets: Vec<Bullet>,
+    timestep: FixedTimestep,
 }
 
 impl Bullets {
@@ -26,65 +34,175 @@ impl Bullets {
         Bullets {
             player,
             bullets: Vec::with_capacity(200),
+            timestep: FixedTimestep::new(),
         }
     }
 
-    pub fn spawn_bullet(&mut self, pos: Vec2, facing: bool) {
-        let dir = if facing {
-            vec2(1.0, 0.0)
-        } else {
-            vec2(-1.0, 0.0)
+    /// `pos` and `facing` are of the fish firing.
+    pub fn spawn_bullet(
+        &mut self,
+        pos: Vec2,
+        facing: bool,
+        weapon: WeaponId,
+        projectile: &Projectile,
+        team: Option<Team>,
+    ) {
+        for (pos, speed) in projectile.spawn(pos, facing) {
+            self.bullets.push(Bullet {
+                pos,
+                speed,
+                lived: 0.0,
+                weapon,
+                projectile: *projectile,
+                team,
+                deflected: false,
+            });
+        }
+    }
+
+    /// A bullet deflected by another client's sword, replaces our copy of it if there is one.
+    /// `team` is of the fish deflecting it.
+    pub fn deflect(&mut self, pos: Vec2, speed: Vec2, weapon: WeaponId, team: Option<Team>) {
+        let mut resources = storage::get_mut::<Resources>();
+        let projectile = match resources.weapons.get(weapon).and_then(|def| def.projectile) {
+            Some(projectile) => projectile,
+            None => return,
         };
+
+        let nearest = self
+            .bullets
+            .iter()
+            .enumerate()
+            .filter(|(_, bullet)| bullet.weapon == weapon && bullet.pos.distance(pos) < 60.)
+            .min_by(|(_, a), (_, b)| {
+                let (a, b) = (a.pos.distance(pos), b.pos.distance(pos));
+                a.partial_cmp(&b).unwrap()
+            })
+            .map(|(n, _)| n);
+        if let Some(n) = nearest {
+            self.bullets.remove(n);
+        }
+
+        resources.hit_fxses.spawn(pos);
         self.bullets.push(Bullet {
-            pos: pos + vec2(16.0, 30.0) + dir * 32.0,
-            speed: dir * consts::BULLET_SPEED,
+            pos,
+            speed,
             lived: 0.0,
-            lifetime: 0.7,
+            weapon,
+            projectile,
+            team,
+            deflected: true,
         });
     }
 }
 
 impl scene::Node for Bullets {
     fn draw(node: RefMut<Self>) {
+        let alpha = node.timestep.alpha();
+
         for bullet in &node.bullets {
-            draw_circle(
-                bullet.pos.x,
-                bullet.pos.y,
-                4.,
-                Color::new(1.0, 1.0, 0.8, 1.0),
-            );
+            draw_projectile(bullet.pos, bullet.speed, &bullet.projectile, alpha);
         }
     }
 
     fn update(mut node: RefMut<Self>) {
-        let mut resources = storage::get_mut::<Resources>();
+        let friendly_fire = scene::find_node_by_type::<NakamaRealtimeGame>()
+            .map_or(true, |nakama| nakama.friendly_fire);
+        let time = scene::find_node_by_type::<Movers>().map_or(0., |movers| movers.time());
         let mut player = scene::get_node(node.player);
-        let mut others = scene::find_nodes_by_type::<RemotePlayer>();
+        let team = player.team();
+        let parry = player.parry_box();
+        let mut resources = storage::get_mut::<Resources>();
+        let mut deflected = vec![];
+        // destructible tiles hit, with the damage dealt to them
+        let mut tile_hits = vec![];
 
-        for bullet in &mut node.bullets {
-            bullet.pos += bullet.speed * get_frame_time();
-            bullet.lived += get_frame_time();
-        }
+        for _ in 0..node.timestep.advance(get_frame_time()) {
+            let others = scene::find_nodes_by_type::<RemotePlayer>()
+                .filter(|other| other.dead == false)
+                .map(|other| (other.pos(), other.team()))
+                .collect::<Vec<_>>();
 
-        node.bullets.retain(|bullet| {
-            let self_damaged =
-                Rect::new(player.pos().x, player.pos().y, 20., 64.).contains(bullet.pos);
-            let direction = bullet.pos.x > (player.pos().x + 10.);
+            for mut bullet in std::mem::take(&mut node.bullets) {
+                let projectile = bullet.projectile;
+                let flying = simulation::fly_bullet(
+                    &projectile,
+                    &mut bullet.pos,
+                    &mut bullet.speed,
+                    &resources.level,
+                    time,
+                );
+                bullet.lived += FixedTimestep::DT;
 
-            if self_damaged {
-                player.kill(direction);
+                // our sword sends the bullet back, everyone else is told about it
+                let parried = parry.map_or(false, |parry| parry.contains(bullet.pos));
+                let spared = simulation::spares(friendly_fire, bullet.team, team);
+                if parried && flying && bullet.deflected == false && spared == false {
+                    bullet.speed = player.state.deflect(bullet.speed);
+                    bullet.lived = 0.;
+                    bullet.team = team;
+                    bullet.deflected = true;
+                    resources.hit_fxses.spawn(bullet.pos);
+                    deflected.push((bullet.pos, bullet.speed, bullet.weapon));
+                    node.bullets.push(bullet);
+                    continue;
+                }
+
+                // the other fish are hit on their own clients, teammates let it fly through
+                let self_hit = spared == false
+                    && player.is_dead() == false
+                    && simulation::fish_hit_box(player.pos()).contains(bullet.pos);
+                let hit = self_hit
+                    || others.iter().any(|(pos, other_team)| {
+                        simulation::spares(friendly_fire, bullet.team, *other_team) == false
+                            && simulation::fish_hit_box(*pos).contains(bullet.pos)
+                    });
+                let expired = bullet.lived >= projectile.lifetime;
+
+                // explosive projectiles only hurt with the blast
+                if self_hit && projectile.explosion_radius == 0. {
+                    player.state.hit_by(&projectile, bullet.pos, bullet.speed);
+                }
+
+                if projectile.explosion_radius != 0. && (hit || expired || !flying) {
+                    resources.explosion_fxses.spawn(bullet.pos);
+
+                    let radius = projectile.explosion_radius;
+                    if spared == false {
+                        player.state.blasted(&projectile, bullet.pos);
+                    }
+                    for tile in resources.level.destructible_in(bullet.pos, radius) {
+                        tile_hits.push((tile, projectile.damage));
+                    }
+                    continue;
+                }
+                if hit || flying == false {
+                    resources.hit_fxses.spawn(bullet.pos);
+                    if let (false, Some(tile)) = (hit, resources.level.tile_index(bullet.pos)) {
+                        tile_hits.push((tile, projectile.damage));
+                    }
+                    continue;
+                }
+                if expired == false {
+                    node.bullets.push(bullet);
+                }
             }
+        }
+        drop(resources);
 
-            if resources.collision_world.solid_at(bullet.pos)
-                || others.any(|other| {
-                    Rect::new(other.pos().x, other.pos().y, 20.0, 64.0).contains(bullet.pos)
-                })
-                || self_damaged
-            {
-                resources.hit_fxses.spawn(bullet.pos);
-                return false;
+        if let Some(mut nakama) = scene::find_node_by_type::<NakamaRealtimeGame>() {
+            for (pos, speed, weapon) in deflected {
+                nakama.deflect(pos, speed, weapon);
             }
-            bullet.lived < bullet.lifetime
-        });
+            nakama.damage_tiles(&tile_hits);
+        }
     }
 }
+
+/// Projectile flying with `speed`, extrapolated `alpha` of a tick ahead to the render time.
+pub fn draw_projectile(pos: Vec2, speed: Vec2, projectile: &Projectile, alpha: f32) {
+    let pos = pos + speed * FixedTimestep::DT * alpha;
+    let Tint { r, g, b } = projectile.color;
+
+    draw_circle(pos.x, pos.y, projectile.radius, Color::new(r, g, b, 1.0));
+}
@@ -3,7 +3,7 @@ use macroquad::{
     prelude::*,
 };
 
-use crate::nodes::Player;
+use crate::nodes::{NakamaRollbackGame, Player};
 
 pub struct Camera {
     bounds: Rect,
@@ -35,12 +35,24 @@ impl Camera {
     pub fn macroquad_camera(&self) -> &Camera2D {
         &self.macroquad_camera
     }
+
+    pub fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = bounds;
+    }
+
+    fn target(&self) -> Option<Vec2> {
+        if let Some(player) = scene::try_get_node::<Player>(self.player) {
+            return Some(player.pos());
+        }
+        // with rollback netcode the local fish lives in the simulation
+        scene::find_node_by_type::<NakamaRollbackGame>().map(|game| game.local_pos())
+    }
 }
 
 impl scene::Node for Camera {
     fn update(mut node: RefMut<Self>) {
-        if let Some(player) = scene::try_get_node::<Player>(node.player) {
-            node.follow_buffer.insert(0, player.pos());
+        if let Some(target) = node.target() {
+            node.follow_buffer.insert(0, target);
             node.follow_buffer.truncate(Self::BUFFER_CAPACITY);
 
             let mut sum = (0.0f64, 0.0f64);
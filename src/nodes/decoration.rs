@@ -6,6 +6,7 @@ use macroquad::{
     },
     prelude::*,
 };
+use macroquad_tiled as tiled;
 
 use crate::Resources;
 
@@ -42,6 +43,16 @@ impl Decoration {
         }
         Decoration { pos, sprite }
     }
+
+    /// Add a node for every object of the `decorations` layer of `map`.
+    pub fn add_all(map: &tiled::Map) {
+        for object in &map.layers["decorations"].objects {
+            scene::add_node(Decoration::new(
+                vec2(object.world_x, object.world_y),
+                object.gid.unwrap(),
+            ));
+        }
+    }
 }
 
 impl scene::Node for Decoration {
@@ -21,5 +21,7 @@ impl scene::Node for Fxses {
         resources.hit_fxses.draw();
         resources.explosion_fxses.draw();
         resources.disarm_fxses.draw();
+        resources.splash_fxses.draw();
+        resources.tile_break_fxses.draw();
     }
 }
@@ -7,7 +7,8 @@ use macroquad::{
 };
 
 use crate::{
-    nodes::{pickup::ItemType, NakamaRealtimeGame, Pickup, Player, RemotePlayer},
+    nodes::{NakamaRealtimeGame, Pickup, Player, RemotePlayer},
+    simulation::{self, Item},
     Resources,
 };
 
@@ -21,8 +22,6 @@ pub struct GlobalEvents {
 }
 
 impl GlobalEvents {
-    const SPAWN_INTERVAL: f32 = 2.0;
-
     pub fn new(player: Handle<Player>, nakama: Handle<NakamaRealtimeGame>) -> GlobalEvents {
         GlobalEvents {
             _player: player,
@@ -32,6 +31,16 @@ impl GlobalEvents {
             spawned_items: vec![],
         }
     }
+
+    /// Spawn a pickup outside of the regular schedule, like a thrown weapon that landed.
+    pub fn add_item(&mut self, pos: Vec2, item: Item, ammo: Option<i32>) {
+        let mut nakama = scene::get_node(self.nakama);
+        let item_id = self.uid;
+        let pickup = nakama.spawn_item(item_id, pos, item, ammo);
+
+        self.spawned_items.push((item_id, pickup));
+        self.uid += 1;
+    }
 }
 
 impl scene::Node for GlobalEvents {
@@ -42,50 +51,25 @@ impl scene::Node for GlobalEvents {
             return;
         }
 
-        if get_time() - node.last_spawn_time >= Self::SPAWN_INTERVAL as _
-            && node.spawned_items.len() < 3
+        if get_time() - node.last_spawn_time >= simulation::PICKUP_SPAWN_INTERVAL as _
+            && node.spawned_items.len() < simulation::MAX_PICKUPS
         {
             let resources = storage::get::<Resources>();
 
-            let tilewidth = resources.tiled_map.raw_tiled_map.tilewidth as f32;
-            let w = resources.tiled_map.raw_tiled_map.width as f32;
-            let tileheight = resources.tiled_map.raw_tiled_map.tileheight as f32;
-            let h = resources.tiled_map.raw_tiled_map.height as f32;
-
-            let pos = loop {
-                let x = rand::gen_range(0, w as i32) as f32;
-                let y = rand::gen_range(0, h as i32 - 6) as f32;
-
-                let pos = vec2((x + 0.5) * tilewidth, (y - 0.5) * tileheight);
-
-                if resources
-                    .collision_world
-                    .collide_solids(pos, tilewidth as _, tileheight as _)
-                    == false
-                    && resources.collision_world.collide_solids(
-                        pos,
-                        tilewidth as _,
-                        tileheight as i32 * 3,
-                    )
-                    && Rect::new(5. * 32., 12. * 32., 8. * 32., 6. * 32.).contains(pos) == false
-                {
-                    break pos;
-                }
-            };
-
             node.last_spawn_time = get_time();
 
-            let item_type = if rand::gen_range(0, 2) == 0 {
-                ItemType::Gun
-            } else {
-                ItemType::Sword
-            };
-            let item_id = node.uid;
-            node.spawned_items
-                .push((item_id, scene::add_node(Pickup::new(pos, item_type))));
-            nakama.spawn_item(item_id, pos, item_type);
-
-            node.uid += 1;
+            let spot = resources.level.pickup_spot(rand::gen_range);
+            let item = spot.and_then(|(_, zone)| {
+                simulation::pickup_item(zone, &resources.weapons, rand::gen_range)
+            });
+
+            if let (Some((pos, _)), Some(item)) = (spot, item) {
+                let item_id = node.uid;
+                let pickup = nakama.spawn_item(item_id, pos, item, None);
+                node.spawned_items.push((item_id, pickup));
+
+                node.uid += 1;
+            }
         }
 
         let mut others = scene::find_nodes_by_type::<RemotePlayer>();
@@ -99,11 +83,7 @@ impl scene::Node for GlobalEvents {
             }
             let item = item.unwrap();
 
-            let collide = |player: Vec2, pickup: Vec2| {
-                (player + vec2(16., 32.)).distance(pickup + vec2(16., 16.)) < 90.
-            };
-
-            let other = others.find(|other| collide(other.pos(), item.pos));
+            let other = others.find(|other| simulation::can_pick(other.pos(), item.pos));
 
             if other.is_some() {
                 item.delete();
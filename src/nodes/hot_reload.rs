@@ -0,0 +1,182 @@
+//! Dev mode reloading of the maps, textures and particle fxses as they change on
+//! disk, without leaving the match.
+
+use std::time::SystemTime;
+
+use macroquad::{
+    experimental::{
+        collections::storage,
+        scene::{self, RefMut},
+    },
+    prelude::*,
+};
+use macroquad_particles::EmittersCache;
+
+use crate::{
+    maps::{self, MapId},
+    nodes::{Camera, Decoration},
+    Resources,
+};
+
+enum Asset {
+    Map(MapId),
+    Texture(Texture2D),
+    // index into `Resources::fxses_mut`
+    Fx(usize),
+}
+
+struct Watched {
+    path: String,
+    asset: Asset,
+    // modification time on the last look, `None` while there is no such file
+    seen: Option<SystemTime>,
+    // modification time of the file in use
+    loaded: Option<SystemTime>,
+}
+
+pub struct HotReload {
+    files: Vec<Watched>,
+    checked_at: f64,
+}
+
+impl HotReload {
+    // seconds between two looks at the files, a file is reloaded once it stayed
+    // the same for that long, not halfway through being saved
+    const INTERVAL: f64 = 0.5;
+
+    pub fn new() -> HotReload {
+        let mut resources = storage::get_mut::<Resources>();
+
+        let maps = resources
+            .maps
+            .maps
+            .iter()
+            .map(|map| (map.path.clone(), Asset::Map(map.id)))
+            .collect::<Vec<_>>();
+        let textures = resources
+            .texture_files
+            .iter()
+            .map(|(path, texture)| (path.clone(), Asset::Texture(*texture)))
+            .collect::<Vec<_>>();
+        let fxses = resources
+            .fxses_mut()
+            .iter()
+            .enumerate()
+            .map(|(n, (path, _, _))| (path.to_string(), Asset::Fx(n)))
+            .collect::<Vec<_>>();
+
+        let files = maps
+            .into_iter()
+            .chain(textures)
+            .chain(fxses)
+            .map(|(path, asset)| {
+                let modified = modified(&path);
+                Watched {
+                    path,
+                    asset,
+                    seen: modified,
+                    loaded: modified,
+                }
+            })
+            .collect();
+
+        HotReload {
+            files,
+            checked_at: get_time(),
+        }
+    }
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|file| file.modified())
+        .ok()
+}
+
+/// Take the map at `path` in if it is still a valid level, moving everyone over
+/// to it when it is the one played on.
+fn reload_map(resources: &mut Resources, id: MapId, path: &str) {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(error) => return warn!("Not reloading {}: {}", path, error),
+    };
+    let errors = maps::validate(&json);
+    if errors.is_empty() == false {
+        for error in errors {
+            warn!("Not reloading {}: {}", path, error);
+        }
+        return;
+    }
+    resources
+        .levels
+        .insert(id, maps::load_level(&json).unwrap());
+    resources.map_jsons.insert(id, json);
+    if resources.map_id != id {
+        return;
+    }
+
+    resources.load_map(id);
+
+    for decoration in scene::find_nodes_by_type::<Decoration>() {
+        decoration.delete();
+    }
+    Decoration::add_all(&resources.tiled_map);
+
+    if let Some(mut camera) = scene::find_node_by_type::<Camera>() {
+        camera.set_bounds(resources.bounds());
+    }
+}
+
+/// Upload the picture at `path` into `texture`, everything drawing with it picks it up.
+fn reload_texture(texture: Texture2D, path: &str) {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(error) => return warn!("Not reloading {}: {}", path, error),
+    };
+    let image = Image::from_file_with_format(&bytes, None);
+    if (image.width(), image.height()) != (texture.width() as usize, texture.height() as usize) {
+        return warn!("Not reloading {}: its size changed, restart the game", path);
+    }
+    texture.update(&image);
+}
+
+/// The particle fx of `path`, or the one built into the game when there is no such file.
+fn reload_fx(fxses: &mut EmittersCache, path: &str, default: &str) {
+    let json = std::fs::read_to_string(path).unwrap_or_else(|_| default.to_string());
+    match nanoserde::DeJson::deserialize_json(&json) {
+        Ok(config) => *fxses = EmittersCache::new(config),
+        Err(error) => warn!("Not reloading {}: {}", path, error),
+    }
+}
+
+impl scene::Node for HotReload {
+    fn update(mut node: RefMut<Self>) {
+        if get_time() - node.checked_at < Self::INTERVAL {
+            return;
+        }
+        node.checked_at = get_time();
+
+        let mut resources = storage::get_mut::<Resources>();
+        for file in &mut node.files {
+            let modified = modified(&file.path);
+            if modified != file.seen {
+                file.seen = modified;
+                continue;
+            }
+            if modified == file.loaded {
+                continue;
+            }
+            file.loaded = modified;
+            info!("Reloading {}", file.path);
+
+            match file.asset {
+                Asset::Map(id) => reload_map(&mut resources, id, &file.path),
+                Asset::Texture(texture) => reload_texture(texture, &file.path),
+                Asset::Fx(n) => {
+                    let (_, default, fxses) = &mut resources.fxses_mut()[n];
+                    reload_fx(fxses, &file.path, default);
+                }
+            }
+        }
+    }
+}
@@ -100,5 +100,24 @@ impl scene::Node for LevelBackground {
         resources
             .tiled_map
             .draw_tiles("main layer", Rect::new(0.0, 0.0, w as _, h as _), None);
+
+        // water volumes have no tiles of their own
+        for water in &resources.level.water {
+            draw_rectangle(
+                water.x,
+                water.y,
+                water.w,
+                water.h,
+                Color::new(0.2, 0.45, 0.8, 0.35),
+            );
+            draw_line(
+                water.x,
+                water.y,
+                water.right(),
+                water.y,
+                2.,
+                Color::new(0.7, 0.85, 1., 0.6),
+            );
+        }
     }
 }
@@ -0,0 +1,138 @@
+//! Moving platforms, crushers and spike traps of the level. They are driven by
+//! the time since the host started the match, so clients agree on them without syncing.
+//! The platforms and crushers are solids of the simulation map, see `Map::solids`:
+//! the fish collide with them and `simulation::Fish::carry` moves the ones riding them.
+
+use macroquad::{
+    experimental::{
+        collections::storage,
+        scene::{self, Handle, RefMut},
+    },
+    miniquad::date,
+    prelude::*,
+};
+
+use crate::{
+    nodes::NakamaRealtimeGame,
+    simulation::{Map, MoverKind},
+    timestep::FixedTimestep,
+    Resources,
+};
+
+pub struct Movers {
+    // ticks since the match started
+    tick: u64,
+    nakama_realtime: Handle<NakamaRealtimeGame>,
+}
+
+impl Movers {
+    pub fn new(nakama_realtime: Handle<NakamaRealtimeGame>) -> Movers {
+        Movers {
+            tick: 0,
+            nakama_realtime,
+        }
+    }
+
+    /// Seconds since the match started, on a tick. The fish, bullets and thrown
+    /// weapons find the movers where they are at this time.
+    pub fn time(&self) -> f32 {
+        self.tick as f32 * FixedTimestep::DT
+    }
+}
+
+/// The movers of `map` where they are `time` seconds into the match.
+pub fn draw_movers(map: &Map, time: f32, tileset: Texture2D) {
+    let tile = vec2(map.tile_width, map.tile_height);
+
+    for mover in &map.movers {
+        let rect = mover.rect_at(time);
+
+        match mover.kind {
+            MoverKind::Platform => draw_blocks(rect, tileset, tile, WHITE),
+            MoverKind::Crusher => {
+                draw_blocks(rect, tileset, tile, Color::new(0.7, 0.6, 0.6, 1.));
+                draw_spikes(
+                    Rect::new(rect.x, rect.bottom(), rect.w, 8.),
+                    false,
+                    LIGHTGRAY,
+                );
+            }
+            MoverKind::SpikeTrap => {
+                // only the tips stick out while it is in
+                let h = if mover.deadly(time) {
+                    rect.h
+                } else {
+                    rect.h / 4.
+                };
+                draw_spikes(
+                    Rect::new(rect.x, rect.bottom() - h, rect.w, h),
+                    true,
+                    LIGHTGRAY,
+                );
+            }
+        }
+    }
+}
+
+/// `rect` filled with the first tile of the tileset.
+fn draw_blocks(rect: Rect, tileset: Texture2D, tile: Vec2, color: Color) {
+    let mut y = rect.y;
+    while y < rect.bottom() {
+        let mut x = rect.x;
+        while x < rect.right() {
+            let size = vec2(tile.x.min(rect.right() - x), tile.y.min(rect.bottom() - y));
+            draw_texture_ex(
+                tileset,
+                x,
+                y,
+                color,
+                DrawTextureParams {
+                    dest_size: Some(size),
+                    source: Some(Rect::new(0., 0., size.x, size.y)),
+                    ..Default::default()
+                },
+            );
+            x += tile.x;
+        }
+        y += tile.y;
+    }
+}
+
+/// A row of spikes across `rect`, pointing up or down.
+fn draw_spikes(rect: Rect, up: bool, color: Color) {
+    const SPIKE_WIDTH: f32 = 8.;
+
+    let (base, tip) = match up {
+        true => (rect.bottom(), rect.y),
+        false => (rect.y, rect.bottom()),
+    };
+    let count = (rect.w / SPIKE_WIDTH).max(1.) as i32;
+    let width = rect.w / count as f32;
+    for n in 0..count {
+        let x = rect.x + n as f32 * width;
+        draw_triangle(
+            vec2(x, base),
+            vec2(x + width / 2., tip),
+            vec2(x + width, base),
+            color,
+        );
+    }
+}
+
+impl scene::Node for Movers {
+    fn update(mut node: RefMut<Self>) {
+        let node = &mut *node;
+        let started_at = scene::get_node(node.nakama_realtime).started_at();
+
+        // back to the start in the lobby, a host clock ahead of ours waits at the start
+        node.tick = started_at.map_or(0, |started_at| {
+            ((date::now() - started_at).max(0.) / FixedTimestep::DT as f64) as u64
+        });
+    }
+
+    fn draw(node: RefMut<Self>) {
+        let resources = storage::get::<Resources>();
+
+        draw_movers(&resources.level, node.time(), resources.tileset);
+    }
+}
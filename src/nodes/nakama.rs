@@ -2,9 +2,12 @@ use macroquad::experimental::scene::{self, RefMut};
 
 use nakama_rs::api_client::ApiClient;
 
+mod desync;
 mod nakama_realtime_game;
+mod nakama_rollback_game;
 
 pub use nakama_realtime_game::NakamaRealtimeGame;
+pub use nakama_rollback_game::NakamaRollbackGame;
 
 /// Persisted singleton node keeping nakama connection
 /// alive across the whole game
@@ -0,0 +1,114 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{simulation::Item, weapons::WeaponId};
+
+/// Pieces of the match every client should see the same way in the
+/// state-broadcast mode.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SharedView {
+    /// Pickup id to its item and position, as sent in `message::SpawnItem`
+    pub pickups: BTreeMap<usize, (Item, u16, u16)>,
+    /// Network id to the dead flag, lives left and the weapon
+    pub players: BTreeMap<String, (bool, u8, Option<WeaponId>)>,
+    /// Destructible tiles broken so far
+    pub broken: BTreeSet<usize>,
+}
+
+impl SharedView {
+    /// FNV-1a over the view, independent of the order things were added in.
+    pub fn checksum(&self) -> u32 {
+        let mut bytes = vec![];
+
+        for (id, (item, x, y)) in &self.pickups {
+            bytes.extend_from_slice(&(*id as u32).to_le_bytes());
+            bytes.push(item.to_u8());
+            bytes.extend_from_slice(&x.to_le_bytes());
+            bytes.extend_from_slice(&y.to_le_bytes());
+        }
+        for (id, (dead, lives, weapon)) in &self.players {
+            bytes.extend_from_slice(id.as_bytes());
+            bytes.push(*dead as u8);
+            bytes.push(*lives);
+            bytes.push(weapon.unwrap_or(0));
+        }
+        for tile in &self.broken {
+            bytes.extend_from_slice(&(*tile as u32).to_le_bytes());
+        }
+
+        bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+            (hash ^ *byte as u32).wrapping_mul(0x0100_0193)
+        })
+    }
+}
+
+/// Remote states arrive with a delay, so a single mismatch may just be a message
+/// in flight. Only a mismatch lasting for a few checks in a row counts as a desync.
+pub struct DesyncDetector {
+    mismatches: BTreeMap<String, usize>,
+}
+
+impl DesyncDetector {
+    pub const CHECK_INTERVAL: f64 = 2.0;
+    const MISMATCHES_IN_A_ROW: usize = 3;
+
+    pub fn new() -> DesyncDetector {
+        DesyncDetector {
+            mismatches: BTreeMap::new(),
+        }
+    }
+
+    /// Returns true once per desync, when the mismatch with the peer got persistent.
+    pub fn check(&mut self, peer: &str, ours: u32, theirs: u32) -> bool {
+        let mismatches = self.mismatches.entry(peer.to_string()).or_insert(0);
+
+        if ours == theirs {
+            *mismatches = 0;
+            return false;
+        }
+        *mismatches += 1;
+        *mismatches == Self::MISMATCHES_IN_A_ROW
+    }
+
+    pub fn forget(&mut self, peer: &str) {
+        self.mismatches.remove(peer);
+    }
+}
+
+#[test]
+fn test_desync_detector() {
+    let mut view = SharedView::default();
+    view.pickups.insert(1, (Item::Weapon(1), 100, 200));
+    view.players.insert("a".to_string(), (false, 3, Some(2)));
+    view.players.insert("b".to_string(), (true, 0, None));
+
+    let mut other = SharedView::default();
+    other.players.insert("b".to_string(), (true, 0, None));
+    other.players.insert("a".to_string(), (false, 3, Some(2)));
+    other.pickups.insert(1, (Item::Weapon(1), 100, 200));
+    assert_eq!(view.checksum(), other.checksum());
+
+    other.broken.insert(42);
+    assert_ne!(view.checksum(), other.checksum());
+    other.broken.clear();
+
+    other.players.insert("a".to_string(), (false, 2, Some(2)));
+    assert_ne!(view.checksum(), other.checksum());
+    other.players.insert("a".to_string(), (false, 3, Some(2)));
+
+    other.players.insert("b".to_string(), (false, 0, None));
+    assert_ne!(view.checksum(), other.checksum());
+
+    let mut detector = DesyncDetector::new();
+    let (ours, theirs) = (view.checksum(), other.checksum());
+
+    assert_eq!(detector.check("b", ours, theirs), false);
+    assert_eq!(detector.check("b", ours, theirs), false);
+    assert_eq!(detector.check("b", ours, theirs), true);
+    // reported only once
+    assert_eq!(detector.check("b", ours, theirs), false);
+
+    assert_eq!(detector.check("b", ours, ours), false);
+    detector.check("b", ours, theirs);
+    detector.check("b", ours, theirs);
+    assert_eq!(detector.check("b", ours, theirs), true);
+}
@@ -1,31 +1,44 @@
 use macroquad::{
+    audio::play_sound_once,
     experimental::{
         collections::storage,
         coroutines::{start_coroutine, wait_seconds},
         scene::{self, Handle, Node, RefMut},
     },
+    miniquad::date,
     prelude::*,
     ui::{self, hash, root_ui, widgets},
 };
 use nanoserde::DeBin;
 use std::collections::{BTreeMap, BTreeSet};
 
-use nakama_rs::api_client::Event;
+use nakama_rs::api_client::{ApiClient, Event};
 
+use super::desync::{DesyncDetector, SharedView};
 use crate::{
     consts,
-    nodes::{pickup::ItemType, Nakama, Pickup, Player, RemotePlayer},
+    maps::{MapId, Rotation},
+    nodes::{
+        objectives, Bullets, Camera, Decoration, GlobalEvents, Movers, Nakama, NakamaRollbackGame,
+        Pickup, Player, RemotePlayer, ThrownWeapons,
+    },
+    simulation::{
+        self, Contender, Flag, Item, Move, Movement, ObjectiveState, PowerUp, Rules, Team,
+        TileDamage, Weapon,
+    },
+    timestep::FixedTimestep,
+    weapons::WeaponId,
     GameType, Resources,
 };
 
 struct NetworkCache {
-    sent_position: [u8; 4],
+    sent_position: [u8; 5],
     last_send_time: f64,
 }
 
 impl NetworkCache {
     fn flush(&mut self) {
-        self.sent_position = [0; 4];
+        self.sent_position = [0; 5];
         self.last_send_time = 0.0;
     }
 }
@@ -40,17 +53,24 @@ bitfield::bitfield! {
     shooting, set_shooting: 21;
     weapon, set_weapon: 30, 22;
     dead, set_dead: 31;
+    movement, set_movement: 33, 32;
+    // `PowerUp::to_u8` + 1, 0 for none
+    power_up, set_power_up: 36, 34;
+    lives, set_lives: 39, 37;
 }
 
 #[test]
 fn test_bitfield() {
-    let mut bits = PlayerStateBits([0; 4]);
+    let mut bits = PlayerStateBits([0; 5]);
 
     bits.set_x(345);
     bits.set_y(567);
     bits.set_facing(true);
     bits.set_shooting(false);
     bits.set_weapon(66);
+    bits.set_movement(Move::Dash.to_u8() as u32);
+    bits.set_power_up(PowerUp::InfiniteAmmo.to_u8() as u32 + 1);
+    bits.set_lives(simulation::MAX_LIVES as u32);
 
     assert_eq!(bits.x(), 345);
     assert_eq!(bits.y(), 567);
@@ -58,14 +78,20 @@ fn test_bitfield() {
     assert_eq!(bits.shooting(), false);
     assert_eq!(bits.weapon(), 66);
     assert_eq!(bits.dead(), false);
-    assert_eq!(std::mem::size_of_val(&bits), 4);
+    assert_eq!(Move::from_u8(bits.movement() as u8), Move::Dash);
+    assert_eq!(
+        PowerUp::from_u8(bits.power_up() as u8 - 1),
+        Some(PowerUp::InfiniteAmmo)
+    );
+    assert_eq!(bits.lives(), simulation::MAX_LIVES as u32);
+    assert_eq!(std::mem::size_of_val(&bits), 5);
 }
 
-mod message {
+pub(super) mod message {
     use nanoserde::{DeBin, SerBin};
 
     #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
-    pub struct State(pub [u8; 4]);
+    pub struct State(pub [u8; 5]);
     impl State {
         pub const OPCODE: i32 = 1;
     }
@@ -74,6 +100,8 @@ mod message {
     pub struct Damage {
         pub target: String,
         pub direction: bool,
+        pub damage: u32,
+        pub knockback: f32,
     }
     impl Damage {
         pub const OPCODE: i32 = 2;
@@ -84,7 +112,10 @@ mod message {
         pub id: u32,
         pub x: u16,
         pub y: u16,
-        pub item_type: u8,
+        /// `Item::to_u8`
+        pub item: u8,
+        /// Ammo left in a thrown weapon, `None` for a full one
+        pub ammo: Option<u8>,
     }
     impl SpawnItem {
         pub const OPCODE: i32 = 4;
@@ -111,10 +142,200 @@ mod message {
     }
 
     #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
-    pub struct StartGame;
+    pub struct StartGame {
+        pub rollback: bool,
+        /// `Movement::to_bits`
+        pub movement: u8,
+        pub lives: u8,
+        pub friendly_fire: bool,
+        /// `GameType::mode`
+        pub mode: u8,
+        /// `MapDef::id`
+        pub map: u8,
+        /// `miniquad::date::now` of the host when it started the match
+        pub started_at: f64,
+    }
     impl StartGame {
         pub const OPCODE: i32 = 8;
     }
+
+    /// Rollback netcode inputs, `Input::to_bits` for consecutive ticks starting from `tick`
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct Inputs {
+        pub tick: u32,
+        pub inputs: Vec<u8>,
+    }
+    impl Inputs {
+        pub const OPCODE: i32 = 9;
+    }
+
+    /// `SharedView::checksum` of the sender
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct Checksum(pub u32);
+    impl Checksum {
+        pub const OPCODE: i32 = 10;
+    }
+
+    /// The host's pickups, replacing whatever the receiver has, every tile broken so far
+    /// and the players as the host sees them
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct Resync {
+        pub pickups: Vec<SpawnItem>,
+        pub broken: Vec<u32>,
+        pub players: Vec<ResyncPlayer>,
+    }
+    impl Resync {
+        pub const OPCODE: i32 = 11;
+    }
+
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct ResyncPlayer {
+        /// Network id
+        pub id: String,
+        pub dead: bool,
+        pub lives: u8,
+        /// `WeaponId`, 0 for none
+        pub weapon: u8,
+    }
+
+    /// The sender let go of its weapon, everyone flies it until it lands
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct Throw {
+        pub x: u16,
+        pub y: u16,
+        pub facing: bool,
+        pub weapon: u8,
+        pub ammo: u8,
+    }
+    impl Throw {
+        pub const OPCODE: i32 = 12;
+    }
+
+    /// A bullet sent back by the sender's sword
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct Deflect {
+        pub x: u16,
+        pub y: u16,
+        pub speed_x: f32,
+        pub speed_y: f32,
+        pub weapon: u8,
+    }
+    impl Deflect {
+        pub const OPCODE: i32 = 13;
+    }
+
+    /// The sender's swing met the one of `target`, both are knocked back
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct Clash {
+        pub target: String,
+        pub x: u16,
+        pub y: u16,
+    }
+    impl Clash {
+        pub const OPCODE: i32 = 14;
+    }
+
+    /// Team the sender picked in the lobby, `Team::to_u8`
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct Team(pub u8);
+    impl Team {
+        pub const OPCODE: i32 = 15;
+    }
+
+    /// `simulation::Flag` of one team: 0 at base, 1 carried by `carrier`, an index
+    /// into the sorted network ids, 2 dropped at `x`, `y`
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct Flag {
+        pub state: u8,
+        pub carrier: u8,
+        pub x: u16,
+        pub y: u16,
+    }
+
+    /// The host's `ObjectiveState` and team scores
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct Objectives {
+        pub flags: Vec<Flag>,
+        pub hill: f32,
+        pub scores: Vec<u32>,
+    }
+    impl Objectives {
+        pub const OPCODE: i32 = 16;
+    }
+
+    /// Destructible tiles the host saw break, indices into `simulation::Map::tiles`
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct TilesBroken(pub Vec<u32>);
+    impl TilesBroken {
+        pub const OPCODE: i32 = 17;
+    }
+
+    /// `StartGame::started_at` of a deathmatch, sent by the host to the players joining it
+    #[derive(Debug, Clone, SerBin, DeBin, PartialEq)]
+    pub struct MatchClock(pub f64);
+    impl MatchClock {
+        pub const OPCODE: i32 = 18;
+    }
+}
+
+fn flag_to_message(flag: Flag) -> message::Flag {
+    let (state, carrier, pos) = match flag {
+        Flag::AtBase => (0, 0, vec2(0., 0.)),
+        Flag::Carried(n) => (1, n as u8, vec2(0., 0.)),
+        Flag::Dropped { pos, .. } => (2, 0, pos),
+    };
+    message::Flag {
+        state,
+        carrier,
+        x: pos.x as _,
+        y: pos.y as _,
+    }
+}
+
+/// The return timer of a dropped flag is only counted by the host.
+fn flag_from_message(flag: &message::Flag) -> Flag {
+    match flag.state {
+        1 => Flag::Carried(flag.carrier as usize),
+        2 => Flag::Dropped {
+            pos: vec2(flag.x as f32, flag.y as f32),
+            timer: 0.,
+        },
+        _ => Flag::AtBase,
+    }
+}
+
+fn team_name(team: Option<Team>) -> &'static str {
+    match team {
+        None => "None",
+        Some(Team::Blue) => "Blue",
+        Some(Team::Green) => "Green",
+    }
+}
+
+/// HUD line of the objective modes: where the flags are or who holds the hill.
+fn objective_status(game_type: GameType, state: &ObjectiveState) -> Option<String> {
+    let flag = |team: Team| match state.flags[team.index()] {
+        Flag::AtBase => "at base",
+        Flag::Carried(_) => "taken",
+        Flag::Dropped { .. } => "dropped",
+    };
+
+    match game_type {
+        GameType::CaptureTheFlag { .. } => Some(format!(
+            "First to {} captures. Blue flag {}, Green flag {}",
+            simulation::CAPTURES_TO_WIN,
+            flag(Team::Blue),
+            flag(Team::Green)
+        )),
+        GameType::KingOfTheHill { .. } => Some(format!(
+            "First to {} points. Hill held by {}",
+            simulation::HILL_POINTS_TO_WIN,
+            state
+                .hill_owner()
+                .map_or("nobody", |team| team_name(Some(team)))
+        )),
+        _ => None,
+    }
 }
 
 /// Node with per-session data, responsible for syncronisation
@@ -122,6 +343,22 @@ mod message {
 pub struct NakamaRealtimeGame {
     pub game_type: GameType,
     pub game_started: bool,
+    // `miniquad::date::now` of the host when the match started, the movers run on it
+    started_at: Option<f64>,
+    /// Movement abilities of the match, picked by the host before the start
+    pub movement: Movement,
+    /// Lives of every fish in `LastFishStanding`, picked by the host before the start
+    pub lives: u8,
+    /// Team of the local player, picked in the lobby
+    pub team: Option<Team>,
+    /// Teammates can hurt each other, picked by the host before the start
+    pub friendly_fire: bool,
+    /// Team scores of the match, counted by the host the same way as `Simulation::scores`
+    pub scores: [u32; 2],
+    /// Flags or hill of the objective modes, run by the host
+    pub objectives: ObjectiveState,
+    /// Level of the match, picked by the host before the start
+    pub map: MapId,
 
     network_id: String,
     network_cache: NetworkCache,
@@ -130,6 +367,23 @@ pub struct NakamaRealtimeGame {
     network_ids: BTreeSet<String>,
     shoot_pending: bool,
     ready: bool,
+    // exchange inputs instead of the player states, picked by the host before the start
+    rollback: bool,
+    rollback_game: Option<Handle<NakamaRollbackGame>>,
+    desync_detector: DesyncDetector,
+    last_checksum_time: f64,
+    // host sends its pickups and asks everyone to repeat their state on a desync
+    resync: bool,
+    objective_timestep: FixedTimestep,
+    sent_objectives: Option<message::Objectives>,
+    last_objectives_time: f64,
+    // switched to once no `RemotePlayer` is borrowed anymore
+    pending_map: Option<MapId>,
+    // damage dealt to the destructible tiles, only counted by the host
+    tile_damage: TileDamage,
+    // tiles broken on the current map, and the ones to break on the next update
+    broken: BTreeSet<usize>,
+    pending_tiles: Vec<usize>,
     nakama: Handle<Nakama>,
 }
 
@@ -139,10 +393,20 @@ impl NakamaRealtimeGame {
         game_type: GameType,
         network_id: String,
     ) -> NakamaRealtimeGame {
+        // quick games all play on the first map, nothing tells the clients which one it
+        // is, the lobby proposes the next one
+        let map = {
+            let resources = storage::get::<Resources>();
+            match game_type {
+                GameType::Deathmatch => resources.maps.first().id,
+                _ => resources.maps.next(storage::get::<Rotation>().last, 1).id,
+            }
+        };
+
         NakamaRealtimeGame {
             game_type,
             network_cache: NetworkCache {
-                sent_position: [0; 4],
+                sent_position: [0; 5],
                 last_send_time: 0.0,
             },
             remote_players: BTreeMap::new(),
@@ -155,7 +419,35 @@ impl NakamaRealtimeGame {
             pickups: BTreeMap::new(),
             shoot_pending: false,
             ready: false,
+            rollback: false,
+            rollback_game: None,
+            desync_detector: DesyncDetector::new(),
+            last_checksum_time: 0.0,
+            resync: true,
             game_started: game_type == GameType::Deathmatch,
+            // a deathmatch runs on its own clock until the host sends theirs
+            started_at: match game_type {
+                GameType::Deathmatch => Some(date::now()),
+                _ => None,
+            },
+            movement: Movement::ALL,
+            lives: 1,
+            team: None,
+            friendly_fire: false,
+            scores: [0; 2],
+            objectives: ObjectiveState::default(),
+            objective_timestep: FixedTimestep::new(),
+            sent_objectives: None,
+            last_objectives_time: 0.0,
+            map,
+            // still on the map of the last lobby match maybe
+            pending_map: match game_type {
+                GameType::Deathmatch => Some(map),
+                _ => None,
+            },
+            tile_damage: TileDamage::default(),
+            broken: BTreeSet::new(),
+            pending_tiles: vec![],
             nakama,
         }
     }
@@ -164,11 +456,25 @@ impl NakamaRealtimeGame {
         self.game_started
     }
 
+    /// `miniquad::date::now` of the host when the match started, the same on every client.
+    pub fn started_at(&self) -> Option<f64> {
+        self.started_at
+    }
+
     pub fn shoot(&mut self) {
         self.shoot_pending = true;
     }
 
-    pub fn spawn_item(&mut self, id: usize, pos: Vec2, item_type: ItemType) {
+    pub fn spawn_item(
+        &mut self,
+        id: usize,
+        pos: Vec2,
+        item: Item,
+        ammo: Option<i32>,
+    ) -> Handle<Pickup> {
+        let pickup = scene::add_node(Pickup::new(pos, item, ammo));
+        self.pickups.insert(id, pickup);
+
         let mut nakama = scene::get_node(self.nakama);
         nakama.api_client.socket_send(
             message::SpawnItem::OPCODE,
@@ -176,12 +482,35 @@ impl NakamaRealtimeGame {
                 id: id as _,
                 x: pos.x as _,
                 y: pos.y as _,
-                item_type: item_type as _,
+                item: item.to_u8(),
+                ammo: ammo.map(|ammo| ammo.max(0) as u8),
+            },
+        );
+        pickup
+    }
+
+    /// Throw the local player's weapon, `pos` and `facing` are of the fish.
+    pub fn throw(&mut self, pos: Vec2, facing: bool, weapon: Weapon) {
+        if let Some(mut thrown) = scene::find_node_by_type::<ThrownWeapons>() {
+            thrown.throw(pos, facing, weapon, None, self.team);
+        }
+
+        let mut nakama = scene::get_node(self.nakama);
+        nakama.api_client.socket_send(
+            message::Throw::OPCODE,
+            &message::Throw {
+                x: pos.x as _,
+                y: pos.y as _,
+                facing,
+                weapon: weapon.id,
+                ammo: weapon.ammo.max(0) as u8,
             },
         );
     }
 
     pub fn delete_item(&mut self, id: usize) {
+        self.pickups.remove(&id);
+
         let mut nakama = scene::get_node(self.nakama);
         nakama.api_client.socket_send(
             message::DeleteItem::OPCODE,
@@ -189,17 +518,258 @@ impl NakamaRealtimeGame {
         );
     }
 
-    pub fn kill(&mut self, target: &str, direction: bool) {
+    pub fn damage(&mut self, target: &str, direction: bool, damage: i32, knockback: f32) {
         let mut nakama = scene::get_node(self.nakama);
         nakama.api_client.socket_send(
             message::Damage::OPCODE,
             &message::Damage {
                 target: target.to_string(),
                 direction,
+                damage: damage.max(0) as u32,
+                knockback,
+            },
+        );
+    }
+
+    pub fn deflect(&mut self, pos: Vec2, speed: Vec2, weapon: WeaponId) {
+        let mut nakama = scene::get_node(self.nakama);
+        nakama.api_client.socket_send(
+            message::Deflect::OPCODE,
+            &message::Deflect {
+                x: pos.x as _,
+                y: pos.y as _,
+                speed_x: speed.x,
+                speed_y: speed.y,
+                weapon,
             },
         );
     }
 
+    /// Weapons hit destructible tiles for `(tile, damage)`. Only the host counts the
+    /// damage, it tells everyone which tiles broke.
+    pub fn damage_tiles(&mut self, hits: &[(usize, i32)]) {
+        if hits.is_empty() || self.is_host() == false {
+            return;
+        }
+
+        let mut broken = vec![];
+        {
+            let resources = storage::get::<Resources>();
+            for (tile, damage) in hits {
+                let durability = match resources.level.durability.get(tile) {
+                    Some(durability) => *durability,
+                    None => continue,
+                };
+                if broken.contains(tile) || self.pending_tiles.contains(tile) {
+                    continue;
+                }
+                if self.tile_damage.deal(*tile, *damage, durability) {
+                    broken.push(*tile);
+                }
+            }
+        }
+        if broken.is_empty() {
+            return;
+        }
+
+        let mut nakama = scene::get_node(self.nakama);
+        nakama.api_client.socket_send(
+            message::TilesBroken::OPCODE,
+            &message::TilesBroken(broken.iter().map(|tile| *tile as u32).collect()),
+        );
+        self.pending_tiles.extend(broken);
+    }
+
+    /// Take the tiles broken since the last update out of the map.
+    fn break_tiles(&mut self) {
+        let mut resources = storage::get_mut::<Resources>();
+        let len = resources.level.tiles.len();
+        let tiles = std::mem::take(&mut self.pending_tiles)
+            .into_iter()
+            .filter(|tile| *tile < len && self.broken.insert(*tile))
+            .collect::<Vec<_>>();
+        if tiles.is_empty() {
+            return;
+        }
+
+        for tile in &tiles {
+            let pos = resources.level.tile_center(*tile);
+            resources.tile_break_fxses.spawn(pos);
+        }
+        resources.break_tiles(&tiles);
+    }
+
+    /// Our swing met the one of `target`, `pos` is where the swords met.
+    pub fn clash(&mut self, target: &str, pos: Vec2) {
+        let mut resources = storage::get_mut::<Resources>();
+        resources.hit_fxses.spawn(pos);
+
+        let mut nakama = scene::get_node(self.nakama);
+        nakama.api_client.socket_send(
+            message::Clash::OPCODE,
+            &message::Clash {
+                target: target.to_string(),
+                x: pos.x as _,
+                y: pos.y as _,
+            },
+        );
+    }
+
+    /// Someone picked a team in the lobby.
+    fn teams(&self) -> bool {
+        self.team.is_some()
+            || self.remote_players.values().any(|player| {
+                scene::try_get_node(*player).map_or(false, |player| player.team().is_some())
+            })
+    }
+
+    /// Place of the local player in the sorted network ids.
+    pub fn local_index(&self) -> usize {
+        self.network_ids
+            .iter()
+            .position(|id| *id == self.network_id)
+            .unwrap_or(0)
+    }
+
+    /// Everyone starts the match on a spawn of their own, all the clients agree on
+    /// who goes where without a message.
+    fn take_start_spawns(&mut self) {
+        let seed = simulation::match_seed(&self.network_ids);
+        let resources = storage::get::<Resources>();
+        let spawns = simulation::start_spawns(&resources.level, self.network_ids.len(), seed);
+
+        let player = scene::find_node_by_type::<Player>();
+        if let (Some(mut player), Some(pos)) = (player, spawns.get(self.local_index())) {
+            player.spawn_at(*pos);
+        }
+    }
+
+    /// Everyone in the match, in the `network_ids` order `Flag::Carried` refers to.
+    fn contenders(&self) -> Vec<Contender> {
+        let missing = Contender {
+            pos: vec2(0., 0.),
+            team: None,
+            alive: false,
+        };
+
+        self.network_ids
+            .iter()
+            .map(|id| match self.remote_players.get(id) {
+                Some(player) => scene::try_get_node(*player).map(|player| Contender {
+                    pos: player.pos(),
+                    team: player.team(),
+                    alive: player.dead == false,
+                }),
+                None => scene::find_node_by_type::<Player>().map(|player| Contender {
+                    pos: player.pos(),
+                    team: player.team(),
+                    alive: player.is_dead() == false,
+                }),
+            })
+            .map(|contender| contender.unwrap_or(missing))
+            .collect()
+    }
+
+    /// Next mode after the picked one the picked map has the objects for.
+    fn next_mode(&self) -> GameType {
+        let level = &storage::get::<Resources>().levels[&self.map];
+        let modes = GameType::MODES.len() as u8;
+
+        (1..=modes)
+            .map(|n| {
+                self.game_type
+                    .with_mode((self.game_type.mode() + n) % modes)
+            })
+            .find(|game_type| level.supports(*game_type))
+            .unwrap()
+    }
+
+    /// Next map with room for everyone, back to the first mode if it can't host the picked one.
+    fn next_map(&mut self) {
+        let resources = storage::get::<Resources>();
+
+        self.map = resources
+            .maps
+            .next(Some(self.map), self.network_ids.len())
+            .id;
+        if resources.levels[&self.map].supports(self.game_type) == false {
+            self.game_type = self.game_type.with_mode(0);
+        }
+    }
+
+    /// Move the match over to `map`, with every node standing on the previous one.
+    fn load_map(&mut self, map: MapId) {
+        storage::get_mut::<Rotation>().last = Some(map);
+
+        let mut resources = storage::get_mut::<Resources>();
+        // the same map with holes in it is loaded again
+        if resources.map_id == map && self.broken.is_empty() {
+            return;
+        }
+        resources.load_map(map);
+        self.tile_damage.clear();
+        self.broken.clear();
+        self.pending_tiles.clear();
+
+        for decoration in scene::find_nodes_by_type::<Decoration>() {
+            decoration.delete();
+        }
+        Decoration::add_all(&resources.tiled_map);
+
+        if let Some(mut camera) = scene::find_node_by_type::<Camera>() {
+            camera.set_bounds(resources.bounds());
+        }
+        if let Some(mut player) = scene::find_node_by_type::<Player>() {
+            player.enter_map(&resources);
+        }
+    }
+
+    /// Step the objectives on the host and send them out with the team scores if they changed.
+    fn update_objectives(&mut self, api_client: &mut ApiClient) {
+        let ticks = self.objective_timestep.advance(get_frame_time());
+        if self.is_host() == false {
+            return;
+        }
+
+        let contenders = self.contenders();
+        let mut events = vec![];
+        {
+            let resources = storage::get::<Resources>();
+            for _ in 0..ticks {
+                self.objectives.update(
+                    self.game_type,
+                    &resources.level,
+                    &contenders,
+                    &mut self.scores,
+                    &mut events,
+                );
+            }
+            if events.is_empty() == false {
+                play_sound_once(resources.pickup_sound);
+            }
+        }
+
+        let message = message::Objectives {
+            flags: self
+                .objectives
+                .flags
+                .iter()
+                .copied()
+                .map(flag_to_message)
+                .collect(),
+            hill: self.objectives.hill,
+            scores: self.scores.to_vec(),
+        };
+        let network_frame =
+            get_time() - self.last_objectives_time > (1. / consts::NETWORK_FPS) as f64;
+
+        if network_frame && self.sent_objectives.as_ref() != Some(&message) {
+            self.last_objectives_time = get_time();
+            api_client.socket_send(message::Objectives::OPCODE, &message);
+            self.sent_objectives = Some(message);
+        }
+    }
+
     pub fn is_host(&self) -> bool {
         // no other players connected
         if self.remote_players.len() == 0 {
@@ -208,6 +778,174 @@ impl NakamaRealtimeGame {
 
         self.network_id < *self.remote_players.keys().nth(0).unwrap()
     }
+
+    fn shared_view(&self) -> SharedView {
+        let mut view = SharedView::default();
+
+        for (id, pickup) in &self.pickups {
+            if let Some(pickup) = scene::try_get_node(*pickup) {
+                view.pickups
+                    .insert(*id, (pickup.item, pickup.pos.x as u16, pickup.pos.y as u16));
+            }
+        }
+        if let Some(player) = scene::find_node_by_type::<Player>() {
+            view.players.insert(
+                self.network_id.clone(),
+                (
+                    player.is_dead(),
+                    player.lives().unwrap_or(0),
+                    player.weapon(),
+                ),
+            );
+        }
+        for (id, player) in &self.remote_players {
+            if let Some(player) = scene::try_get_node(*player) {
+                view.players
+                    .insert(id.clone(), (player.dead, player.lives, player.weapon()));
+            }
+        }
+        view.broken = self.broken.clone();
+        view
+    }
+
+    /// Bring a remote player to the dead flag, lives and weapon its owner or the host sent.
+    fn sync_player(&mut self, other: &mut RemotePlayer, dead: bool, lives: u8, weapon: WeaponId) {
+        if dead && other.dead != dead {
+            let mut resources = storage::get_mut::<Resources>();
+            resources
+                .explosion_fxses
+                .spawn(other.pos() + vec2(15., 33.));
+
+            // the host counts the deaths of the other modes, clients take its scores
+            let counted = self.is_host() && self.game_type.is_objective() == false;
+            if let (Some(team), true) = (other.team(), counted) {
+                self.scores[team.other().index()] += 1;
+            }
+        }
+        other.set_dead(dead);
+        other.lives = lives;
+
+        if other.weapon().is_some() && weapon == 0 {
+            let mut resources = storage::get_mut::<Resources>();
+            resources.disarm_fxses.spawn(other.pos() + vec2(16., 33.));
+            other.disarm();
+        }
+        if weapon != 0 && other.weapon() != Some(weapon) {
+            other.pick_weapon(weapon);
+        }
+    }
+
+    fn check_desync(&mut self, api_client: &mut ApiClient, peer: &str, theirs: u32) {
+        let view = self.shared_view();
+        let ours = view.checksum();
+
+        if self.desync_detector.check(peer, ours, theirs) == false {
+            return;
+        }
+        warn!(
+            "Desync with {}: our checksum {:08x}, theirs {:08x}, our view: {:#?}",
+            peer, ours, theirs, view
+        );
+
+        if self.is_host() && self.resync {
+            let pickups = view
+                .pickups
+                .iter()
+                .map(|(id, (item, x, y))| message::SpawnItem {
+                    id: *id as _,
+                    x: *x,
+                    y: *y,
+                    item: item.to_u8(),
+                    ammo: self
+                        .pickups
+                        .get(id)
+                        .and_then(|pickup| scene::try_get_node(*pickup))
+                        .and_then(|pickup| pickup.ammo)
+                        .map(|ammo| ammo.max(0) as u8),
+                })
+                .collect();
+            let broken = view.broken.iter().map(|tile| *tile as u32).collect();
+            let players = view
+                .players
+                .iter()
+                .map(|(id, (dead, lives, weapon))| message::ResyncPlayer {
+                    id: id.clone(),
+                    dead: *dead,
+                    lives: *lives,
+                    weapon: weapon.unwrap_or(0),
+                })
+                .collect();
+
+            api_client.socket_send(
+                message::Resync::OPCODE,
+                &message::Resync {
+                    pickups,
+                    broken,
+                    players,
+                },
+            );
+            self.network_cache.flush();
+        }
+    }
+
+    /// Replace the state-broadcasting nodes with a rollback simulation of everyone.
+    fn start_rollback(&mut self) {
+        for node in scene::find_nodes_by_type::<Player>() {
+            node.delete();
+        }
+        for node in scene::find_nodes_by_type::<Bullets>() {
+            node.delete();
+        }
+        for node in scene::find_nodes_by_type::<ThrownWeapons>() {
+            node.delete();
+        }
+        for node in scene::find_nodes_by_type::<GlobalEvents>() {
+            node.delete();
+        }
+        // the simulation has movers of its own
+        for node in scene::find_nodes_by_type::<Movers>() {
+            node.delete();
+        }
+
+        let teams = self
+            .network_ids
+            .iter()
+            .map(|id| match self.remote_players.get(id) {
+                Some(player) => scene::get_node(*player).team(),
+                None => self.team,
+            })
+            .collect();
+        let rules = Rules {
+            movement: self.movement,
+            lives: self.lives,
+            teams,
+            friendly_fire: self.friendly_fire,
+        };
+
+        let players = self
+            .network_ids
+            .iter()
+            .map(|id| {
+                let username = match self.remote_players.get(id) {
+                    Some(player) => {
+                        let mut player = scene::get_node(*player);
+                        player.visible = false;
+                        player.username.clone()
+                    }
+                    None => String::new(),
+                };
+                (id.clone(), username)
+            })
+            .collect();
+
+        self.rollback_game = Some(scene::add_node(NakamaRollbackGame::new(
+            Some(self.nakama),
+            self.game_type,
+            &rules,
+            players,
+            &self.network_id,
+        )));
+    }
 }
 
 impl Node for NakamaRealtimeGame {
@@ -233,6 +971,35 @@ impl Node for NakamaRealtimeGame {
             root_ui().label(None, "You are the host");
         }
 
+        // the rollback game draws the ones of its simulation
+        if node.game_started && node.rollback_game.is_none() {
+            let positions = node
+                .contenders()
+                .iter()
+                .map(|contender| contender.pos)
+                .collect::<Vec<_>>();
+            objectives::draw_objectives(
+                &node.objectives,
+                node.game_type,
+                &storage::get::<Resources>().level,
+                &positions,
+            );
+        }
+
+        if node.game_started && node.teams() {
+            let (scores, objectives) = match node.rollback_game {
+                Some(game) => {
+                    let game = scene::get_node(game);
+                    (game.scores(), game.objectives())
+                }
+                None => (node.scores, node.objectives.clone()),
+            };
+            root_ui().label(None, &format!("Blue {} : {} Green", scores[0], scores[1]));
+            if let Some(status) = objective_status(node.game_type, &objectives) {
+                root_ui().label(None, &status);
+            }
+        }
+
         if node.game_type != GameType::Deathmatch && node.game_started == false {
             let resources = storage::get::<crate::gui::GuiResources>();
             let nakama = &mut scene::get_node(node.nakama).api_client;
@@ -242,11 +1009,11 @@ impl Node for NakamaRealtimeGame {
                 hash!(),
                 Vec2::new(
                     screen_width() / 2. - 500. / 2.,
-                    screen_height() / 2. - 200. / 2.,
+                    screen_height() / 2. - 400. / 2.,
                 ),
-                Vec2::new(500., 200.),
+                Vec2::new(500., 400.),
                 |ui| {
-                    if let GameType::LastFishStanding { private: true } = node.game_type {
+                    if node.game_type.is_private() {
                         let mut match_id = nakama.match_id().unwrap_or("".to_string());
 
                         widgets::InputText::new(hash!())
@@ -256,7 +1023,10 @@ impl Node for NakamaRealtimeGame {
                     }
                     for player in node.remote_players.values() {
                         let player = scene::get_node(*player);
-                        ui.label(None, &format!("{}: ", player.username));
+                        ui.label(
+                            None,
+                            &format!("{} ({}): ", player.username, team_name(player.team())),
+                        );
                         ui.same_line(300.0);
                         if player.ready {
                             ui.label(None, "Ready");
@@ -284,10 +1054,86 @@ impl Node for NakamaRealtimeGame {
                         nakama.socket_send(message::Ready::OPCODE, &message::Ready);
                     }
 
+                    let label = format!("Team: {}", team_name(node.team));
+                    if ui.button(vec2(300.0, 100.0), label.as_str()) {
+                        node.team = match node.team {
+                            None => Some(Team::Blue),
+                            Some(Team::Blue) => Some(Team::Green),
+                            Some(Team::Green) => None,
+                        };
+                        nakama.socket_send(
+                            message::Team::OPCODE,
+                            &message::Team(Team::to_u8(node.team)),
+                        );
+                    }
+
                     if node.is_host() && everyone_ready {
-                        if ui.button(vec2(150.0, 100.0), "Start match!") {
+                        ui.checkbox(hash!(), "Rollback netcode", &mut node.rollback);
+                        ui.checkbox(hash!(), "Resync on desync", &mut node.resync);
+                        ui.checkbox(hash!(), "Double jump", &mut node.movement.double_jump);
+                        ui.checkbox(hash!(), "Wall jump", &mut node.movement.wall_jump);
+                        ui.checkbox(hash!(), "Dash", &mut node.movement.dash);
+                        ui.checkbox(hash!(), "Friendly fire", &mut node.friendly_fire);
+
+                        let label =
+                            format!("Mode: {}", GameType::MODES[node.game_type.mode() as usize]);
+                        if ui.button(None, label.as_str()) {
+                            node.game_type = node.next_mode();
+                        }
+
+                        let (label, thumbnail) = {
+                            let resources = storage::get::<Resources>();
+                            let map = resources.maps.get(node.map).unwrap();
+                            (
+                                format!("Map: {} ({} players)", map.name, map.players),
+                                resources.map_thumbnails[&node.map],
+                            )
+                        };
+                        if ui.button(None, label.as_str()) {
+                            node.next_map();
+                        }
+                        widgets::Texture::new(thumbnail)
+                            .position(vec2(340., 150.))
+                            .size(108., 84.)
+                            .ui(ui);
+
+                        if node.game_type.has_lives() {
+                            ui.label(None, &format!("Lives: {}", node.lives));
+                            ui.same_line(120.0);
+                            if ui.button(None, "-") && node.lives > 1 {
+                                node.lives -= 1;
+                            }
+                            ui.same_line(150.0);
+                            if ui.button(None, "+") && node.lives < simulation::MAX_LIVES {
+                                node.lives += 1;
+                            }
+                        }
+
+                        // the objectives are played team against team
+                        let teams_picked = node.team.is_some()
+                            && node
+                                .remote_players
+                                .values()
+                                .all(|player| scene::get_node(*player).team().is_some());
+                        if node.game_type.is_objective() && teams_picked == false {
+                            ui.label(vec2(20.0, 300.0), "Everyone needs a team for this mode");
+                        } else if ui.button(vec2(150.0, 300.0), "Start match!") {
+                            let started_at = date::now();
                             node.game_started = true;
-                            nakama.socket_send(message::StartGame::OPCODE, &message::StartGame);
+                            node.started_at = Some(started_at);
+                            node.pending_map = Some(node.map);
+                            nakama.socket_send(
+                                message::StartGame::OPCODE,
+                                &message::StartGame {
+                                    rollback: node.rollback,
+                                    movement: node.movement.to_bits(),
+                                    lives: node.lives,
+                                    friendly_fire: node.friendly_fire,
+                                    mode: node.game_type.mode(),
+                                    map: node.map,
+                                    started_at,
+                                },
+                            );
                         }
                     } else if node.ready {
                         ui.label(
@@ -304,30 +1150,44 @@ impl Node for NakamaRealtimeGame {
     fn update(mut node: RefMut<Self>) {
         let api_client = &mut scene::get_node(node.nakama).api_client;
 
-        {
+        if let Some(map) = node.pending_map.take() {
+            node.load_map(map);
+            // a quick game is joined midway, not started together
+            if node.game_type != GameType::Deathmatch {
+                node.take_start_spawns();
+            }
+        }
+        node.break_tiles();
+
+        if node.game_started && node.rollback && node.rollback_game.is_none() {
+            node.start_rollback();
+        }
+
+        // no `Player` in rollback mode, the inputs are sent by `NakamaRollbackGame`
+        if let Some(player) = scene::find_node_by_type::<Player>() {
             let shooting = node.shoot_pending;
             node.shoot_pending = false;
             let network_frame =
                 get_time() - node.network_cache.last_send_time > (1. / consts::NETWORK_FPS) as f64;
 
             if shooting || network_frame {
-                let player = scene::find_node_by_type::<Player>().unwrap();
-
                 node.network_cache.last_send_time = get_time();
 
-                let mut state = PlayerStateBits([0; 4]);
+                let mut state = PlayerStateBits([0; 5]);
 
                 state.set_x(player.pos().x as u32);
                 state.set_y(player.pos().y as u32);
                 state.set_facing(player.facing());
                 state.set_shooting(shooting);
-                let weapon = match player.weapon() {
-                    None => 0,
-                    Some(ItemType::Gun) => 1,
-                    Some(ItemType::Sword) => 2,
-                };
-                state.set_weapon(weapon);
+                state.set_weapon(player.weapon().map_or(0, |weapon| weapon as u32));
                 state.set_dead(player.is_dead());
+                state.set_movement(player.current_move().to_u8() as u32);
+                state.set_power_up(
+                    player
+                        .power_up()
+                        .map_or(0, |power_up| power_up.to_u8() as u32 + 1),
+                );
+                state.set_lives(player.lives().unwrap_or(0) as u32);
 
                 if node.network_cache.sent_position != state.0 {
                     node.network_cache.sent_position = state.0;
@@ -337,11 +1197,30 @@ impl Node for NakamaRealtimeGame {
             }
         }
 
+        if node.game_started && node.rollback_game.is_none() {
+            node.update_objectives(api_client);
+        }
+
+        if node.game_started
+            && node.rollback_game.is_none()
+            && get_time() - node.last_checksum_time > DesyncDetector::CHECK_INTERVAL
+        {
+            node.last_checksum_time = get_time();
+
+            let checksum = node.shared_view().checksum();
+            api_client.socket_send(message::Checksum::OPCODE, &message::Checksum(checksum));
+        }
+
+        // compared after all the messages are handled, not while a `RemotePlayer` is borrowed
+        let mut checksums = vec![];
+
         while let Some(event) = api_client.try_recv() {
             match event {
                 Event::Presence { joins, leaves } => {
                     for leaver in leaves {
                         let leaver = leaver.session_id;
+                        // the flags carried by index follow the fish after the leaver
+                        let index = node.network_ids.iter().position(|id| *id == leaver);
                         if let Some(leaver) = node.remote_players.remove(&leaver) {
                             let mut resources = storage::get_mut::<Resources>();
 
@@ -349,9 +1228,16 @@ impl Node for NakamaRealtimeGame {
                             resources
                                 .explosion_fxses
                                 .spawn(leaver.pos() + vec2(15., 33.));
+                            if let Some(n) = index {
+                                node.objectives.fish_left(n, leaver.pos());
+                            }
 
                             leaver.delete();
                         }
+                        if let Some(game) = node.rollback_game {
+                            scene::get_node(game).disconnect(&leaver);
+                        }
+                        node.desync_detector.forget(&leaver);
                         node.network_ids.remove(&leaver);
                     }
 
@@ -365,6 +1251,21 @@ impl Node for NakamaRealtimeGame {
 
                         node.network_cache.flush();
                         node.network_ids.insert(joined.clone());
+                        // checked before the joiner counts, they may be the next host
+                        if let (GameType::Deathmatch, Some(started_at), true) =
+                            (node.game_type, node.started_at, node.is_host())
+                        {
+                            api_client.socket_send(
+                                message::MatchClock::OPCODE,
+                                &message::MatchClock(started_at),
+                            );
+                        }
+                        if node.team.is_some() {
+                            api_client.socket_send(
+                                message::Team::OPCODE,
+                                &message::Team(Team::to_u8(node.team)),
+                            );
+                        }
                         if node.remote_players.contains_key(&joined) == false {
                             node.remote_players.insert(
                                 joined.clone(),
@@ -390,45 +1291,93 @@ impl Node for NakamaRealtimeGame {
                                 other.set_pos(pos);
                                 other.set_facing(state.facing());
 
-                                if state.dead() && other.dead != state.dead() {
-                                    let mut resources = storage::get_mut::<Resources>();
-                                    resources
-                                        .explosion_fxses
-                                        .spawn(other.pos() + vec2(15., 33.));
-                                }
-                                other.set_dead(state.dead());
-
-                                if other.weapon().is_some() && state.weapon() == 0 {
-                                    let mut resources = storage::get_mut::<Resources>();
-                                    resources.disarm_fxses.spawn(pos + vec2(16., 33.));
-                                    other.disarm();
-                                }
-                                if other.weapon().map_or(0, |weapon| weapon as u32)
-                                    != state.weapon()
-                                {
-                                    match state.weapon() {
-                                        1 => other.pick_weapon(ItemType::Gun),
-                                        2 => other.pick_weapon(ItemType::Sword),
-                                        _ => unreachable!(),
-                                    }
-                                }
+                                node.sync_player(
+                                    &mut other,
+                                    state.dead(),
+                                    state.lives() as u8,
+                                    state.weapon() as WeaponId,
+                                );
+                                other.set_move(Move::from_u8(state.movement() as u8));
+                                other.set_power_up(
+                                    (state.power_up() as u8)
+                                        .checked_sub(1)
+                                        .and_then(PowerUp::from_u8),
+                                );
                                 if state.shooting() {
-                                    let handle = other.handle();
-                                    other.shoot(handle);
+                                    other.shoot();
                                 }
                             }
                             message::Ready::OPCODE => {
                                 other.ready = true;
                             }
+                            message::Team::OPCODE => {
+                                let message::Team(team) = DeBin::deserialize_bin(&data).unwrap();
+                                other.set_team(Team::from_u8(team));
+                            }
+                            message::MatchClock::OPCODE => {
+                                let message::MatchClock(started_at) =
+                                    DeBin::deserialize_bin(&data).unwrap();
+                                node.started_at = Some(started_at);
+                            }
                             message::StartGame::OPCODE => {
+                                let message::StartGame {
+                                    rollback,
+                                    movement,
+                                    lives,
+                                    friendly_fire,
+                                    mode,
+                                    map,
+                                    started_at,
+                                } = DeBin::deserialize_bin(&data).unwrap();
                                 node.game_started = true;
+                                node.started_at = Some(started_at);
+                                node.game_type = node.game_type.with_mode(mode);
+                                // a host with maps we don't have, play on ours anyway
+                                if storage::get::<Resources>().levels.contains_key(&map) {
+                                    node.map = map;
+                                } else {
+                                    warn!("Unknown map {}, staying on map {}", map, node.map);
+                                }
+                                node.pending_map = Some(node.map);
+                                node.rollback = rollback;
+                                node.movement = Movement::from_bits(movement);
+                                node.lives = lives;
+                                node.friendly_fire = friendly_fire;
                             }
-                            message::Damage::OPCODE => {
-                                let message::Damage { target, direction } =
+                            message::Inputs::OPCODE => {
+                                let message::Inputs { tick, inputs } =
                                     DeBin::deserialize_bin(&data).unwrap();
+                                if let Some(game) = node.rollback_game {
+                                    scene::get_node(game).remote_inputs(&user_id, tick, &inputs);
+                                }
+                            }
+                            message::Damage::OPCODE => {
+                                let message::Damage {
+                                    target,
+                                    direction,
+                                    damage,
+                                    knockback,
+                                } = DeBin::deserialize_bin(&data).unwrap();
                                 if target == node.network_id {
                                     let mut player = scene::find_node_by_type::<Player>().unwrap();
-                                    player.kill(direction);
+
+                                    // we were swinging too, but they didn't know yet
+                                    if player.parry_box().is_some() {
+                                        let pos =
+                                            (player.pos() + other.pos()) / 2. + vec2(10., 32.);
+                                        storage::get_mut::<Resources>().hit_fxses.spawn(pos);
+                                        api_client.socket_send(
+                                            message::Clash::OPCODE,
+                                            &message::Clash {
+                                                target: user_id.clone(),
+                                                x: pos.x as _,
+                                                y: pos.y as _,
+                                            },
+                                        );
+                                        player.clash();
+                                    } else {
+                                        player.state.hurt(damage as i32, knockback, direction);
+                                    }
                                 }
                             }
                             message::SpawnItem::OPCODE => {
@@ -436,24 +1385,71 @@ impl Node for NakamaRealtimeGame {
                                     id,
                                     x,
                                     y,
-                                    item_type,
+                                    item,
+                                    ammo,
                                 } = DeBin::deserialize_bin(&data).unwrap();
                                 let pos = vec2(x as f32, y as f32);
+                                let ammo = ammo.map(|ammo| ammo as i32);
 
-                                let new_node = scene::add_node(Pickup::new(
-                                    pos,
-                                    match item_type {
-                                        x if x == ItemType::Sword as u8 => ItemType::Sword,
-                                        x if x == ItemType::Gun as u8 => ItemType::Gun,
-                                        _ => unreachable!(),
-                                    },
-                                ));
+                                let new_node =
+                                    scene::add_node(Pickup::new(pos, Item::from_u8(item), ammo));
                                 if let Some(pickup) = node.pickups.insert(id as _, new_node) {
                                     if let Some(node) = scene::try_get_node(pickup) {
                                         node.delete();
                                     }
                                 }
                             }
+                            message::Throw::OPCODE => {
+                                let message::Throw {
+                                    x,
+                                    y,
+                                    facing,
+                                    weapon,
+                                    ammo,
+                                } = DeBin::deserialize_bin(&data).unwrap();
+                                let weapon = Weapon {
+                                    id: weapon,
+                                    ammo: ammo as i32,
+                                };
+
+                                // before the state without the weapon arrives, no disarm effect
+                                other.disarm();
+                                if let Some(mut thrown) =
+                                    scene::find_node_by_type::<ThrownWeapons>()
+                                {
+                                    let pos = vec2(x as f32, y as f32);
+                                    let thrower = Some(user_id.clone());
+                                    thrown.throw(pos, facing, weapon, thrower, other.team());
+                                }
+                            }
+                            message::Deflect::OPCODE => {
+                                let message::Deflect {
+                                    x,
+                                    y,
+                                    speed_x,
+                                    speed_y,
+                                    weapon,
+                                } = DeBin::deserialize_bin(&data).unwrap();
+
+                                if let Some(mut bullets) = scene::find_node_by_type::<Bullets>() {
+                                    let pos = vec2(x as f32, y as f32);
+                                    let speed = vec2(speed_x, speed_y);
+                                    bullets.deflect(pos, speed, weapon, other.team());
+                                }
+                            }
+                            message::Clash::OPCODE => {
+                                let message::Clash { target, x, y } =
+                                    DeBin::deserialize_bin(&data).unwrap();
+
+                                storage::get_mut::<Resources>()
+                                    .hit_fxses
+                                    .spawn(vec2(x as f32, y as f32));
+                                if target == node.network_id {
+                                    if let Some(mut player) = scene::find_node_by_type::<Player>() {
+                                        player.clash();
+                                    }
+                                }
+                            }
                             message::DeleteItem::OPCODE => {
                                 let message::DeleteItem { id } =
                                     DeBin::deserialize_bin(&data).unwrap();
@@ -464,6 +1460,80 @@ impl Node for NakamaRealtimeGame {
                                     }
                                 }
                             }
+                            message::Checksum::OPCODE => {
+                                let message::Checksum(checksum) =
+                                    DeBin::deserialize_bin(&data).unwrap();
+                                checksums.push((user_id.clone(), checksum));
+                            }
+                            message::Resync::OPCODE => {
+                                let message::Resync {
+                                    pickups,
+                                    broken,
+                                    players,
+                                } = DeBin::deserialize_bin(&data).unwrap();
+
+                                warn!("Resync requested by {}", user_id);
+                                for (_, pickup) in std::mem::take(&mut node.pickups) {
+                                    if let Some(node) = scene::try_get_node(pickup) {
+                                        node.delete();
+                                    }
+                                }
+                                for pickup in pickups {
+                                    let pos = vec2(pickup.x as f32, pickup.y as f32);
+                                    node.pickups.insert(
+                                        pickup.id as _,
+                                        scene::add_node(Pickup::new(
+                                            pos,
+                                            Item::from_u8(pickup.item),
+                                            pickup.ammo.map(|ammo| ammo as i32),
+                                        )),
+                                    );
+                                }
+                                node.pending_tiles
+                                    .extend(broken.iter().map(|tile| *tile as usize));
+                                for player in players {
+                                    let (dead, lives, weapon) =
+                                        (player.dead, player.lives, player.weapon);
+
+                                    // our own player is ours to tell, flushing below repeats it
+                                    if player.id == node.network_id {
+                                        continue;
+                                    }
+                                    if player.id == user_id {
+                                        node.sync_player(&mut other, dead, lives, weapon);
+                                    } else if let Some(handle) =
+                                        node.remote_players.get(&player.id).copied()
+                                    {
+                                        let mut remote = scene::get_node(handle);
+                                        node.sync_player(&mut remote, dead, lives, weapon);
+                                    }
+                                }
+                                node.network_cache.flush();
+                            }
+                            message::TilesBroken::OPCODE => {
+                                let message::TilesBroken(tiles) =
+                                    DeBin::deserialize_bin(&data).unwrap();
+                                node.pending_tiles
+                                    .extend(tiles.iter().map(|tile| *tile as usize));
+                            }
+                            message::Objectives::OPCODE => {
+                                let message::Objectives {
+                                    flags,
+                                    hill,
+                                    scores,
+                                } = DeBin::deserialize_bin(&data).unwrap();
+
+                                let flags = flags.iter().map(flag_from_message).collect::<Vec<_>>();
+                                if flags.len() == 2 && scores.len() == 2 {
+                                    if node.objectives.flags[..] != flags[..] {
+                                        let resources = storage::get::<Resources>();
+                                        play_sound_once(resources.pickup_sound);
+                                    }
+                                    node.objectives.flags = [flags[0], flags[1]];
+                                    node.objectives.hill = hill;
+                                    node.scores = [scores[0], scores[1]];
+                                }
+                            }
                             message::Idle::OPCODE => {}
                             opcode => {
                                 warn!("Unknown opcode: {}", opcode);
@@ -474,6 +1544,10 @@ impl Node for NakamaRealtimeGame {
             }
         }
 
+        for (peer, checksum) in checksums {
+            node.check_desync(api_client, &peer, checksum);
+        }
+
         if is_key_pressed(KeyCode::U) {
             for id in &node.network_ids {
                 warn!("id: {}", id);
@@ -0,0 +1,321 @@
+use macroquad::{
+    experimental::{
+        collections::storage,
+        scene::{self, Handle, RefMut},
+    },
+    prelude::*,
+    ui::{self, hash},
+};
+use macroquad_tiled as tiled;
+
+use crate::{
+    nodes::{
+        bullets, movers, objectives, pickup,
+        player::{self, Fish},
+        thrown_weapons, Nakama,
+    },
+    simulation::{self, rollback::Session, FishState, Input, ObjectiveState, Rules},
+    timestep::FixedTimestep,
+    GameType, Resources,
+};
+
+use super::nakama_realtime_game::message;
+
+/// Node running the match with rollback netcode: only inputs are exchanged,
+/// every client simulates all the fish with `simulation::Simulation`.
+/// Replaces `Player`, `RemotePlayer`s, `Bullets` and `GlobalEvents` once
+/// the match started in this mode.
+pub struct NakamaRollbackGame {
+    pub want_quit: bool,
+
+    session: Session,
+    network_ids: Vec<String>,
+    usernames: Vec<String>,
+    fish: Vec<Fish>,
+    // fish positions before the last tick, used for render interpolation
+    prev_pos: Vec<Vec2>,
+    timestep: FixedTimestep,
+    // presses not consumed by a simulation tick yet
+    pending: Input,
+    leaderboard_written: bool,
+    // tiles broken in the simulation, taken out of the drawn `main layer`
+    hidden_tiles: Vec<(usize, Option<tiled::Tile>)>,
+    // `None` when playing alone offline, testing a level
+    nakama: Option<Handle<Nakama>>,
+}
+
+impl NakamaRollbackGame {
+    // pickups pop up for 25 frames when spawned
+    const PICKUP_POP_TIME: f32 = 25. / 60.;
+
+    /// `players` are (network id, username) pairs, the same list on every client.
+    pub fn new(
+        nakama: Option<Handle<Nakama>>,
+        game_type: GameType,
+        rules: &Rules,
+        players: Vec<(String, String)>,
+        network_id: &str,
+    ) -> NakamaRollbackGame {
+        let (network_ids, usernames): (Vec<_>, Vec<_>) = players.into_iter().unzip();
+        let local = network_ids.iter().position(|id| id == network_id).unwrap();
+
+        let seed = simulation::match_seed(&network_ids);
+
+        let resources = storage::get::<Resources>();
+        let session = Session::new(
+            &resources.level,
+            resources.weapons.clone(),
+            network_ids.len(),
+            local,
+            game_type,
+            rules,
+            seed,
+        );
+        drop(resources);
+
+        let fish = session
+            .sim()
+            .fish
+            .iter()
+            .map(|fish| Fish::new(fish.pos()))
+            .collect();
+        let prev_pos = session.sim().fish.iter().map(|fish| fish.pos()).collect();
+
+        NakamaRollbackGame {
+            want_quit: false,
+            session,
+            network_ids,
+            usernames,
+            fish,
+            prev_pos,
+            timestep: FixedTimestep::new(),
+            pending: Input::default(),
+            leaderboard_written: false,
+            hidden_tiles: vec![],
+            nakama,
+        }
+    }
+
+    /// `Simulation::scores` of the team match.
+    pub fn scores(&self) -> [u32; 2] {
+        self.session.sim().scores
+    }
+
+    pub fn objectives(&self) -> ObjectiveState {
+        self.session.sim().objectives.clone()
+    }
+
+    pub fn local_pos(&self) -> Vec2 {
+        self.session.sim().fish[self.session.local()].pos()
+    }
+
+    pub fn remote_inputs(&mut self, network_id: &str, tick: u32, inputs: &[u8]) {
+        if let Some(player) = self.network_ids.iter().position(|id| id == network_id) {
+            for (n, input) in inputs.iter().enumerate() {
+                self.session.add_remote_input(
+                    player,
+                    tick as u64 + n as u64,
+                    Input::from_bits(*input),
+                );
+            }
+        }
+    }
+
+    pub fn disconnect(&mut self, network_id: &str) {
+        if let Some(player) = self.network_ids.iter().position(|id| id == network_id) {
+            self.session.disconnect(player);
+        }
+    }
+
+    /// Draw the `main layer` without the tiles broken in the simulation, a rollback
+    /// may bring some of them back.
+    fn sync_tiles(&mut self) {
+        let broken = &self.session.sim().broken;
+        let mut resources = storage::get_mut::<Resources>();
+        let layer = match resources.tiled_map.layers.get_mut("main layer") {
+            Some(layer) => layer,
+            None => return,
+        };
+
+        let (hidden, restored): (Vec<_>, Vec<_>) = std::mem::take(&mut self.hidden_tiles)
+            .into_iter()
+            .partition(|(n, _)| broken.contains(n));
+        for (n, tile) in restored {
+            layer.data[n] = tile;
+        }
+        self.hidden_tiles = hidden;
+
+        for n in broken {
+            if self.hidden_tiles.iter().all(|(hidden, _)| hidden != n) {
+                self.hidden_tiles.push((*n, layer.data[*n].take()));
+            }
+        }
+    }
+
+    /// A match with only the local fish and nothing sent anywhere, to try out the current map.
+    pub fn offline(game_type: GameType) -> NakamaRollbackGame {
+        let players = vec![("offline".to_string(), "you".to_string())];
+        Self::new(None, game_type, &Rules::default(), players, "offline")
+    }
+
+    fn draw_aftermatch(&mut self, win: bool) {
+        let resources = storage::get::<crate::gui::GuiResources>();
+
+        if let Some(nakama) = self.nakama.filter(|_| win && !self.leaderboard_written) {
+            let nakama = &mut scene::get_node(nakama).api_client;
+            nakama.write_leaderboard_record("fish_game_macroquad_wins", 1);
+            self.leaderboard_written = true;
+        }
+
+        ui::root_ui().push_skin(&resources.login_skin);
+        ui::root_ui().window(
+            hash!(),
+            Vec2::new(
+                screen_width() / 2. - 500. / 2.,
+                screen_height() / 2. - 200. / 2.,
+            ),
+            Vec2::new(500., 200.),
+            |ui| {
+                if win {
+                    ui.label(vec2(190., 30.), "You win!");
+                } else {
+                    ui.label(vec2(190., 30.), "You lost!");
+                }
+                if ui.button(vec2(130., 60.), "Return to lobby") {
+                    self.want_quit = true;
+                }
+            },
+        );
+        ui::root_ui().pop_skin();
+    }
+}
+
+impl scene::Node for NakamaRollbackGame {
+    fn draw(mut node: RefMut<Self>) {
+        let node = &mut *node;
+        let sim = node.session.sim();
+
+        {
+            let resources = storage::get::<Resources>();
+            movers::draw_movers(&resources.level, sim.time(), resources.tileset);
+        }
+
+        for pickup in &sim.pickups {
+            let scale = if pickup.lived < Self::PICKUP_POP_TIME {
+                1.0 + (pickup.lived / Self::PICKUP_POP_TIME * std::f32::consts::PI).sin() * 3.0
+            } else {
+                1.0
+            };
+            pickup::draw_item(pickup.pos, pickup.item, scale);
+        }
+
+        let positions = node.fish.iter().map(|fish| fish.pos()).collect::<Vec<_>>();
+        objectives::draw_objectives(
+            &sim.objectives,
+            sim.game_type,
+            &storage::get::<Resources>().level,
+            &positions,
+        );
+
+        let stock = sim.game_type.has_lives();
+
+        for (n, fish) in sim.fish.iter().enumerate() {
+            if fish.state == FishState::Eliminated {
+                continue;
+            }
+            let visual = &mut node.fish[n];
+
+            if n != node.session.local() {
+                let name = match stock {
+                    true => player::name_tag(&node.usernames[n], fish.lives),
+                    false => node.usernames[n].clone(),
+                };
+                draw_text_ex(
+                    &name,
+                    visual.pos().x - 1.,
+                    visual.pos().y - 1.,
+                    TextParams {
+                        font_size: 50,
+                        font_scale: 0.25,
+                        ..Default::default()
+                    },
+                );
+            }
+            visual.draw();
+        }
+
+        let alpha = node.timestep.alpha();
+        for bullet in &sim.bullets {
+            bullets::draw_projectile(bullet.pos, bullet.speed, &bullet.projectile, alpha);
+        }
+        for thrown in &sim.thrown {
+            let pos = thrown.pos + thrown.speed * FixedTimestep::DT * alpha;
+            thrown_weapons::draw_thrown(pos, thrown.lived, thrown.weapon.id);
+        }
+
+        let local = node.session.local();
+        player::draw_hud(node.fish[local].pos(), &sim.fish[local], stock);
+    }
+
+    fn update(mut node: RefMut<Self>) {
+        let node = &mut *node;
+        let input = player::read_input();
+
+        node.pending = Input {
+            jump: node.pending.jump || input.jump,
+            fire: node.pending.fire || input.fire,
+            throw: node.pending.throw || input.throw,
+            dash: node.pending.dash || input.dash,
+            ..input
+        };
+
+        let mut first_tick = None;
+        let mut inputs = vec![];
+
+        for _ in 0..node.timestep.advance(get_frame_time()) {
+            // too far ahead of the others, wait for their inputs
+            if node.session.can_advance() == false {
+                break;
+            }
+
+            let tick = node.session.add_local_input(node.pending);
+            first_tick.get_or_insert(tick);
+            inputs.push(node.pending.to_bits());
+            node.pending.jump = false;
+            node.pending.fire = false;
+            node.pending.throw = false;
+            node.pending.dash = false;
+
+            node.prev_pos = node.session.sim().fish.iter().map(|f| f.pos()).collect();
+            let events = {
+                let resources = storage::get::<Resources>();
+                node.session.advance(&resources.level)
+            };
+            player::play_events(&events, &node.session.sim().fish);
+        }
+        node.sync_tiles();
+
+        if let (Some(tick), Some(nakama)) = (first_tick, node.nakama) {
+            let mut nakama = scene::get_node(nakama);
+            nakama.api_client.socket_send(
+                message::Inputs::OPCODE,
+                &message::Inputs {
+                    tick: tick as u32,
+                    inputs,
+                },
+            );
+        }
+
+        let alpha = node.timestep.alpha();
+        for (n, fish) in node.session.sim().fish.iter().enumerate() {
+            node.fish[n].sync(fish, node.prev_pos[n].lerp(fish.pos(), alpha));
+        }
+
+        // only trust the result once nobody's late input can change it
+        let sim = node.session.confirmed_sim();
+        if sim.winner.is_some() {
+            let win = sim.is_winner(node.session.local());
+            node.draw_aftermatch(win);
+        }
+    }
+}
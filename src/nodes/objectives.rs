@@ -0,0 +1,85 @@
+//! Flags and the hill of the objective modes, drawn the same way by
+//! `NakamaRealtimeGame` and `NakamaRollbackGame`.
+
+use macroquad::prelude::*;
+
+use crate::{
+    simulation::{Map, ObjectiveState, Team},
+    GameType,
+};
+
+pub fn team_color(team: Team) -> Color {
+    match team {
+        Team::Blue => Color::new(0.25, 0.55, 1.0, 1.0),
+        Team::Green => Color::new(0.3, 0.85, 0.35, 1.0),
+    }
+}
+
+/// `fish` are the positions of every fish, in the order `Flag::Carried` refers to.
+pub fn draw_objectives(state: &ObjectiveState, game_type: GameType, map: &Map, fish: &[Vec2]) {
+    match game_type {
+        GameType::CaptureTheFlag { .. } => {
+            for &team in &Team::ALL {
+                let color = team_color(team);
+
+                if let Some(base) = map.flag_bases[team.index()] {
+                    draw_rectangle(
+                        base.x - 14.,
+                        base.y - 4.,
+                        28.,
+                        4.,
+                        Color { a: 0.6, ..color },
+                    );
+                }
+                if let Some(pos) = state.flag_pos(team, map, fish) {
+                    draw_flag(pos, color);
+                }
+            }
+        }
+        GameType::KingOfTheHill { .. } => {
+            if let Some(hill) = map.hill {
+                draw_hill(state, hill);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn draw_flag(pos: Vec2, color: Color) {
+    draw_line(pos.x, pos.y, pos.x, pos.y - 48., 2., DARKGRAY);
+    draw_triangle(
+        vec2(pos.x + 1., pos.y - 48.),
+        vec2(pos.x + 21., pos.y - 41.),
+        vec2(pos.x + 1., pos.y - 34.),
+        color,
+    );
+}
+
+/// The zone, tinted by its owner, with the contest meter filling from the middle
+/// towards the side of the team taking it.
+fn draw_hill(state: &ObjectiveState, hill: Rect) {
+    let tint = state
+        .hill_owner()
+        .map_or(Color::new(1., 1., 1., 0.1), |team| Color {
+            a: 0.25,
+            ..team_color(team)
+        });
+    draw_rectangle(hill.x, hill.y, hill.w, hill.h, tint);
+    draw_rectangle_lines(
+        hill.x,
+        hill.y,
+        hill.w,
+        hill.h,
+        2.,
+        Color::new(1., 1., 1., 0.5),
+    );
+
+    let (middle, y) = (hill.x + hill.w / 2., hill.y - 10.);
+    let fill = state.hill.abs() * hill.w / 2.;
+    draw_rectangle(hill.x, y, hill.w, 4., Color::new(0., 0., 0., 0.5));
+    if state.hill < 0. {
+        draw_rectangle(middle - fill, y, fill, 4., team_color(Team::Blue));
+    } else {
+        draw_rectangle(middle, y, fill, 4., team_color(Team::Green));
+    }
+}
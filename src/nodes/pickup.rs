@@ -7,27 +7,27 @@ use macroquad::{
     prelude::*,
 };
 
-use crate::Resources;
-
-#[derive(Debug, Clone, Copy, PartialEq)]
-#[repr(u8)]
-pub enum ItemType {
-    Gun = 1,
-    Sword = 2,
-}
+use crate::{
+    simulation::{Item, PowerUp},
+    weapons::{PickupIcon, Tint},
+    Resources,
+};
 
 pub struct Pickup {
     pub pos: Vec2,
-    pub item_type: ItemType,
+    pub item: Item,
+    /// Ammo left in a weapon that was thrown, `None` for a full one
+    pub ammo: Option<i32>,
     visual_scale: f32,
 }
 
 impl Pickup {
-    pub fn new(pos: Vec2, item_type: ItemType) -> Pickup {
+    pub fn new(pos: Vec2, item: Item, ammo: Option<i32>) -> Pickup {
         Pickup {
             pos,
             visual_scale: 1.0,
-            item_type,
+            item,
+            ammo,
         }
     }
 }
@@ -67,42 +67,72 @@ impl scene::Node for Pickup {
     }
 
     fn draw(node: RefMut<Self>) {
-        let resources = storage::get_mut::<Resources>();
-
-        resources.tiled_map.spr_ex(
-            "tileset",
-            Rect::new(0.0 * 32.0, 6.0 * 32.0, 32.0, 32.0),
-            Rect::new(
-                node.pos.x - (32.0 * node.visual_scale - 32.) / 2.,
-                node.pos.y - (32.0 * node.visual_scale - 32.) / 2.,
-                32.0 * node.visual_scale,
-                32.0 * node.visual_scale,
-            ),
-        );
+        draw_item(node.pos, node.item, node.visual_scale);
+    }
+}
+
+/// Item on its pedestal tile, `scale` is used for the pop in and out animations.
+pub fn draw_item(pos: Vec2, item: Item, scale: f32) {
+    let resources = storage::get_mut::<Resources>();
+
+    resources.tiled_map.spr_ex(
+        "tileset",
+        Rect::new(0.0 * 32.0, 6.0 * 32.0, 32.0, 32.0),
+        Rect::new(
+            pos.x - (32.0 * scale - 32.) / 2.,
+            pos.y - (32.0 * scale - 32.) / 2.,
+            32.0 * scale,
+            32.0 * scale,
+        ),
+    );
 
-        match node.item_type {
-            ItemType::Gun => draw_texture_ex(
-                resources.gun,
-                node.pos.x,
-                node.pos.y + 8.,
-                WHITE,
-                DrawTextureParams {
-                    source: Some(Rect::new(0.0, 0.0, 64., 32.)),
-                    dest_size: Some(vec2(32., 16.)),
-                    ..Default::default()
-                },
-            ),
-            ItemType::Sword => draw_texture_ex(
-                resources.sword,
-                node.pos.x + 4.,
-                node.pos.y - 4.,
-                WHITE,
-                DrawTextureParams {
-                    source: Some(Rect::new(195.0 + 5., 93.0 + 5., 65. - 10., 93. - 10.)),
-                    dest_size: Some(vec2(32., 32.)),
-                    ..Default::default()
-                },
-            ),
+    let weapon = match item {
+        Item::Weapon(weapon) => weapon,
+        Item::Armor => {
+            // no art for the shield yet
+            let (x, y) = (pos.x + 16., pos.y + 14.);
+            draw_circle(x, y, 9., Color::new(0.4, 0.7, 1.0, 1.0));
+            draw_circle_lines(x, y, 9., 2., Color::new(0.9, 0.95, 1.0, 1.0));
+            return;
         }
+        Item::PowerUp(power_up) => {
+            let (x, y) = (pos.x + 16., pos.y + 14.);
+            let label = match power_up {
+                PowerUp::Speed => "S",
+                PowerUp::HighJump => "J",
+                PowerUp::Shield => "P",
+                PowerUp::InfiniteAmmo => "A",
+            };
+            draw_circle(x, y, 9., power_up_color(power_up));
+            draw_text(label, x - 4., y + 5., 16., BLACK);
+            return;
+        }
+    };
+
+    if let Some(def) = resources.weapons.get(weapon) {
+        let PickupIcon { source, dest } = def.pickup;
+        let Tint { r, g, b } = def.sprite.tint;
+
+        draw_texture_ex(
+            resources.weapon_textures[&weapon],
+            pos.x + dest.x,
+            pos.y + dest.y,
+            Color::new(r, g, b, 1.0),
+            DrawTextureParams {
+                source: Some(source.rect()),
+                dest_size: Some(vec2(dest.w, dest.h)),
+                ..Default::default()
+            },
+        );
+    }
+}
+
+/// Color of the power-up's pickup, aura and timer.
+pub fn power_up_color(power_up: PowerUp) -> Color {
+    match power_up {
+        PowerUp::Speed => Color::new(1.0, 0.9, 0.3, 1.0),
+        PowerUp::HighJump => Color::new(0.5, 1.0, 0.4, 1.0),
+        PowerUp::Shield => Color::new(0.8, 0.5, 1.0, 1.0),
+        PowerUp::InfiniteAmmo => Color::new(1.0, 0.5, 0.3, 1.0),
     }
 }
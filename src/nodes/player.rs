@@ -4,54 +4,43 @@ use macroquad::{
     experimental::{
         animation::{AnimatedSprite, Animation},
         collections::storage,
-        coroutines::{start_coroutine, wait_seconds, Coroutine},
         scene::{self, Handle, RefMut},
-        state_machine::{State, StateMachine},
     },
     prelude::*,
     ui::{self, hash},
 };
-use macroquad_platformer::Actor;
 
 use crate::{
-    consts,
-    nodes::{pickup::ItemType, Nakama, NakamaRealtimeGame, Pickup},
-    Resources,
+    nodes::{pickup, Movers, Nakama, NakamaRealtimeGame, Pickup, RemotePlayer, TouchControls},
+    simulation::{
+        self, Arena, Bystander, Event, FishState, Input, Move, Movement, PowerUp, Team, Weapon,
+    },
+    timestep::FixedTimestep,
+    weapons::{SpriteAnimation, Tint, WeaponDef, WeaponId, Weapons},
+    GameType, Resources,
 };
 
-#[derive(Default, Debug, Clone)]
-pub struct Input {
-    jump: bool,
-    fire: bool,
-    left: bool,
-    right: bool,
-}
-
-pub enum Weapon {
-    Gun { bullets: i32 },
-    Sword,
-}
-
 pub struct Fish {
     fish_sprite: AnimatedSprite,
-    gun_sprite: AnimatedSprite,
-    pub sword_sprite: AnimatedSprite,
-    gun_fx_sprite: AnimatedSprite,
-    gun_fx: bool,
-    pub collider: Actor,
+    // sprites of the weapon held, rebuilt when the weapon changes
+    weapon_sprites: Option<WeaponSprites>,
+    // where it is drawn, between two simulation ticks for the simulated fish
     pos: Vec2,
-    speed: Vec2,
-    on_ground: bool,
+    // splashes when it goes in or out
+    in_water: bool,
     dead: bool,
+    // blinks while it can't be hurt
+    invulnerable: bool,
     facing: bool,
     pub weapon: Option<Weapon>,
-    input: Input,
+    // glows in its color while it lasts
+    pub power_up: Option<PowerUp>,
+    // drawn with the team colored whale
+    pub team: Option<Team>,
 }
 
 impl Fish {
     pub fn new(spawner_pos: Vec2) -> Fish {
-        let mut resources = storage::get_mut::<Resources>();
-
         let fish_sprite = AnimatedSprite::new(
             76,
             66,
@@ -80,73 +69,38 @@ impl Fish {
                     frames: 4,
                     fps: 8,
                 },
-            ],
-            true,
-        );
-        let gun_sprite = AnimatedSprite::new(
-            92,
-            32,
-            &[
-                Animation {
-                    name: "idle".to_string(),
-                    row: 0,
-                    frames: 1,
-                    fps: 1,
-                },
                 Animation {
-                    name: "shoot".to_string(),
-                    row: 1,
+                    name: "double_jump".to_string(),
+                    row: 4,
                     frames: 3,
-                    fps: 15,
+                    fps: 12,
                 },
-            ],
-            false,
-        );
-        let sword_sprite = AnimatedSprite::new(
-            65,
-            93,
-            &[
                 Animation {
-                    name: "idle".to_string(),
-                    row: 0,
+                    name: "wall_slide".to_string(),
+                    row: 8,
                     frames: 1,
                     fps: 1,
                 },
                 Animation {
-                    name: "shoot".to_string(),
-                    row: 1,
-                    frames: 4,
-                    fps: 15,
+                    name: "dash".to_string(),
+                    row: 10,
+                    frames: 2,
+                    fps: 10,
                 },
             ],
-            false,
-        );
-        let gun_fx_sprite = AnimatedSprite::new(
-            92,
-            32,
-            &[Animation {
-                name: "shoot".to_string(),
-                row: 2,
-                frames: 3,
-                fps: 15,
-            }],
-            false,
+            true,
         );
-
         Fish {
             fish_sprite,
-            gun_fx_sprite,
-            gun_fx: false,
-            gun_sprite,
-            sword_sprite,
-            collider: resources.collision_world.add_actor(spawner_pos, 30, 54),
-            on_ground: false,
+            weapon_sprites: None,
             dead: false,
+            invulnerable: false,
             pos: spawner_pos,
-            speed: vec2(0., 0.),
+            in_water: false,
             facing: true,
             weapon: None,
-            input: Default::default(),
+            power_up: None,
+            team: None,
         }
     }
 
@@ -166,45 +120,113 @@ impl Fish {
         self.fish_sprite.set_animation(animation);
     }
 
+    /// Play the animation of the movement ability in use, if any.
+    pub fn set_move(&mut self, movement: Move) {
+        match movement {
+            Move::None => {}
+            Move::DoubleJump => self.fish_sprite.set_animation(4),
+            Move::WallSlide => self.fish_sprite.set_animation(5),
+            Move::Dash => self.fish_sprite.set_animation(6),
+        }
+    }
+
     pub fn set_facing(&mut self, facing: bool) {
         self.facing = facing;
     }
 
+    pub fn set_dead(&mut self, dead: bool) {
+        self.dead = dead;
+    }
+
+    pub fn set_invulnerable(&mut self, invulnerable: bool) {
+        self.invulnerable = invulnerable;
+    }
+
     pub fn disarm(&mut self) {
         self.weapon = None;
     }
 
-    pub fn pick_weapon(&mut self, item_type: ItemType) {
-        let resources = storage::get_mut::<Resources>();
-        play_sound_once(resources.pickup_sound);
+    /// Show a frame of the weapon's attack animation, or the idle one with `None`.
+    pub fn set_attack_frame(&mut self, frame: Option<u32>) {
+        let resources = storage::get::<Resources>();
 
-        match item_type {
-            ItemType::Gun => {
-                self.weapon = Some(Weapon::Gun { bullets: 3 });
+        if let Some(sprites) = self.weapon_sprites(&resources.weapons) {
+            match frame {
+                Some(frame) => {
+                    sprites.weapon.set_animation(1);
+                    sprites.weapon.set_frame(frame);
+                    if let Some(effect) = &mut sprites.effect {
+                        effect.set_frame(frame);
+                    }
+                }
+                None => sprites.weapon.set_animation(0),
             }
-            ItemType::Sword => self.weapon = Some(Weapon::Sword),
+            sprites.attacking = frame.is_some();
         }
     }
 
-    pub fn facing_dir(&self) -> f32 {
-        if self.facing {
-            1.
-        } else {
-            -1.
+    /// `ammo` is of a weapon thrown before, `None` for a full one.
+    pub fn pick_weapon(&mut self, weapon: WeaponId, ammo: Option<i32>) {
+        let resources = storage::get::<Resources>();
+
+        if let Some(def) = resources.weapons.get(weapon) {
+            play_sound_once(resources.pickup_sound);
+            self.weapon = Some(Weapon {
+                ammo: ammo.unwrap_or(def.ammo),
+                ..Weapon::new(def)
+            });
         }
     }
 
-    pub fn jump(&mut self) {
-        let resources = storage::get::<Resources>();
+    /// Show the simulated `fish`, drawn at `pos`.
+    pub fn sync(&mut self, fish: &simulation::Fish, pos: Vec2) {
+        self.set_pos(pos);
+        self.set_facing(fish.facing);
+        self.set_dead(fish.is_dead());
+        self.set_invulnerable(fish.invulnerable > 0.);
+        self.weapon = fish.weapon;
+        self.power_up = fish.power_up;
+        self.team = fish.team;
+
+        match fish.state {
+            FishState::Normal if fish.speed.x != 0. => self.set_animation(1),
+            FishState::Dying { .. } => self.set_animation(2),
+            FishState::Dead { .. } => self.set_animation(3),
+            _ => self.set_animation(0),
+        }
+        self.set_move(fish.current_move());
+
+        let frame = match (fish.state, fish.weapon) {
+            (FishState::Attacking { timer }, Some(weapon)) => storage::get::<Resources>()
+                .weapons
+                .get(weapon.id)
+                .map(|def| def.attack_frame(timer)),
+            _ => None,
+        };
+        self.set_attack_frame(frame);
+    }
 
-        self.speed.y = -consts::JUMP_SPEED;
-        audio::play_sound(
-            resources.jump_sound,
-            audio::PlaySoundParams {
-                looped: false,
-                volume: 0.6,
-            },
-        );
+    fn weapon_sprites(&mut self, weapons: &Weapons) -> Option<&mut WeaponSprites> {
+        let id = self.weapon?.id;
+
+        if self.weapon_sprites.as_ref().map(|sprites| sprites.id) != Some(id) {
+            self.weapon_sprites = weapons.get(id).map(WeaponSprites::new);
+        }
+        self.weapon_sprites.as_mut()
+    }
+
+    /// Splash going in or out of the water, for the fish the simulation does not drive.
+    pub fn update_water(&mut self) {
+        let mut resources = storage::get_mut::<Resources>();
+        let (width, height) = (simulation::FISH_WIDTH, simulation::FISH_HEIGHT);
+
+        let in_water = resources.level.in_water(self.pos, width, height);
+        if in_water != self.in_water {
+            self.in_water = in_water;
+            resources
+                .splash_fxses
+                .spawn(self.pos + vec2(width as f32 / 2., height as f32 / 2.));
+        }
     }
 
     pub fn draw(&mut self) {
@@ -212,11 +234,38 @@ impl Fish {
 
         self.fish_sprite.update();
 
+        if self.invulnerable && (get_time() * 10.) as i64 % 2 == 0 {
+            return;
+        }
+
+        let pos = self.pos;
+        let underwater =
+            resources
+                .level
+                .in_water(pos, simulation::FISH_WIDTH, simulation::FISH_HEIGHT);
+
+        if let (false, Some(power_up)) = (self.dead, self.power_up) {
+            let Color { r, g, b, .. } = pickup::power_up_color(power_up);
+            let radius = 38. + (get_time() * 6.).sin() as f32 * 3.;
+            draw_circle(pos.x + 15., pos.y + 27., radius, Color::new(r, g, b, 0.2));
+            draw_circle_lines(
+                pos.x + 15.,
+                pos.y + 27.,
+                radius,
+                2.,
+                Color::new(r, g, b, 0.6),
+            );
+        }
+
+        let whale = match self.team {
+            Some(team) => resources.team_whales[team.index()],
+            None => resources.whale,
+        };
         draw_texture_ex(
-            resources.whale,
-            self.pos.x - 25.,
-            self.pos.y - 10.,
-            color::WHITE,
+            whale,
+            pos.x - 25.,
+            pos.y - 10.,
+            underwater_tint(color::WHITE, underwater),
             DrawTextureParams {
                 source: Some(self.fish_sprite.frame().source_rect),
                 dest_size: Some(self.fish_sprite.frame().dest_size),
@@ -225,130 +274,157 @@ impl Fish {
             },
         );
 
-        if self.dead == false && matches!(self.weapon, Some(Weapon::Gun { .. })) {
-            let gun_mount_pos = if self.facing {
-                vec2(0., 16.)
-            } else {
-                vec2(-60., 16.)
-            };
-            self.gun_sprite.update();
+        let def = self
+            .weapon
+            .and_then(|weapon| resources.weapons.get(weapon.id));
+        if let (false, Some(def)) = (self.dead, def) {
+            let texture = resources.weapon_textures[&def.id];
+            let mount = pos + def.sprite.mount(self.facing);
+            let flip_x = !self.facing;
+            let Tint { r, g, b } = def.sprite.tint;
+            let tint = underwater_tint(Color::new(r, g, b, 1.0), underwater);
+            let sprites = self.weapon_sprites(&resources.weapons).unwrap();
+
+            sprites.weapon.update();
             draw_texture_ex(
-                resources.gun,
-                self.pos.x + gun_mount_pos.x,
-                self.pos.y + gun_mount_pos.y,
-                color::WHITE,
+                texture,
+                mount.x,
+                mount.y,
+                tint,
                 DrawTextureParams {
-                    source: Some(self.gun_sprite.frame().source_rect),
-                    dest_size: Some(self.gun_sprite.frame().dest_size),
-                    flip_x: !self.facing,
+                    source: Some(sprites.weapon.frame().source_rect),
+                    dest_size: Some(sprites.weapon.frame().dest_size),
+                    flip_x,
                     ..Default::default()
                 },
             );
 
-            if self.gun_fx {
-                self.gun_fx_sprite.update();
+            if let (true, Some(effect)) = (sprites.attacking, &mut sprites.effect) {
+                effect.update();
                 draw_texture_ex(
-                    resources.gun,
-                    self.pos.x + gun_mount_pos.x,
-                    self.pos.y + gun_mount_pos.y,
-                    color::WHITE,
+                    texture,
+                    mount.x,
+                    mount.y,
+                    underwater_tint(color::WHITE, underwater),
                     DrawTextureParams {
-                        source: Some(self.gun_fx_sprite.frame().source_rect),
-                        dest_size: Some(self.gun_fx_sprite.frame().dest_size),
-                        flip_x: !self.facing,
+                        source: Some(effect.frame().source_rect),
+                        dest_size: Some(effect.frame().dest_size),
+                        flip_x,
                         ..Default::default()
                     },
                 );
             }
         }
+    }
+}
 
-        if self.dead == false && matches!(self.weapon, Some(Weapon::Sword)) {
-            let sword_mount_pos = if self.facing {
-                vec2(10., -35.)
-            } else {
-                vec2(-50., -35.)
-            };
-            self.sword_sprite.update();
-            draw_texture_ex(
-                resources.sword,
-                self.pos.x + sword_mount_pos.x,
-                self.pos.y + sword_mount_pos.y,
-                color::WHITE,
-                DrawTextureParams {
-                    source: Some(self.sword_sprite.frame().source_rect),
-                    dest_size: Some(self.sword_sprite.frame().dest_size),
-                    flip_x: !self.facing,
-                    ..Default::default()
-                },
-            );
+/// `color` in the blue of the water when `underwater`.
+pub fn underwater_tint(color: Color, underwater: bool) -> Color {
+    match underwater {
+        true => Color::new(color.r * 0.55, color.g * 0.75, color.b, color.a),
+        false => color,
+    }
+}
+
+/// Sprites of the weapon a fish holds, built from its definition.
+struct WeaponSprites {
+    id: WeaponId,
+    weapon: AnimatedSprite,
+    // drawn over the weapon during attacks
+    effect: Option<AnimatedSprite>,
+    attacking: bool,
+}
+
+impl WeaponSprites {
+    fn new(def: &WeaponDef) -> WeaponSprites {
+        let sprite = &def.sprite;
+        let animation = |name: &str, animation: &SpriteAnimation| Animation {
+            name: name.to_string(),
+            row: animation.row,
+            frames: animation.frames,
+            fps: animation.fps,
+        };
+
+        WeaponSprites {
+            id: def.id,
+            weapon: AnimatedSprite::new(
+                sprite.width,
+                sprite.height,
+                &[
+                    animation("idle", &sprite.idle),
+                    animation("attack", &sprite.attack),
+                ],
+                false,
+            ),
+            effect: sprite.effect.as_ref().map(|effect| {
+                AnimatedSprite::new(
+                    sprite.width,
+                    sprite.height,
+                    &[animation("attack", effect)],
+                    false,
+                )
+            }),
+            attacking: false,
         }
     }
 }
 
 pub struct Player {
     pub fish: Fish,
-
-    deathmatch: bool,
+    /// The fish as the game rules see it, `fish` only shows it
+    pub state: simulation::Fish,
+
+    // presses not consumed by a simulation tick yet
+    pending: Input,
+    movement: Movement,
+    // the host may pick another mode in the lobby, copied from `NakamaRealtimeGame`
+    game_type: GameType,
+    friendly_fire: bool,
+    // place among the players, fish respawning together on different clients
+    // go to different spawns with it
+    spawn_pick: usize,
     win: bool,
+    aftermatch: bool,
     pub want_quit: bool,
-    jump_grace_timer: f32,
-    state_machine: StateMachine<RefMut<Player>>,
+    timestep: FixedTimestep,
+    // fish position before the last simulation tick, used for render interpolation
+    prev_pos: Vec2,
+    // time of the movers the fish was carried to last
+    movers_time: f32,
+    // dead on the last frame already, the enemy team scored for it
+    was_dead: bool,
     leaderboard_written: bool,
     nakama: Handle<Nakama>,
     nakama_realtime: Handle<NakamaRealtimeGame>,
 }
 
 impl Player {
-    const ST_NORMAL: usize = 0;
-    const ST_DEATH: usize = 1;
-    const ST_SHOOT: usize = 2;
-    const ST_SWORD_SHOOT: usize = 3;
-    const ST_AFTERMATCH: usize = 4;
-
     pub fn new(
-        deathmatch: bool,
+        game_type: GameType,
         nakama: Handle<Nakama>,
         nakama_realtime: Handle<NakamaRealtimeGame>,
     ) -> Player {
         let spawner_pos = {
             let resources = storage::get_mut::<Resources>();
-            let objects = &resources.tiled_map.layers["logic"].objects;
-            let macroquad_tiled::Object {
-                world_x, world_y, ..
-            } = objects[rand::gen_range(0, objects.len()) as usize];
-            vec2(world_x, world_y)
+            let spawns = &resources.level.spawns;
+            spawns[rand::gen_range(0, spawns.len()) as usize]
         };
 
-        let mut state_machine = StateMachine::new();
-        state_machine.add_state(Self::ST_NORMAL, State::new().update(Self::update_normal));
-        state_machine.add_state(
-            Self::ST_DEATH,
-            State::new().coroutine(Self::death_coroutine),
-        );
-        state_machine.add_state(
-            Self::ST_SHOOT,
-            State::new()
-                .update(Self::update_shoot)
-                .coroutine(Self::shoot_coroutine),
-        );
-        state_machine.add_state(
-            Self::ST_SWORD_SHOOT,
-            State::new()
-                .update(Self::update_sword_shoot)
-                .coroutine(Self::sword_shoot_coroutine),
-        );
-        state_machine.add_state(
-            Self::ST_AFTERMATCH,
-            State::new().update(Self::update_aftermatch),
-        );
-
         Player {
             fish: Fish::new(spawner_pos),
-            deathmatch,
+            state: simulation::Fish::new(spawner_pos),
+            pending: Input::default(),
+            movement: Movement::default(),
+            game_type,
+            friendly_fire: false,
+            spawn_pick: 0,
             win: false,
+            aftermatch: false,
             want_quit: false,
-            jump_grace_timer: 0.,
-            state_machine,
+            timestep: FixedTimestep::new(),
+            prev_pos: spawner_pos,
+            movers_time: 0.,
+            was_dead: false,
             leaderboard_written: false,
             nakama,
             nakama_realtime,
@@ -356,227 +432,142 @@ impl Player {
     }
 
     pub fn pos(&self) -> Vec2 {
-        self.fish.pos
+        self.state.pos()
     }
 
-    pub fn facing(&self) -> bool {
-        self.fish.facing
+    /// Start over from a spawn of the map just loaded into `resources`.
+    pub fn enter_map(&mut self, resources: &Resources) {
+        let pos = self.safe_spawn(&resources.level);
+        self.spawn_at(pos);
     }
 
-    pub fn pick_weapon(&mut self, item_type: ItemType) {
-        if self.state_machine.state() != Self::ST_SHOOT
-            && self.state_machine.state() != Self::ST_SWORD_SHOOT
-        {
-            self.fish.pick_weapon(item_type);
-        }
+    /// Put the fish on `pos` of the current map, standing still.
+    pub fn spawn_at(&mut self, pos: Vec2) {
+        self.state.body.set_pos(pos);
+        self.state.speed = vec2(0., 0.);
+        self.prev_pos = pos;
+        self.fish.set_pos(pos);
     }
 
-    pub fn is_dead(&self) -> bool {
-        self.fish.dead
+    /// The spawn of `level` furthest from the enemies and out of their line of fire.
+    fn safe_spawn(&self, level: &simulation::Map) -> Vec2 {
+        let bystanders = scene::find_nodes_by_type::<RemotePlayer>()
+            .filter(|other| other.dead == false)
+            .map(|other| Bystander {
+                pos: other.pos(),
+                facing: other.facing(),
+                enemy: simulation::spares(self.friendly_fire, other.team(), self.team()) == false,
+            })
+            .collect::<Vec<_>>();
+
+        simulation::pick_spawn(level, &bystanders, self.spawn_pick).unwrap_or_default()
     }
 
-    pub fn kill(&mut self, direction: bool) {
-        self.fish.facing = direction;
-        self.state_machine.set_state(Self::ST_DEATH);
+    pub fn facing(&self) -> bool {
+        self.state.facing
     }
 
-    pub fn weapon(&self) -> Option<ItemType> {
-        match self.fish.weapon {
-            None => None,
-            Some(Weapon::Gun { .. }) => Some(ItemType::Gun),
-            Some(Weapon::Sword) => Some(ItemType::Sword),
-        }
+    pub fn is_dead(&self) -> bool {
+        self.state.is_dead()
     }
 
-    fn death_coroutine(node: &mut RefMut<Player>) -> Coroutine {
-        let handle = node.handle();
-        let coroutine = async move {
-            {
-                let mut node = scene::get_node(handle);
-                node.fish.speed.x = -300. * node.fish.facing_dir();
-                node.fish.speed.y = -150.;
-
-                node.fish.dead = true;
-                node.fish.fish_sprite.set_animation(2);
-            }
-            // give some take for a dead fish to take off the ground
-            wait_seconds(0.1).await;
-
-            // wait until it lands
-            while scene::get_node(handle).fish.on_ground == false {
-                next_frame().await;
-            }
-
-            {
-                let mut node = scene::get_node(handle);
-                node.fish.fish_sprite.set_animation(3);
-                node.fish.speed = vec2(0., 0.);
-            }
-
-            wait_seconds(0.5).await;
-
-            {
-                let mut resources = storage::get_mut::<Resources>();
-                let mut node = scene::get_node(handle);
-                let pos = node.fish.pos;
-
-                node.fish.fish_sprite.playing = false;
-                node.fish.speed = vec2(0., 0.);
-                resources.explosion_fxses.spawn(pos + vec2(15., 33.));
-            }
-
-            wait_seconds(0.5).await;
-
-            let mut resources = storage::get_mut::<Resources>();
-            let mut this = scene::get_node(handle);
-
-            this.fish.pos = {
-                let objects = &resources.tiled_map.layers["logic"].objects;
-                let macroquad_tiled::Object {
-                    world_x, world_y, ..
-                } = objects[rand::gen_range(0, objects.len()) as usize];
-
-                vec2(world_x, world_y)
-            };
-            this.fish.fish_sprite.playing = true;
-            this.fish.disarm();
-
-            // in deathmatch we can just get back to normal after death
-            if this.deathmatch {
-                this.state_machine.set_state(Self::ST_NORMAL);
-                this.fish.dead = false;
-                resources
-                    .collision_world
-                    .set_actor_position(this.fish.collider, this.fish.pos);
-            }
-        };
-
-        start_coroutine(coroutine)
+    pub fn kill(&mut self, direction: bool) {
+        self.state.kill(direction);
     }
 
-    fn shoot_coroutine(node: &mut RefMut<Player>) -> Coroutine {
-        let handle = node.handle();
-        let coroutine = async move {
-            {
-                let resources = storage::get_mut::<Resources>();
-                play_sound_once(resources.shoot_sound);
-
-                let mut node = &mut *scene::get_node(handle);
-
-                node.fish.gun_fx = true;
-                let mut nakama = scene::get_node(node.nakama_realtime);
-                nakama.shoot();
-
-                let mut bullets = scene::find_node_by_type::<crate::nodes::Bullets>().unwrap();
-                bullets.spawn_bullet(node.fish.pos, node.fish.facing);
-                node.fish.speed.x = -consts::GUN_THROWBACK * node.fish.facing_dir();
-            }
-            {
-                let node = &mut *scene::get_node(handle);
-                node.fish.gun_sprite.set_animation(1);
-            }
-            for i in 0u32..3 {
-                {
-                    let node = &mut *scene::get_node(handle);
-                    node.fish.gun_sprite.set_frame(i);
-                    node.fish.gun_fx_sprite.set_frame(i);
-                }
-
-                wait_seconds(0.08).await;
-            }
-            {
-                let mut node = scene::get_node(handle);
-                node.fish.gun_sprite.set_animation(0);
-            }
-
-            let mut node = &mut *scene::get_node(handle);
-
-            node.fish.gun_fx = false;
-
-            if let Weapon::Gun { bullets } = node.fish.weapon.as_mut().unwrap() {
-                *bullets -= 1;
-
-                if *bullets <= 0 {
-                    let mut resources = storage::get_mut::<Resources>();
-                    resources.disarm_fxses.spawn(node.fish.pos + vec2(16., 33.));
-                    node.fish.weapon = None;
-                }
-            }
+    pub fn weapon(&self) -> Option<WeaponId> {
+        self.state.weapon.map(|weapon| weapon.id)
+    }
 
-            // node.weapon_animation.play(0, 0..5).await;
-            // node.weapon_animation.play(0, 5..).await;
-            node.state_machine.set_state(Self::ST_NORMAL);
-        };
+    pub fn power_up(&self) -> Option<PowerUp> {
+        self.state.power_up
+    }
 
-        start_coroutine(coroutine)
+    pub fn team(&self) -> Option<Team> {
+        self.state.team
     }
 
-    fn update_shoot(node: &mut RefMut<Player>, _dt: f32) {
-        node.fish.speed.x *= 0.9;
+    /// Lives left, `None` in the modes respawning forever.
+    pub fn lives(&self) -> Option<u8> {
+        if self.game_type.has_lives() == false {
+            return None;
+        }
+        Some(self.state.lives)
     }
 
-    fn sword_shoot_coroutine(node: &mut RefMut<Player>) -> Coroutine {
-        let handle = node.handle();
-        let coroutine = async move {
-            {
-                let resources = storage::get_mut::<Resources>();
-                play_sound_once(resources.sword_sound);
+    /// Movement ability in use, for the animation.
+    pub fn current_move(&self) -> Move {
+        self.state.current_move()
+    }
 
-                let node = &mut *scene::get_node(handle);
-                node.fish.sword_sprite.set_animation(1);
+    /// Reach of a melee swing still within `simulation::PARRY_TIME`.
+    pub fn parry_box(&self) -> Option<Rect> {
+        self.state.parry_box(&storage::get::<Resources>().weapons)
+    }
 
-                let mut nakama = scene::get_node(node.nakama_realtime);
-                nakama.shoot();
-            }
+    /// Pushed back by the sword our swing met, the swing is over.
+    pub fn clash(&mut self) {
+        self.state.clash();
+    }
 
-            {
-                let node = &mut *scene::get_node(handle);
-                let others = scene::find_nodes_by_type::<crate::nodes::RemotePlayer>();
-                let sword_hit_box = if node.fish.facing {
-                    Rect::new(node.pos().x + 35., node.pos().y - 5., 40., 60.)
-                } else {
-                    Rect::new(node.pos().x - 50., node.pos().y - 5., 40., 60.)
-                };
+    /// Swing or fire the weapon `def`, the simulated fish already started its attack.
+    /// The other fish are hurt on their own clients, they are only told about it.
+    fn attack(&mut self, def: &WeaponDef) {
+        let mut nakama = scene::get_node(self.nakama_realtime);
+        nakama.shoot();
 
-                for player in others {
-                    if Rect::new(player.pos().x, player.pos().y, 20., 64.).overlaps(&sword_hit_box)
-                    {
-                        let mut net = scene::get_node(node.nakama_realtime);
-                        net.kill(&player.id, !node.fish.facing);
-                    }
-                }
+        let (pos, facing) = (self.pos(), self.facing());
+        if let Some(projectile) = &def.projectile {
+            let mut bullets = scene::find_node_by_type::<crate::nodes::Bullets>().unwrap();
+            bullets.spawn_bullet(pos, facing, def.id, projectile, self.team());
+        }
+        if let Some(melee) = &def.melee {
+            let hit_box = melee.hit_box.facing(pos, facing);
+
+            // a swing meeting another fresh one clashes instead of hitting
+            let clash = scene::find_nodes_by_type::<crate::nodes::RemotePlayer>()
+                .find(|other| {
+                    other
+                        .parry_box()
+                        .map_or(false, |parry| parry.overlaps(&hit_box))
+                })
+                .map(|other| (other.id.clone(), other.pos()));
+            if let Some((id, other)) = clash {
+                nakama.clash(&id, (pos + other) / 2. + vec2(10., 32.));
+                self.state.clash();
+                return;
             }
 
-            for i in 0u32..3 {
+            for other in scene::find_nodes_by_type::<crate::nodes::RemotePlayer>() {
+                let spared = simulation::spares(nakama.friendly_fire, self.team(), other.team());
+                if spared == false
+                    && other.dead == false
+                    && simulation::fish_hit_box(other.pos()).overlaps(&hit_box)
                 {
-                    let node = &mut *scene::get_node(handle);
-                    node.fish.sword_sprite.set_frame(i);
+                    nakama.damage(&other.id, !facing, melee.damage, melee.knockback);
                 }
-
-                wait_seconds(0.08).await;
             }
-
-            {
-                let mut node = scene::get_node(handle);
-                node.fish.sword_sprite.set_animation(0);
-            }
-
-            let node = &mut *scene::get_node(handle);
-            node.state_machine.set_state(Self::ST_NORMAL);
-        };
-
-        start_coroutine(coroutine)
+        }
     }
 
-    fn update_sword_shoot(node: &mut RefMut<Player>, _dt: f32) {
-        node.fish.speed.x *= 0.9;
+    /// Let go of the weapon, it flies until it lands as a pickup.
+    fn throw_weapon(&mut self) {
+        let weapon = match self.state.weapon.take() {
+            Some(weapon) => weapon,
+            None => return,
+        };
+        let mut nakama = scene::get_node(self.nakama_realtime);
+        nakama.throw(self.pos(), self.facing(), weapon);
     }
 
-    fn update_aftermatch(node: &mut RefMut<Player>, _dt: f32) {
+    fn draw_aftermatch(&mut self) {
         let resources = storage::get::<crate::gui::GuiResources>();
-        let nakama = &mut scene::get_node(node.nakama).api_client;
 
-        node.fish.speed.x = 0.0;
+        if self.win && !self.leaderboard_written {
+            let nakama = &mut scene::get_node(self.nakama).api_client;
+            nakama.write_leaderboard_record("fish_game_macroquad_wins", 1);
+            self.leaderboard_written = true;
+        }
 
         ui::root_ui().push_skin(&resources.login_skin);
         ui::root_ui().window(
@@ -586,85 +577,165 @@ impl Player {
                 screen_height() / 2. - 200. / 2.,
             ),
             Vec2::new(500., 200.),
-            move |ui| {
-                if node.win {
+            |ui| {
+                if self.win {
                     ui.label(vec2(190., 30.), "You win!");
-                    if !node.leaderboard_written {
-                        nakama.write_leaderboard_record("fish_game_macroquad_wins", 1);
-                        node.leaderboard_written = true;
-                    }
                 } else {
                     ui.label(vec2(190., 30.), "You lost!");
                 }
                 if ui.button(vec2(130., 60.), "Return to lobby") {
-                    node.want_quit = true;
+                    self.want_quit = true;
                 }
             },
         );
         ui::root_ui().pop_skin();
     }
+}
 
-    fn update_normal(node: &mut RefMut<Player>, _dt: f32) {
-        // self destruct, for debugging only
-        if is_key_pressed(KeyCode::Y) {
-            node.kill(true);
-        }
-        if is_key_pressed(KeyCode::U) {
-            node.kill(false);
-        }
-
-        let node = &mut **node;
-        let fish = &mut node.fish;
-
-        if fish.input.right {
-            fish.fish_sprite.set_animation(1);
-            fish.speed.x = consts::RUN_SPEED;
-            fish.facing = true;
-        } else if fish.input.left {
-            fish.fish_sprite.set_animation(1);
-            fish.speed.x = -consts::RUN_SPEED;
-            fish.facing = false;
-        } else {
-            fish.fish_sprite.set_animation(0);
-            fish.speed.x = 0.;
-        }
+/// Health, armor, ammo, lives (with `stock`) and power-up of `fish`, drawn at `pos`.
+pub fn draw_hud(pos: Vec2, fish: &simulation::Fish, stock: bool) {
+    if fish.is_dead() {
+        return;
+    }
+    if let Some(weapon) = fish.weapon {
+        draw_ammo(pos, weapon);
+    }
+    draw_health(pos, fish.health, fish.armor);
+    if stock {
+        draw_lives(pos, fish.lives);
+    }
+    if let Some(power_up) = fish.power_up {
+        draw_power_up(pos, power_up, fish.power_up_time);
+    }
+}
 
-        if fish.input.jump {
-            if node.jump_grace_timer > 0. {
-                node.jump_grace_timer = 0.0;
-                fish.jump();
+/// Sounds and particles of the simulation `events`, `fish` are the fish they refer to.
+pub fn play_events(events: &[Event], fish: &[simulation::Fish]) {
+    let mut resources = storage::get_mut::<Resources>();
+
+    for event in events {
+        match *event {
+            Event::Jump { .. } => audio::play_sound(
+                resources.jump_sound,
+                audio::PlaySoundParams {
+                    looped: false,
+                    volume: 0.6,
+                },
+            ),
+            Event::Attack { weapon, .. } => play_sound_once(resources.weapon_sounds[&weapon]),
+            Event::BulletHit { pos } => resources.hit_fxses.spawn(pos),
+            Event::Blast { pos } => resources.explosion_fxses.spawn(pos),
+            Event::Deflect { pos, .. } | Event::Clash { pos } => resources.hit_fxses.spawn(pos),
+            Event::Explode { fish: n } => resources
+                .explosion_fxses
+                .spawn(fish[n].pos() + vec2(15., 33.)),
+            Event::OutOfAmmo { fish: n } => {
+                resources.disarm_fxses.spawn(fish[n].pos() + vec2(16., 33.))
             }
+            Event::Splash { pos } => resources.splash_fxses.spawn(pos),
+            Event::TileBroken { pos, .. } => resources.tile_break_fxses.spawn(pos),
+            Event::PickupTaken { .. }
+            | Event::FlagTaken { .. }
+            | Event::FlagCaptured { .. }
+            | Event::HillTaken { .. } => play_sound_once(resources.pickup_sound),
+            _ => {}
         }
+    }
+}
 
-        if fish.input.fire {
-            match fish.weapon {
-                Some(Weapon::Gun { .. }) => node.state_machine.set_state(Self::ST_SHOOT),
-                Some(Weapon::Sword) => node.state_machine.set_state(Self::ST_SWORD_SHOOT),
-                None => {}
-            }
-        }
+/// Health and armor bars, drawn above the ammo.
+pub fn draw_health(pos: Vec2, health: i32, armor: i32) {
+    let (x, y, w, h) = (pos.x - 5., pos.y - 18., 40., 4.);
+    let health = health as f32 / simulation::MAX_HEALTH as f32;
+    let armor = armor as f32 / simulation::MAX_ARMOR as f32;
+
+    draw_rectangle(x, y, w, h, Color::new(0.2, 0.0, 0.0, 0.6));
+    draw_rectangle(x, y, w * health, h, Color::new(0.9, 0.2, 0.2, 1.0));
+    if armor > 0. {
+        draw_rectangle(x, y - h, w * armor, h - 1., Color::new(0.4, 0.7, 1.0, 1.0));
     }
+}
 
-    fn draw_hud(&self) {
-        if self.is_dead() {
-            return;
-        }
-        if let Some(Weapon::Gun { bullets }) = self.fish.weapon {
-            let full_color = Color::new(0.8, 0.9, 1.0, 1.0);
-            let empty_color = Color::new(0.8, 0.9, 1.0, 0.8);
-            for i in 0..3 {
-                let x = self.fish.pos.x + 15.0 * i as f32;
-
-                if i >= bullets {
-                    draw_circle_lines(x, self.fish.pos.y - 4.0, 4.0, 2., empty_color);
-                } else {
-                    draw_circle(x, self.fish.pos.y - 4.0, 4.0, full_color);
-                };
-            }
-        }
+/// Lives left, drawn next to the health bar.
+pub fn draw_lives(pos: Vec2, lives: u8) {
+    draw_text(
+        &format!("x{}", lives),
+        pos.x + 38.,
+        pos.y - 13.,
+        14.,
+        Color::new(0.9, 0.2, 0.2, 1.0),
+    );
+}
+
+/// Name shown above a fish, with its lives left.
+pub fn name_tag(username: &str, lives: u8) -> String {
+    format!("{} x{}", username, lives)
+}
+
+/// Time left of the power-up, drawn above the health and armor bars.
+pub fn draw_power_up(pos: Vec2, power_up: PowerUp, time: f32) {
+    let (x, y, w, h) = (pos.x - 5., pos.y - 30., 40., 4.);
+    let color = pickup::power_up_color(power_up);
+
+    draw_rectangle(x, y, w * time / simulation::POWER_UP_TIME, h, color);
+    draw_text(
+        &format!("{}", time.ceil() as i32),
+        x + w + 3.,
+        y + h + 1.,
+        14.,
+        color,
+    );
+}
+
+/// Attacks left in the weapon, drawn above the fish.
+/// Nothing is drawn for weapons with unlimited ammo.
+pub fn draw_ammo(pos: Vec2, weapon: Weapon) {
+    let resources = storage::get::<Resources>();
+    let max_ammo = resources.weapons.get(weapon.id).map_or(0, |def| def.ammo);
+
+    let full_color = Color::new(0.8, 0.9, 1.0, 1.0);
+    let empty_color = Color::new(0.8, 0.9, 1.0, 0.8);
+    for i in 0..max_ammo {
+        let x = pos.x + 15.0 * i as f32;
+
+        if i >= weapon.ammo {
+            draw_circle_lines(x, pos.y - 4.0, 4.0, 2., empty_color);
+        } else {
+            draw_circle(x, pos.y - 4.0, 4.0, full_color);
+        };
     }
 }
 
+/// Keyboard and on-screen touch controls merged together.
+pub fn read_input() -> Input {
+    let mut input = Input {
+        jump: is_key_pressed(KeyCode::Space)
+            || is_key_pressed(KeyCode::W)
+            || is_key_pressed(KeyCode::Up),
+        fire: is_key_pressed(KeyCode::LeftControl) || is_key_pressed(KeyCode::F),
+        left: is_key_down(KeyCode::Left) || is_key_down(KeyCode::A),
+        right: is_key_down(KeyCode::Right) || is_key_down(KeyCode::D),
+        throw: is_key_pressed(KeyCode::G),
+        dash: is_key_pressed(KeyCode::LeftShift) || is_key_pressed(KeyCode::E),
+        up: is_key_down(KeyCode::Up) || is_key_down(KeyCode::W),
+        down: is_key_down(KeyCode::Down) || is_key_down(KeyCode::S),
+    };
+
+    if let Some(touch_controls) = scene::find_node_by_type::<TouchControls>() {
+        let touch = touch_controls.input();
+
+        input.jump |= touch.jump;
+        input.fire |= touch.fire;
+        input.throw |= touch.throw;
+        input.dash |= touch.dash;
+        input.left |= touch.left;
+        input.right |= touch.right;
+        input.up |= touch.up;
+        input.down |= touch.down;
+    }
+    input
+}
+
 impl scene::Node for Player {
     fn draw(mut node: RefMut<Self>) {
         //     let sword_hit_box = if node.fish.facing {
@@ -679,84 +750,159 @@ impl scene::Node for Player {
         //         sword_hit_box.h,
         //         RED,
         //     );
-        node.fish.draw();
+        if node.state.state != FishState::Eliminated {
+            node.fish.draw();
+        }
 
-        node.draw_hud();
+        draw_hud(node.fish.pos(), &node.state, node.game_type.has_lives());
     }
 
     fn update(mut node: RefMut<Self>) {
-        let game_started = scene::find_node_by_type::<crate::nodes::NakamaRealtimeGame>()
-            .unwrap()
-            .game_started();
-
-        if game_started {
-            node.fish.input.jump = is_key_pressed(KeyCode::Space)
-                || is_key_pressed(KeyCode::W)
-                || is_key_pressed(KeyCode::Up);
-            node.fish.input.fire =
-                is_key_pressed(KeyCode::LeftControl) || is_key_pressed(KeyCode::F);
-            node.fish.input.left = is_key_down(KeyCode::Left) || is_key_down(KeyCode::A);
-            node.fish.input.right = is_key_down(KeyCode::Right) || is_key_down(KeyCode::D);
+        let node = &mut *node;
+        let (game_started, game_type, movement, lives, team, scores) = {
+            let nakama = scene::get_node(node.nakama_realtime);
+            node.friendly_fire = nakama.friendly_fire;
+            node.spawn_pick = nakama.local_index();
+            (
+                nakama.game_started(),
+                nakama.game_type,
+                nakama.movement,
+                nakama.lives,
+                nakama.team,
+                nakama.scores,
+            )
+        };
+        node.game_type = game_type;
+        node.movement = movement;
+        node.state.team = team;
+        if game_started == false {
+            node.state.lives = lives;
         }
 
-        // win condition
-        if node.deathmatch == false && game_started {
-            let others = scene::find_nodes_by_type::<crate::nodes::RemotePlayer>();
-            let alive_enemies = others.filter(|player| player.dead == false).count();
+        // win condition of the objective modes, decided by the scores the host sends
+        if game_type.is_objective() && game_started && node.aftermatch == false {
+            if let Some(winner) = simulation::objective_winner(game_type, scores) {
+                node.win = team == Some(winner);
+                node.aftermatch = true;
+            }
+        } else if game_type != GameType::Deathmatch && game_started && node.aftermatch == false {
+            // the last team standing wins together
+            let others = scene::find_nodes_by_type::<crate::nodes::RemotePlayer>()
+                .filter(|player| player.in_match());
+            let (allies, alive_enemies) = others.fold((0, 0), |(allies, enemies), player| {
+                if team.is_some() && player.team() == team {
+                    (allies + 1, enemies)
+                } else {
+                    (allies, enemies + 1)
+                }
+            });
 
-            if node.fish.dead {
+            if node.state.in_match() == false && allies == 0 {
                 node.win = false;
-                node.state_machine.set_state(Self::ST_AFTERMATCH);
+                node.aftermatch = true;
             }
 
             if alive_enemies == 0 {
                 node.win = true;
-                node.state_machine.set_state(Self::ST_AFTERMATCH);
+                node.aftermatch = true;
             }
         }
 
-        {
-            let node = &mut *node;
-            let fish = &mut node.fish;
-
-            let mut resources = storage::get_mut::<Resources>();
-            fish.pos = resources.collision_world.actor_pos(fish.collider);
+        // the fish stands still before the start and once the match is over
+        let input = match game_started && node.aftermatch == false {
+            true => read_input(),
+            false => Input::default(),
+        };
+        node.pending = Input {
+            jump: node.pending.jump || input.jump,
+            fire: node.pending.fire || input.fire,
+            throw: node.pending.throw || input.throw,
+            dash: node.pending.dash || input.dash,
+            ..input
+        };
 
-            fish.on_ground = resources
-                .collision_world
-                .collide_check(fish.collider, fish.pos + vec2(0., 1.));
+        // self destruct, for debugging only
+        if is_key_pressed(KeyCode::Y) {
+            node.kill(true);
+        }
+        if is_key_pressed(KeyCode::U) {
+            node.kill(false);
+        }
 
-            if fish.on_ground == false {
-                fish.speed.y += consts::GRAVITY * get_frame_time();
+        let weapons = storage::get::<Resources>().weapons.clone();
+        let time = scene::find_node_by_type::<Movers>().map_or(0., |movers| movers.time());
+        // back to the start in the lobby, nothing to carry the fish along
+        if time < node.movers_time {
+            node.movers_time = time;
+        }
+        let from = node.movers_time;
+        let ticks = node.timestep.advance(get_frame_time());
+        let mut events = vec![];
+
+        for tick in 0..ticks {
+            node.prev_pos = node.state.pos();
+
+            // the movers got to `time` this frame, the fish follows them tick by tick
+            let now = from + (time - from) * (tick + 1) as f32 / ticks as f32;
+
+            let step = {
+                let resources = storage::get::<Resources>();
+                // nothing survives a crusher
+                node.state.carry(&resources.level, node.movers_time, now);
+                node.movers_time = now;
+
+                let arena = Arena {
+                    map: &resources.level,
+                    time: now,
+                    movement: node.movement,
+                    weapons: &weapons,
+                };
+                node.state.step(&arena, 0, node.pending, &mut events)
+            };
+            node.pending.jump = false;
+            node.pending.fire = false;
+            node.pending.throw = false;
+            node.pending.dash = false;
+
+            if let Some(def) = step.attack.and_then(|id| weapons.get(id)) {
+                node.state.attack(def, 0, &mut events);
+                node.attack(def);
             }
-
-            if fish.on_ground {
-                node.jump_grace_timer = consts::JUMP_GRACE_TIME;
-            } else if node.jump_grace_timer > 0. {
-                node.jump_grace_timer -= get_frame_time();
+            if step.throw {
+                node.throw_weapon();
             }
-
-            resources
-                .collision_world
-                .move_h(fish.collider, fish.speed.x * get_frame_time());
-            if !resources
-                .collision_world
-                .move_v(fish.collider, fish.speed.y * get_frame_time())
-            {
-                fish.speed.y = 0.0;
+            if step.respawn {
+                let pos = node.safe_spawn(&storage::get::<Resources>().level);
+                node.state.respawn(pos, node.game_type.has_lives());
             }
         }
-        StateMachine::update_detached(&mut node, |node| &mut node.state_machine);
+        play_events(&events, std::slice::from_ref(&node.state));
+
+        // whatever killed the fish, bullets and swings of the others included
+        let dead = node.state.is_dead();
+        if dead && node.was_dead == false {
+            let mut nakama = scene::get_node(node.nakama_realtime);
+            // counted by the host like the deaths of the others
+            let counted = nakama.is_host() && nakama.game_type.is_objective() == false;
+            if let (Some(team), true) = (node.team(), counted) {
+                nakama.scores[team.other().index()] += 1;
+            }
+        }
+        node.was_dead = dead;
 
         for pickup in scene::find_nodes_by_type::<Pickup>() {
-            let collide = |player: Vec2, pickup: Vec2| {
-                (player + vec2(16., 32.)).distance(pickup + vec2(16., 16.)) < 90.
-            };
-
-            if collide(node.pos(), pickup.pos) {
-                node.pick_weapon(pickup.item_type);
+            if node.state.can_take(pickup.pos) {
+                node.state.take(pickup.item, pickup.ammo, &weapons);
+                play_sound_once(storage::get::<Resources>().pickup_sound);
                 pickup.delete();
             }
         }
+
+        let pos = node.prev_pos.lerp(node.state.pos(), node.timestep.alpha());
+        node.fish.sync(&node.state, pos);
+
+        if node.aftermatch {
+            node.draw_aftermatch();
+        }
     }
 }
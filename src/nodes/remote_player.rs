@@ -1,17 +1,19 @@
 use macroquad::{
     experimental::{
         collections::storage,
-        coroutines::{start_coroutine, wait_seconds},
-        scene::{self, Handle, RefMut},
+        scene::{self, RefMut},
     },
     prelude::*,
 };
 
 use crate::{
     nodes::{
-        pickup::ItemType,
-        player::{Fish, Weapon},
+        player::{self, Fish},
+        Movers,
     },
+    simulation::{self, Body, Move, PowerUp, Team},
+    timestep::FixedTimestep,
+    weapons::WeaponId,
     Resources,
 };
 
@@ -22,8 +24,16 @@ pub struct RemotePlayer {
 
     pub dead: bool,
     pub ready: bool,
+    /// Lives left in `LastFishStanding`, 0 in deathmatch
+    pub lives: u8,
+    // rollback netcode draws all the fish by itself
+    pub visible: bool,
     pos_delta: Vec2,
     last_move_time: f64,
+    // seconds into the attack shown, ticked like the simulation does
+    swing: Option<f32>,
+    timestep: FixedTimestep,
+    movement: Move,
 }
 
 impl RemotePlayer {
@@ -36,25 +46,39 @@ impl RemotePlayer {
             id: id.to_string(),
             pos_delta: vec2(0.0, 0.0),
             last_move_time: 0.0,
+            swing: None,
+            timestep: FixedTimestep::new(),
+            movement: Move::None,
             ready: false,
             dead: false,
+            lives: 0,
+            visible: true,
         }
     }
 
-    pub fn pick_weapon(&mut self, item_type: ItemType) {
-        self.fish.pick_weapon(item_type)
+    pub fn pick_weapon(&mut self, weapon: WeaponId) {
+        self.fish.pick_weapon(weapon, None)
     }
 
     pub fn disarm(&mut self) {
         self.fish.disarm()
     }
 
-    pub fn weapon(&self) -> Option<ItemType> {
-        match self.fish.weapon {
-            None => None,
-            Some(Weapon::Gun { .. }) => Some(ItemType::Gun),
-            Some(Weapon::Sword) => Some(ItemType::Sword),
-        }
+    pub fn weapon(&self) -> Option<WeaponId> {
+        self.fish.weapon.map(|weapon| weapon.id)
+    }
+
+    /// Reach of a melee swing still within `simulation::PARRY_TIME`, as far as we know.
+    pub fn parry_box(&self) -> Option<Rect> {
+        let resources = storage::get::<Resources>();
+
+        simulation::parry_box(
+            &resources.weapons,
+            self.weapon()?,
+            self.swing?,
+            self.pos(),
+            self.fish.facing(),
+        )
     }
 
     pub fn set_pos(&mut self, pos: Vec2) {
@@ -67,6 +91,27 @@ impl RemotePlayer {
         self.fish.set_facing(facing);
     }
 
+    pub fn set_move(&mut self, movement: Move) {
+        self.movement = movement;
+    }
+
+    pub fn team(&self) -> Option<Team> {
+        self.fish.team
+    }
+
+    pub fn set_team(&mut self, team: Option<Team>) {
+        self.fish.team = team;
+    }
+
+    pub fn set_power_up(&mut self, power_up: Option<PowerUp>) {
+        self.fish.power_up = power_up;
+    }
+
+    /// Not eliminated yet, even if dying right now.
+    pub fn in_match(&self) -> bool {
+        self.dead == false || self.lives > 0
+    }
+
     pub fn set_dead(&mut self, dead: bool) {
         self.dead = dead;
     }
@@ -75,48 +120,68 @@ impl RemotePlayer {
         self.fish.pos()
     }
 
-    pub fn shoot(&mut self, handle: Handle<Self>) {
-        match self.fish.weapon {
-            Some(Weapon::Gun { .. }) => {
-                let mut bullets = scene::find_node_by_type::<crate::nodes::Bullets>().unwrap();
-                bullets.spawn_bullet(self.pos(), self.fish.facing());
-            }
-            Some(Weapon::Sword) => {
-                let swing = async move {
-                    {
-                        if let Some(mut node) = scene::try_get_node(handle) {
-                            node.fish.sword_sprite.set_animation(1);
-                        }
-                    }
-
-                    for i in 0u32..3 {
-                        {
-                            if let Some(mut node) = scene::try_get_node(handle) {
-                                node.fish.sword_sprite.set_frame(i);
-                            }
-                        }
-
-                        wait_seconds(0.08).await;
-                    }
-
-                    {
-                        if let Some(mut node) = scene::try_get_node(handle) {
-                            node.fish.sword_sprite.set_animation(0);
-                        }
-                    }
-                };
-                start_coroutine(swing);
-            }
-            None => {
-                println!("well");
+    pub fn facing(&self) -> bool {
+        self.fish.facing()
+    }
+
+    pub fn shoot(&mut self) {
+        let def = {
+            let resources = storage::get::<Resources>();
+            match self
+                .weapon()
+                .and_then(|weapon| resources.weapons.get(weapon))
+            {
+                Some(def) => def.clone(),
+                None => {
+                    println!("well");
+                    return;
+                }
             }
+        };
+
+        self.swing = Some(0.);
+
+        if let Some(projectile) = &def.projectile {
+            let mut bullets = scene::find_node_by_type::<crate::nodes::Bullets>().unwrap();
+            bullets.spawn_bullet(
+                self.pos(),
+                self.fish.facing(),
+                def.id,
+                projectile,
+                self.team(),
+            );
         }
     }
+
+    /// Advance the attack shown by `ticks`, over once the weapon cooled down.
+    fn update_swing(&mut self, ticks: usize) {
+        let resources = storage::get::<Resources>();
+        let def = self
+            .weapon()
+            .and_then(|weapon| resources.weapons.get(weapon));
+
+        self.swing = self
+            .swing
+            .map(|timer| timer + ticks as f32 * FixedTimestep::DT)
+            .filter(|timer| def.map_or(false, |def| *timer < def.cooldown));
+        let frame = self
+            .swing
+            .and_then(|timer| def.map(|def| def.attack_frame(timer)));
+        self.fish.set_attack_frame(frame);
+    }
 }
 impl scene::Node for RemotePlayer {
     fn draw(mut node: RefMut<Self>) {
+        if node.visible == false {
+            return;
+        }
+
+        let name = match node.lives {
+            0 => node.username.clone(),
+            lives => player::name_tag(&node.username, lives),
+        };
         draw_text_ex(
-            &node.username,
+            &name,
             node.fish.pos().x - 1.,
             node.fish.pos().y - 1.,
             TextParams {
@@ -130,11 +195,24 @@ impl scene::Node for RemotePlayer {
     }
 
     fn update(mut node: RefMut<Self>) {
+        if node.visible {
+            node.fish.update_water();
+        }
+
+        let ticks = node.timestep.advance(get_frame_time());
+        node.update_swing(ticks);
+
         if node.dead {
+            let time = scene::find_node_by_type::<Movers>().map_or(0., |movers| movers.time());
             let resources = storage::get::<Resources>();
-            let on_ground = resources
-                .collision_world
-                .collide_check(node.fish.collider, node.fish.pos() + vec2(0., 1.));
+            let level = &resources.level;
+            let body = Body::new(
+                node.fish.pos(),
+                simulation::FISH_WIDTH,
+                simulation::FISH_HEIGHT,
+            );
+            let on_ground = body.collide_check(level, &level.solids(time), body.pos + vec2(0., 1.))
+                || body.on_platform(level);
 
             if on_ground {
                 node.fish.set_animation(3);
@@ -146,5 +224,10 @@ impl scene::Node for RemotePlayer {
         } else {
             node.fish.set_animation(1);
         }
+
+        if node.dead == false {
+            let movement = node.movement;
+            node.fish.set_move(movement);
+        }
     }
 }
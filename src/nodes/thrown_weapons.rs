@@ -0,0 +1,176 @@
+use macroquad::{
+    experimental::{collections::storage, scene::RefMut},
+    prelude::*,
+};
+
+use crate::{
+    nodes::{GlobalEvents, Movers, NakamaRealtimeGame, Player, RemotePlayer},
+    simulation::{self, Item, Team, Weapon, THROW},
+    timestep::FixedTimestep,
+    weapons::{Tint, WeaponId},
+    Resources,
+};
+
+struct Thrown {
+    pos: Vec2,
+    speed: Vec2,
+    lived: f32,
+    weapon: Weapon,
+    // network id of the fish throwing, `None` for the local player
+    thrower: Option<String>,
+    team: Option<Team>,
+    hit: bool,
+}
+
+/// Weapons flying after being thrown, see `simulation::THROW`.
+/// Every client flies all of them, the host turns the landed ones into pickups.
+pub struct ThrownWeapons {
+    player: scene::Handle<Player>,
+    nakama: scene::Handle<NakamaRealtimeGame>,
+    thrown: Vec<Thrown>,
+    timestep: FixedTimestep,
+}
+
+impl ThrownWeapons {
+    pub fn new(
+        player: scene::Handle<Player>,
+        nakama: scene::Handle<NakamaRealtimeGame>,
+    ) -> ThrownWeapons {
+        ThrownWeapons {
+            player,
+            nakama,
+            thrown: vec![],
+            timestep: FixedTimestep::new(),
+        }
+    }
+
+    /// `pos`, `facing` and `team` are of the fish throwing.
+    pub fn throw(
+        &mut self,
+        pos: Vec2,
+        facing: bool,
+        weapon: Weapon,
+        thrower: Option<String>,
+        team: Option<Team>,
+    ) {
+        for (pos, speed) in THROW.spawn(pos, facing) {
+            self.thrown.push(Thrown {
+                pos,
+                speed,
+                lived: 0.,
+                weapon,
+                thrower: thrower.clone(),
+                team,
+                hit: false,
+            });
+        }
+    }
+}
+
+impl scene::Node for ThrownWeapons {
+    fn draw(node: RefMut<Self>) {
+        let alpha = node.timestep.alpha();
+
+        for thrown in &node.thrown {
+            let pos = thrown.pos + thrown.speed * FixedTimestep::DT * alpha;
+            draw_thrown(pos, thrown.lived, thrown.weapon.id);
+        }
+    }
+
+    fn update(mut node: RefMut<Self>) {
+        let (is_host, friendly_fire) = {
+            let nakama = scene::get_node(node.nakama);
+            (nakama.is_host(), nakama.friendly_fire)
+        };
+        let time = scene::find_node_by_type::<Movers>().map_or(0., |movers| movers.time());
+        let mut landed = vec![];
+
+        {
+            let mut resources = storage::get_mut::<Resources>();
+            let mut player = scene::get_node(node.player);
+            let dt = FixedTimestep::DT;
+
+            for _ in 0..node.timestep.advance(get_frame_time()) {
+                let others = scene::find_nodes_by_type::<RemotePlayer>()
+                    .filter(|other| other.dead == false)
+                    .map(|other| (other.id.clone(), other.pos(), other.team()))
+                    .collect::<Vec<_>>();
+
+                for mut thrown in std::mem::take(&mut node.thrown) {
+                    let falling = thrown.speed.y > 0.;
+                    THROW.fly(&mut thrown.pos, &mut thrown.speed, dt, |pos| {
+                        resources.level.blocked_at(pos, time)
+                    });
+                    thrown.lived += dt;
+
+                    // the other fish are hurt on their own clients
+                    let self_hit = thrown.thrower.is_some()
+                        && player.is_dead() == false
+                        && simulation::spares(friendly_fire, thrown.team, player.team()) == false
+                        && simulation::fish_hit_box(player.pos()).contains(thrown.pos);
+                    let hit = self_hit
+                        || others.iter().any(|(id, pos, team)| {
+                            thrown.thrower.as_ref() != Some(id)
+                                && simulation::spares(friendly_fire, thrown.team, *team) == false
+                                && simulation::fish_hit_box(*pos).contains(thrown.pos)
+                        });
+
+                    if hit && thrown.hit == false {
+                        if self_hit {
+                            player.state.hit_by(&THROW, thrown.pos, thrown.speed);
+                        }
+
+                        thrown.hit = true;
+                        thrown.speed.x *= -THROW.bounce;
+                        resources.hit_fxses.spawn(thrown.pos);
+                    }
+
+                    // bounced off the floor
+                    if (falling && thrown.speed.y < 0.) || thrown.lived >= THROW.lifetime {
+                        landed.push((thrown.pos - vec2(16., 24.), thrown.weapon));
+                    } else {
+                        node.thrown.push(thrown);
+                    }
+                }
+            }
+        }
+
+        if is_host {
+            if let Some(mut events) = scene::find_node_by_type::<GlobalEvents>() {
+                for (pos, weapon) in landed {
+                    events.add_item(pos, Item::Weapon(weapon.id), Some(weapon.ammo));
+                }
+            }
+        }
+    }
+}
+
+/// Weapon spinning through the air, `lived` seconds after the throw.
+pub fn draw_thrown(pos: Vec2, lived: f32, id: WeaponId) {
+    let resources = storage::get::<Resources>();
+    let def = match resources.weapons.get(id) {
+        Some(def) => def,
+        None => return,
+    };
+    let sprite = &def.sprite;
+    let Tint { r, g, b } = sprite.tint;
+    let size = vec2(sprite.width as f32, sprite.height as f32) * 0.6;
+
+    draw_texture_ex(
+        resources.weapon_textures[&id],
+        pos.x - size.x / 2.,
+        pos.y - size.y / 2.,
+        Color::new(r, g, b, 1.0),
+        DrawTextureParams {
+            source: Some(Rect::new(
+                0.,
+                (sprite.idle.row * sprite.height) as f32,
+                sprite.width as f32,
+                sprite.height as f32,
+            )),
+            dest_size: Some(size),
+            rotation: lived * 12.,
+            ..Default::default()
+        },
+    );
+}
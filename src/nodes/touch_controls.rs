@@ -0,0 +1,194 @@
+use macroquad::{
+    experimental::scene::{self, Handle, RefMut},
+    prelude::*,
+};
+
+use crate::{nodes::Camera, simulation::Input};
+
+/// On-screen virtual joystick and jump/fire/throw/dash buttons.
+/// Stays hidden until the first touch event, so desktop players
+/// never see it.
+pub struct TouchControls {
+    pub camera: Handle<Camera>,
+
+    active: bool,
+    input: Input,
+    // touch id and the position the joystick was grabbed at
+    joystick: Option<(u64, Vec2)>,
+    joystick_pos: Vec2,
+}
+
+impl TouchControls {
+    const DEAD_ZONE: f32 = 0.25;
+
+    pub fn new() -> TouchControls {
+        TouchControls {
+            camera: Handle::null(),
+            active: false,
+            input: Default::default(),
+            joystick: None,
+            joystick_pos: vec2(0., 0.),
+        }
+    }
+
+    pub fn input(&self) -> Input {
+        self.input
+    }
+
+    fn joystick_radius() -> f32 {
+        screen_height() * 0.12
+    }
+
+    fn button_radius() -> f32 {
+        screen_height() * 0.08
+    }
+
+    fn jump_button() -> Vec2 {
+        let r = Self::button_radius();
+        vec2(screen_width() - r * 1.5, screen_height() - r * 3.5)
+    }
+
+    fn fire_button() -> Vec2 {
+        let r = Self::button_radius();
+        vec2(screen_width() - r * 3.5, screen_height() - r * 1.5)
+    }
+
+    fn throw_button() -> Vec2 {
+        let r = Self::button_radius();
+        vec2(screen_width() - r * 1.5, screen_height() - r * 1.5)
+    }
+
+    fn dash_button() -> Vec2 {
+        let r = Self::button_radius();
+        vec2(screen_width() - r * 3.5, screen_height() - r * 3.5)
+    }
+
+    fn joystick_rest() -> Vec2 {
+        let r = Self::joystick_radius();
+        vec2(r * 1.5, screen_height() - r * 1.5)
+    }
+}
+
+impl scene::Node for TouchControls {
+    fn draw(node: RefMut<Self>) {
+        if node.active == false {
+            return;
+        }
+
+        // controls are drawn in screen space, on top of the level
+        set_default_camera();
+
+        let color = Color::new(1.0, 1.0, 1.0, 0.3);
+        let pressed_color = Color::new(1.0, 1.0, 1.0, 0.6);
+
+        let (origin, stick) = match node.joystick {
+            Some((_, origin)) => (origin, node.joystick_pos),
+            None => (Self::joystick_rest(), Self::joystick_rest()),
+        };
+        let r = Self::joystick_radius();
+        draw_circle_lines(origin.x, origin.y, r, 4., color);
+        draw_circle(stick.x, stick.y, r * 0.4, pressed_color);
+
+        let r = Self::button_radius();
+        let jump = Self::jump_button();
+        draw_circle(jump.x, jump.y, r, color);
+        draw_text("JUMP", jump.x - r * 0.6, jump.y + r * 0.15, r * 0.5, WHITE);
+
+        let fire = Self::fire_button();
+        draw_circle(fire.x, fire.y, r, color);
+        draw_text("FIRE", fire.x - r * 0.6, fire.y + r * 0.15, r * 0.5, WHITE);
+
+        let throw = Self::throw_button();
+        draw_circle(throw.x, throw.y, r, color);
+        draw_text(
+            "THROW",
+            throw.x - r * 0.7,
+            throw.y + r * 0.12,
+            r * 0.4,
+            WHITE,
+        );
+
+        let dash = Self::dash_button();
+        draw_circle(dash.x, dash.y, r, color);
+        draw_text("DASH", dash.x - r * 0.6, dash.y + r * 0.15, r * 0.5, WHITE);
+
+        if let Some(camera) = scene::try_get_node(node.camera) {
+            set_camera(camera.macroquad_camera());
+        }
+    }
+
+    fn update(mut node: RefMut<Self>) {
+        let touches = touches();
+
+        if touches.is_empty() == false {
+            node.active = true;
+        }
+
+        // the buttons are edge triggered, same as is_key_pressed
+        node.input.jump = false;
+        node.input.fire = false;
+        node.input.throw = false;
+        node.input.dash = false;
+
+        for touch in &touches {
+            let started = touch.phase == TouchPhase::Started;
+            let finished = touch.phase == TouchPhase::Ended || touch.phase == TouchPhase::Cancelled;
+
+            if started {
+                let r = Self::button_radius();
+                if touch.position.distance(Self::jump_button()) < r * 1.2 {
+                    node.input.jump = true;
+                    continue;
+                }
+                if touch.position.distance(Self::fire_button()) < r * 1.2 {
+                    node.input.fire = true;
+                    continue;
+                }
+                if touch.position.distance(Self::throw_button()) < r * 1.2 {
+                    node.input.throw = true;
+                    continue;
+                }
+                if touch.position.distance(Self::dash_button()) < r * 1.2 {
+                    node.input.dash = true;
+                    continue;
+                }
+                if node.joystick.is_none() && touch.position.x < screen_width() / 2. {
+                    node.joystick = Some((touch.id, touch.position));
+                    node.joystick_pos = touch.position;
+                }
+            }
+
+            if let Some((id, origin)) = node.joystick {
+                if id != touch.id {
+                    continue;
+                }
+                if finished {
+                    node.joystick = None;
+                    continue;
+                }
+                let r = Self::joystick_radius();
+                let mut delta = touch.position - origin;
+                if delta.length() > r {
+                    delta = delta.normalize() * r;
+                }
+                node.joystick_pos = origin + delta;
+            }
+        }
+
+        // the touch may vanish without an Ended event when the page loses focus
+        if let Some((id, _)) = node.joystick {
+            if touches.iter().any(|touch| touch.id == id) == false {
+                node.joystick = None;
+            }
+        }
+
+        let offset = match node.joystick {
+            Some((_, origin)) => (node.joystick_pos - origin) / Self::joystick_radius(),
+            None => vec2(0., 0.),
+        };
+        node.input.left = offset.x < -Self::DEAD_ZONE;
+        node.input.right = offset.x > Self::DEAD_ZONE;
+        node.input.up = offset.y < -Self::DEAD_ZONE;
+        node.input.down = offset.y > Self::DEAD_ZONE;
+    }
+}
@@ -0,0 +1,1783 @@
+//! Game rules, independent from the engine: no scene nodes, no `storage`,
+//! no textures and no sounds, only `macroquad::math` types.
+//!
+//! `Simulation` takes a `Map` and one `Input` per fish each tick and produces
+//! the new state plus a list of `Event`s for the presentation layer to play
+//! sounds and particles on. The same inputs always give the same state, and the
+//! whole state is `Clone`, so it can be snapshotted and re-simulated.
+
+use macroquad::math::{vec2, Rect, Vec2};
+use std::rc::Rc;
+
+use crate::{
+    consts,
+    timestep::{self, FixedTimestep},
+    weapons::{Offset, Projectile, Tint, WeaponDef, WeaponId, Weapons},
+    GameType,
+};
+
+mod map;
+mod objective;
+pub mod rollback;
+mod spawn;
+
+#[cfg(test)]
+mod tests;
+
+pub use map::{Body, Collision, Map, Mover, MoverKind, PickupZone, TileDamage};
+pub use objective::{
+    objective_winner, Contender, Flag, ObjectiveState, CAPTURES_TO_WIN, HILL_POINTS_TO_WIN,
+};
+pub use spawn::{match_seed, pick_spawn, start_spawns, Bystander};
+
+pub const TICK: f32 = FixedTimestep::DT;
+
+pub const FISH_WIDTH: i32 = 30;
+pub const FISH_HEIGHT: i32 = 54;
+pub const PICKUP_LIFETIME: f32 = 10.0;
+pub const PICKUP_SPAWN_INTERVAL: f32 = 2.0;
+pub const MAX_PICKUPS: usize = 3;
+pub const MAX_HEALTH: i32 = 100;
+pub const MAX_ARMOR: i32 = 50;
+/// Respawned fish can't be hurt for this long
+pub const SPAWN_INVULNERABILITY: f32 = 1.5;
+/// Most lives a fish can start a `LastFishStanding` match with.
+pub const MAX_LIVES: u8 = 7;
+
+// tuned for a 60 fps frame, which is what the simulation ticks at
+const ATTACK_DAMPING: f32 = 0.9;
+// speed kept every 60 fps frame in water
+const WATER_DAMPING: f32 = 0.98;
+const BULLET_WATER_DAMPING: f32 = 0.92;
+
+/// Seconds from the start of a melee swing during which it deflects bullets
+/// and clashes with other swings.
+pub const PARRY_TIME: f32 = 0.15;
+/// Speed clashing fish are pushed apart with.
+pub const CLASH_KNOCKBACK: f32 = 350.;
+
+/// How long a power-up lasts once picked.
+pub const POWER_UP_TIME: f32 = 8.0;
+/// Run speed multiplier of `PowerUp::Speed`.
+pub const SPEED_BOOST: f32 = 1.5;
+/// Jump speed multiplier of `PowerUp::HighJump`.
+pub const JUMP_BOOST: f32 = 1.35;
+
+/// How thrown weapons fly and what they do to the fish they hit.
+/// They land as a pickup when bouncing off the floor, or when `lifetime` is over.
+pub const THROW: Projectile = Projectile {
+    speed: 450.,
+    lifetime: 3.,
+    muzzle: Offset { x: 15., y: 20. },
+    angle: 25.,
+    count: 1,
+    spread: 0.,
+    gravity: 1.,
+    bounce: 0.3,
+    explosion_radius: 0.,
+    pull: 0.,
+    damage: 20,
+    knockback: 400.,
+    radius: 0.,
+    color: Tint {
+        r: 1.,
+        g: 1.,
+        b: 1.,
+    },
+};
+
+/// Buttons of one fish for one tick.
+/// `jump`, `fire`, `throw` and `dash` are presses, not holds, like `is_key_pressed`.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Input {
+    pub jump: bool,
+    pub fire: bool,
+    pub left: bool,
+    pub right: bool,
+    pub throw: bool,
+    pub dash: bool,
+    /// Climbs ladders
+    pub up: bool,
+    /// Climbs down ladders and drops through platforms
+    pub down: bool,
+}
+
+impl Input {
+    pub fn to_bits(self) -> u8 {
+        self.jump as u8
+            | (self.fire as u8) << 1
+            | (self.left as u8) << 2
+            | (self.right as u8) << 3
+            | (self.throw as u8) << 4
+            | (self.dash as u8) << 5
+            | (self.up as u8) << 6
+            | (self.down as u8) << 7
+    }
+
+    pub fn from_bits(bits: u8) -> Input {
+        Input {
+            jump: bits & 1 != 0,
+            fire: bits & 2 != 0,
+            left: bits & 4 != 0,
+            right: bits & 8 != 0,
+            throw: bits & 16 != 0,
+            dash: bits & 32 != 0,
+            up: bits & 64 != 0,
+            down: bits & 128 != 0,
+        }
+    }
+}
+
+/// Movement abilities on top of running and jumping, picked for a match.
+/// None of them by default.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Movement {
+    /// One more jump in the air
+    pub double_jump: bool,
+    /// Slide down a wall while pushing into it, and jump off it
+    pub wall_jump: bool,
+    /// A short burst of speed with a cooldown
+    pub dash: bool,
+}
+
+impl Movement {
+    pub const ALL: Movement = Movement {
+        double_jump: true,
+        wall_jump: true,
+        dash: true,
+    };
+
+    pub fn to_bits(self) -> u8 {
+        self.double_jump as u8 | (self.wall_jump as u8) << 1 | (self.dash as u8) << 2
+    }
+
+    pub fn from_bits(bits: u8) -> Movement {
+        Movement {
+            double_jump: bits & 1 != 0,
+            wall_jump: bits & 2 != 0,
+            dash: bits & 4 != 0,
+        }
+    }
+}
+
+/// Side a fish plays for in a team match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Team {
+    Blue,
+    Green,
+}
+
+impl Team {
+    pub const ALL: [Team; 2] = [Team::Blue, Team::Green];
+
+    /// Index into `Simulation::scores`.
+    pub fn index(self) -> usize {
+        match self {
+            Team::Blue => 0,
+            Team::Green => 1,
+        }
+    }
+
+    pub fn other(self) -> Team {
+        match self {
+            Team::Blue => Team::Green,
+            Team::Green => Team::Blue,
+        }
+    }
+
+    /// 0 stands for no team.
+    pub fn to_u8(team: Option<Team>) -> u8 {
+        team.map_or(0, |team| team.index() as u8 + 1)
+    }
+
+    pub fn from_u8(team: u8) -> Option<Team> {
+        Team::ALL.get((team as usize).checked_sub(1)?).copied()
+    }
+}
+
+/// Whether a hit by someone of the `attacker` team is ignored by the `target` one.
+pub fn spares(friendly_fire: bool, attacker: Option<Team>, target: Option<Team>) -> bool {
+    friendly_fire == false && attacker.is_some() && attacker == target
+}
+
+/// Match options picked by the host before the start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rules {
+    pub movement: Movement,
+    /// Lives of every fish in `LastFishStanding`
+    pub lives: u8,
+    /// Team of every fish, `None` for playing on its own
+    pub teams: Vec<Option<Team>>,
+    /// Teammates can hurt each other
+    pub friendly_fire: bool,
+}
+
+impl Default for Rules {
+    fn default() -> Rules {
+        Rules {
+            movement: Movement::default(),
+            lives: 1,
+            teams: vec![],
+            friendly_fire: false,
+        }
+    }
+}
+
+/// Movement ability a fish is in the middle of, to pick the animation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Move {
+    None,
+    DoubleJump,
+    WallSlide,
+    Dash,
+}
+
+impl Move {
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Move::None => 0,
+            Move::DoubleJump => 1,
+            Move::WallSlide => 2,
+            Move::Dash => 3,
+        }
+    }
+
+    pub fn from_u8(movement: u8) -> Move {
+        match movement {
+            1 => Move::DoubleJump,
+            2 => Move::WallSlide,
+            3 => Move::Dash,
+            _ => Move::None,
+        }
+    }
+}
+
+/// Effect lasting for `POWER_UP_TIME` after being picked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowerUp {
+    /// Runs `SPEED_BOOST` times faster
+    Speed,
+    /// Jumps `JUMP_BOOST` times higher
+    HighJump,
+    /// Takes no damage
+    Shield,
+    /// Weapons don't use up their ammo
+    InfiniteAmmo,
+}
+
+impl PowerUp {
+    pub const ALL: [PowerUp; 4] = [
+        PowerUp::Speed,
+        PowerUp::HighJump,
+        PowerUp::Shield,
+        PowerUp::InfiniteAmmo,
+    ];
+
+    pub fn to_u8(self) -> u8 {
+        match self {
+            PowerUp::Speed => 0,
+            PowerUp::HighJump => 1,
+            PowerUp::Shield => 2,
+            PowerUp::InfiniteAmmo => 3,
+        }
+    }
+
+    pub fn from_u8(power_up: u8) -> Option<PowerUp> {
+        PowerUp::ALL.get(power_up as usize).copied()
+    }
+
+    /// What the weights of pickup zones call it.
+    pub fn name(self) -> &'static str {
+        match self {
+            PowerUp::Speed => "speed",
+            PowerUp::HighJump => "high_jump",
+            PowerUp::Shield => "shield",
+            PowerUp::InfiniteAmmo => "infinite_ammo",
+        }
+    }
+}
+
+/// Run speed of a fish with `power_up`.
+pub fn run_speed(power_up: Option<PowerUp>) -> f32 {
+    match power_up {
+        Some(PowerUp::Speed) => consts::RUN_SPEED * SPEED_BOOST,
+        _ => consts::RUN_SPEED,
+    }
+}
+
+/// Multiplier of every jump speed of a fish with `power_up`.
+pub fn jump_boost(power_up: Option<PowerUp>) -> f32 {
+    match power_up {
+        Some(PowerUp::HighJump) => JUMP_BOOST,
+        _ => 1.,
+    }
+}
+
+/// A fish on a ladder grabs it holding up or down, then holds on to it
+/// until it jumps off or leaves the ladder.
+pub fn climbing(climbing: bool, on_ladder: bool, input: Input) -> bool {
+    on_ladder && (input.up || input.down || (climbing && input.jump == false))
+}
+
+/// Vertical speed of a fish climbing a ladder.
+pub fn climb_speed(input: Input) -> f32 {
+    (input.down as i32 - input.up as i32) as f32 * consts::CLIMB_SPEED
+}
+
+/// Vertical speed of a fish in water after `dt`: gravity pulls less, the water
+/// holds it back and it never sinks faster than `WATER_SINK_SPEED`.
+pub fn swim_speed(speed: f32, dt: f32) -> f32 {
+    let speed = speed + consts::GRAVITY * consts::WATER_GRAVITY * dt;
+    (speed * timestep::damping(WATER_DAMPING, dt)).min(consts::WATER_SINK_SPEED)
+}
+
+/// Speed of a bullet after flying `dt` through water.
+pub fn bullet_water_speed(speed: Vec2, dt: f32) -> Vec2 {
+    speed * timestep::damping(BULLET_WATER_DAMPING, dt)
+}
+
+/// Fly a `projectile` at `pos` with `speed` for one `TICK`, slowed down in water.
+/// Returns false once it hit something solid, `time` seconds into the match.
+pub fn fly_bullet(
+    projectile: &Projectile,
+    pos: &mut Vec2,
+    speed: &mut Vec2,
+    map: &Map,
+    time: f32,
+) -> bool {
+    if map.water_at(*pos) {
+        *speed = bullet_water_speed(*speed, TICK);
+    }
+    projectile.fly(pos, speed, TICK, |pos| map.blocked_at(pos, time))
+}
+
+/// What a pickup gives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Item {
+    Weapon(WeaponId),
+    /// Fills up the armor
+    Armor,
+    PowerUp(PowerUp),
+}
+
+impl Item {
+    /// Power-ups are sent as `POWER_UPS + PowerUp::to_u8`
+    pub const POWER_UPS: u8 = 0xf0;
+
+    /// Weapon ids are never 0, on the wire it stands for armor.
+    /// Ids from 0xf0 up are taken by the power-ups.
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Item::Weapon(id) => id,
+            Item::Armor => 0,
+            Item::PowerUp(power_up) => Self::POWER_UPS + power_up.to_u8(),
+        }
+    }
+
+    pub fn from_u8(item: u8) -> Item {
+        match item {
+            0 => Item::Armor,
+            id if id >= Self::POWER_UPS => match PowerUp::from_u8(id - Self::POWER_UPS) {
+                Some(power_up) => Item::PowerUp(power_up),
+                None => Item::Weapon(id),
+            },
+            id => Item::Weapon(id),
+        }
+    }
+
+    /// Everything a pickup can give.
+    pub fn all(weapons: &Weapons) -> Vec<Item> {
+        weapons
+            .ids()
+            .map(Item::Weapon)
+            .chain(Some(Item::Armor))
+            .chain(PowerUp::ALL.iter().copied().map(Item::PowerUp))
+            .collect()
+    }
+
+    /// What the weights of pickup zones call it, weapons go by their name.
+    pub fn name(self, weapons: &Weapons) -> &str {
+        match self {
+            Item::Weapon(id) => weapons.get(id).map_or("", |def| &def.name),
+            Item::Armor => "armor",
+            Item::PowerUp(power_up) => power_up.name(),
+        }
+    }
+}
+
+/// Roll the item of a pickup showing up in `zone` by its weights, all the items are as
+/// likely outside of zones. `None` when the zone gives nothing.
+pub fn pickup_item(
+    zone: Option<&PickupZone>,
+    weapons: &Weapons,
+    mut gen_range: impl FnMut(i32, i32) -> i32,
+) -> Option<Item> {
+    let weighted = Item::all(weapons)
+        .into_iter()
+        .map(|item| {
+            let weight = zone.map_or(1, |zone| zone.weight(item.name(weapons)));
+            (item, weight as i32)
+        })
+        .collect::<Vec<_>>();
+    let total = weighted.iter().map(|(_, weight)| weight).sum::<i32>();
+    if total <= 0 {
+        return None;
+    }
+
+    let mut roll = gen_range(0, total);
+    for (item, weight) in weighted {
+        if roll < weight {
+            return Some(item);
+        }
+        roll -= weight;
+    }
+    None
+}
+
+/// Weapon in the fins of a fish.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weapon {
+    pub id: WeaponId,
+    /// Attacks left, meaningless for weapons with unlimited ammo
+    pub ammo: i32,
+}
+
+impl Weapon {
+    pub fn new(def: &WeaponDef) -> Weapon {
+        Weapon {
+            id: def.id,
+            ammo: def.ammo,
+        }
+    }
+
+    /// Out of ammo, only good for throwing.
+    pub fn is_empty(&self, def: &WeaponDef) -> bool {
+        def.ammo > 0 && self.ammo <= 0
+    }
+}
+
+/// The part of the fish that may be hit, relative to the fish position.
+pub fn fish_hit_box(pos: Vec2) -> Rect {
+    Rect::new(pos.x, pos.y, 20., 64.)
+}
+
+/// Whether a fish at `pos` is caught in an explosion at `blast`.
+pub fn in_blast(pos: Vec2, blast: Vec2, radius: f32) -> bool {
+    fish_hit_box(pos).center().distance(blast) < radius
+}
+
+pub fn can_pick(fish: Vec2, pickup: Vec2) -> bool {
+    (fish + vec2(16., 32.)).distance(pickup + vec2(16., 16.)) < 90.
+}
+
+/// Reach of a swing of `weapon`, `timer` seconds into it, for as long as it parries.
+/// `None` for the weapons without a melee attack.
+pub fn parry_box(
+    weapons: &Weapons,
+    weapon: WeaponId,
+    timer: f32,
+    pos: Vec2,
+    facing: bool,
+) -> Option<Rect> {
+    if timer >= PARRY_TIME {
+        return None;
+    }
+    let melee = weapons.get(weapon).and_then(|def| def.melee)?;
+    Some(melee.hit_box.facing(pos, facing))
+}
+
+/// The match around a fish, as far as `Fish::step` is concerned.
+#[derive(Clone, Copy)]
+pub struct Arena<'a> {
+    pub map: &'a Map,
+    /// Seconds since the start of the match, the movers are driven by it
+    pub time: f32,
+    pub movement: Movement,
+    pub weapons: &'a Weapons,
+}
+
+/// What a fish goes on to do after a `Fish::step`, it takes more than the fish itself.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+    /// Attacks with the weapon held, it has ammo left
+    pub attack: Option<WeaponId>,
+    /// Lets go of the weapon held
+    pub throw: bool,
+    /// Done lying dead, back to a spawn or out of the match
+    pub respawn: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FishState {
+    Normal,
+    /// Busy using the weapon for its `cooldown`
+    Attacking {
+        timer: f32,
+    },
+    /// Pushed by a hit or yanked by a harpoon, not controllable for a moment
+    Knocked {
+        timer: f32,
+    },
+    /// Pushed off a wall, not controllable for a moment so it doesn't stick right back
+    WallJumping {
+        timer: f32,
+    },
+    /// Flying straight ahead, ignoring gravity
+    Dashing {
+        timer: f32,
+    },
+    /// Knocked back, flying until it lands
+    Dying {
+        timer: f32,
+    },
+    /// Lying on the ground, explodes and respawns after a while
+    Dead {
+        timer: f32,
+    },
+    /// Out of the match, only in `LastFishStanding`
+    Eliminated,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fish {
+    pub body: Body,
+    pub speed: Vec2,
+    pub facing: bool,
+    pub on_ground: bool,
+    pub jump_grace_timer: f32,
+    /// Used the double jump since leaving the ground
+    pub double_jumped: bool,
+    /// The side of the wall it slides down, `true` for the right one
+    pub wall: Option<bool>,
+    /// Time left until the next dash
+    pub dash_cooldown: f32,
+    /// Holds on to a ladder, gravity does not pull it down
+    pub climbing: bool,
+    /// Swims, jumps are swim strokes
+    pub in_water: bool,
+    pub weapon: Option<Weapon>,
+    pub health: i32,
+    /// Absorbs damage before the health does
+    pub armor: i32,
+    /// Time left until the fish can be hurt
+    pub invulnerable: f32,
+    pub power_up: Option<PowerUp>,
+    /// Time left until `power_up` wears off
+    pub power_up_time: f32,
+    /// Deaths left before being eliminated, only used in `LastFishStanding`
+    pub lives: u8,
+    pub team: Option<Team>,
+    pub state: FishState,
+}
+
+impl Fish {
+    const DEAD_EXPLODE_TIME: f32 = 0.5;
+    const DEAD_RESPAWN_TIME: f32 = 1.0;
+    const KNOCK_TIME: f32 = 0.3;
+
+    pub fn new(pos: Vec2) -> Fish {
+        Fish {
+            body: Body::new(pos, FISH_WIDTH, FISH_HEIGHT),
+            speed: vec2(0., 0.),
+            facing: true,
+            on_ground: false,
+            jump_grace_timer: 0.,
+            double_jumped: false,
+            wall: None,
+            dash_cooldown: 0.,
+            climbing: false,
+            in_water: false,
+            weapon: None,
+            health: MAX_HEALTH,
+            armor: 0,
+            invulnerable: 0.,
+            power_up: None,
+            power_up_time: 0.,
+            lives: 1,
+            team: None,
+            state: FishState::Normal,
+        }
+    }
+
+    pub fn pos(&self) -> Vec2 {
+        self.body.pos
+    }
+
+    pub fn facing_dir(&self) -> f32 {
+        if self.facing {
+            1.
+        } else {
+            -1.
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        matches!(
+            self.state,
+            FishState::Dying { .. } | FishState::Dead { .. } | FishState::Eliminated
+        )
+    }
+
+    /// Not eliminated yet, even if dying right now.
+    pub fn in_match(&self) -> bool {
+        self.is_dead() == false || self.lives > 0
+    }
+
+    pub fn current_move(&self) -> Move {
+        match self.state {
+            FishState::Dashing { .. } => Move::Dash,
+            FishState::Normal if self.wall.is_some() => Move::WallSlide,
+            FishState::Normal if self.double_jumped && self.speed.y < 0. => Move::DoubleJump,
+            _ => Move::None,
+        }
+    }
+
+    pub fn is_busy(&self) -> bool {
+        matches!(self.state, FishState::Attacking { .. })
+    }
+
+    /// Reach of a melee swing still within `PARRY_TIME`.
+    pub fn parry_box(&self, weapons: &Weapons) -> Option<Rect> {
+        match (self.state, self.weapon) {
+            (FishState::Attacking { timer }, Some(weapon)) => {
+                parry_box(weapons, weapon.id, timer, self.pos(), self.facing)
+            }
+            _ => None,
+        }
+    }
+
+    /// Speed of a bullet flying at `speed` once its swing sent it back.
+    pub fn deflect(&self, speed: Vec2) -> Vec2 {
+        vec2(self.facing_dir() * speed.x.abs(), speed.y)
+    }
+
+    pub fn kill(&mut self, direction: bool) {
+        if self.is_dead() {
+            return;
+        }
+        self.lives = self.lives.saturating_sub(1);
+        self.facing = direction;
+        self.speed = vec2(-300. * self.facing_dir(), -150.);
+        self.state = FishState::Dying { timer: 0. };
+    }
+
+    /// Fly with `speed` without control for a moment, after a hit or a harpoon.
+    pub fn knock(&mut self, speed: Vec2) {
+        if self.is_dead() {
+            return;
+        }
+        self.speed = speed;
+        if self.state == FishState::Normal {
+            self.state = FishState::Knocked { timer: 0. };
+        }
+    }
+
+    /// Pushed back by the sword it swung into, the swing is over.
+    pub fn clash(&mut self) {
+        if self.is_dead() {
+            return;
+        }
+        self.speed = vec2(-CLASH_KNOCKBACK * self.facing_dir(), -CLASH_KNOCKBACK / 2.);
+        self.state = FishState::Knocked { timer: 0. };
+    }
+
+    /// Take a hit coming from the `direction` side, the armor goes first.
+    /// Returns true if it was lethal.
+    pub fn hurt(&mut self, damage: i32, knockback: f32, direction: bool) -> bool {
+        if self.is_dead() || self.invulnerable > 0. || self.power_up == Some(PowerUp::Shield) {
+            return false;
+        }
+        let absorbed = damage.min(self.armor);
+        self.armor -= absorbed;
+        self.health -= damage - absorbed;
+
+        if self.health <= 0 {
+            self.health = 0;
+            self.kill(direction);
+            return true;
+        }
+        if knockback != 0. {
+            let dir = if direction { 1. } else { -1. };
+            self.knock(vec2(-dir * knockback, -knockback / 2.));
+        }
+        false
+    }
+
+    /// Hit by a `projectile` at `pos` flying with `speed`, returns true if it was lethal.
+    pub fn hit_by(&mut self, projectile: &Projectile, pos: Vec2, speed: Vec2) -> bool {
+        let direction = pos.x > self.pos().x + 10.;
+
+        if self.hurt(projectile.damage, projectile.knockback, direction) {
+            return true;
+        }
+        if projectile.pull != 0. {
+            self.knock(projectile.pull_speed(speed));
+        }
+        false
+    }
+
+    /// Caught in the blast of a `projectile` exploding at `pos`, if close enough.
+    /// Returns true if it was lethal.
+    pub fn blasted(&mut self, projectile: &Projectile, pos: Vec2) -> bool {
+        let direction = pos.x > self.pos().x + 10.;
+
+        in_blast(self.pos(), pos, projectile.explosion_radius)
+            && self.hurt(projectile.damage, projectile.knockback, direction)
+    }
+
+    /// Close enough to the pickup at `pos` to take it, and not in the middle of an attack.
+    pub fn can_take(&self, pos: Vec2) -> bool {
+        self.is_dead() == false && self.is_busy() == false && can_pick(self.pos(), pos)
+    }
+
+    /// `ammo` is left in a weapon thrown before, `None` for a full one.
+    pub fn take(&mut self, item: Item, ammo: Option<i32>, weapons: &Weapons) {
+        match item {
+            Item::Weapon(id) => {
+                self.weapon = weapons.get(id).map(|def| Weapon {
+                    ammo: ammo.unwrap_or(def.ammo),
+                    ..Weapon::new(def)
+                })
+            }
+            Item::Armor => self.armor = MAX_ARMOR,
+            Item::PowerUp(power_up) => {
+                self.power_up = Some(power_up);
+                self.power_up_time = POWER_UP_TIME;
+            }
+        }
+    }
+
+    /// Start using the weapon `def`, its projectiles and swings are up to the caller.
+    pub fn attack(&mut self, def: &WeaponDef, i: usize, events: &mut Vec<Event>) {
+        if def.recoil != 0. {
+            self.speed.x = -def.recoil * self.facing_dir();
+        }
+        self.state = FishState::Attacking { timer: 0. };
+        events.push(Event::Attack {
+            fish: i,
+            weapon: def.id,
+        });
+    }
+
+    /// Back on `pos` with full health, or out of the match in a `has_lives` one
+    /// once the lives are gone. Returns true if it is back.
+    pub fn respawn(&mut self, pos: Vec2, has_lives: bool) -> bool {
+        self.weapon = None;
+        self.speed = vec2(0., 0.);
+        self.health = MAX_HEALTH;
+        self.armor = 0;
+        self.invulnerable = SPAWN_INVULNERABILITY;
+        self.power_up = None;
+        self.power_up_time = 0.;
+
+        // only last fish standing runs out of lives
+        if has_lives && self.lives == 0 {
+            self.state = FishState::Eliminated;
+            return false;
+        }
+        self.body.set_pos(pos);
+        self.state = FishState::Normal;
+        true
+    }
+
+    /// Carried by the solid movers of `map` it rides and pushed by the ones in its way,
+    /// from `before` to `now` seconds into the match. Returns true if one crushed it.
+    pub fn carry(&mut self, map: &Map, before: f32, now: f32) -> bool {
+        let mut crushed = false;
+        if self.state == FishState::Eliminated {
+            return false;
+        }
+
+        for (n, mover) in map.movers.iter().enumerate() {
+            let (from, to) = (mover.rect_at(before), mover.rect_at(now));
+            if mover.solid() == false
+                || from == to
+                || (self.body.rides(&from) || self.body.overlaps(&to)) == false
+            {
+                continue;
+            }
+            let others = map
+                .movers
+                .iter()
+                .enumerate()
+                .filter(|(m, other)| *m != n && other.solid())
+                .map(|(_, other)| other.rect_at(now))
+                .collect::<Vec<_>>();
+            let delta = to.point() - from.point();
+
+            self.body.move_h(map, &others, delta.x);
+            self.body.move_v(map, &others, delta.y, false);
+
+            if self.body.overlaps(&to) && self.is_dead() == false {
+                self.kill(!self.facing);
+                crushed = true;
+            }
+        }
+        crushed
+    }
+
+    /// Move the fish `i` by one `TICK` with `input`. What it does with its weapon
+    /// and when it respawns is up to the caller, who knows about the other fish.
+    pub fn step(&mut self, arena: &Arena, i: usize, input: Input, events: &mut Vec<Event>) -> Step {
+        let Arena {
+            map,
+            time,
+            movement,
+            weapons,
+        } = *arena;
+        let solids = map.solids(time);
+        let fish = self;
+        let mut fire = None;
+        let mut throw = false;
+        let mut respawn = false;
+
+        if fish.state != FishState::Normal {
+            fish.climbing = false;
+        }
+
+        let in_water = map.in_water(fish.pos(), fish.body.width, fish.body.height);
+        if in_water != fish.in_water {
+            fish.in_water = in_water;
+            events.push(Event::Splash {
+                pos: fish.pos() + vec2(FISH_WIDTH as f32 / 2., FISH_HEIGHT as f32 / 2.),
+            });
+        }
+
+        match fish.state {
+            FishState::Normal => {
+                let run_speed = run_speed(fish.power_up);
+                let jump_boost = jump_boost(fish.power_up);
+                let on_ladder = fish.body.touches(map, Collision::Ladder);
+                fish.climbing = climbing(fish.climbing, on_ladder, input);
+
+                if input.right {
+                    fish.speed.x = run_speed;
+                    fish.facing = true;
+                } else if input.left {
+                    fish.speed.x = -run_speed;
+                    fish.facing = false;
+                } else {
+                    fish.speed.x = 0.;
+                }
+
+                // up on a ladder climbs instead of jumping
+                let jump = input.jump && fish.climbing == false;
+
+                if jump && fish.jump_grace_timer > 0. {
+                    fish.jump_grace_timer = 0.;
+                    fish.speed.y = -consts::JUMP_SPEED * jump_boost;
+                    events.push(Event::Jump { fish: i });
+                } else if jump && fish.in_water {
+                    // swims up a bit with every stroke
+                    fish.speed.y = -consts::SWIM_SPEED;
+                    events.push(Event::Jump { fish: i });
+                } else if let (true, Some(wall)) = (jump, fish.wall) {
+                    // away from the wall
+                    fish.facing = !wall;
+                    fish.speed = vec2(
+                        consts::WALL_JUMP_SPEED * fish.facing_dir(),
+                        -consts::JUMP_SPEED * jump_boost,
+                    );
+                    fish.wall = None;
+                    fish.state = FishState::WallJumping { timer: 0. };
+                    events.push(Event::Jump { fish: i });
+                } else if jump && movement.double_jump && fish.double_jumped == false {
+                    fish.double_jumped = true;
+                    fish.speed.y = -consts::DOUBLE_JUMP_SPEED * jump_boost;
+                    events.push(Event::Jump { fish: i });
+                }
+
+                if input.dash && movement.dash && fish.dash_cooldown <= 0. {
+                    fish.dash_cooldown = consts::DASH_COOLDOWN;
+                    fish.state = FishState::Dashing { timer: 0. };
+                    events.push(Event::Dash { fish: i });
+                }
+
+                if input.throw {
+                    throw = fish.weapon.is_some();
+                } else if input.fire {
+                    fire = fish.weapon;
+                }
+            }
+            FishState::Attacking { timer } => {
+                let def = fish.weapon.and_then(|weapon| weapons.get(weapon.id));
+                let cooldown = def.map_or(0., |def| def.cooldown);
+
+                fish.speed.x *= ATTACK_DAMPING;
+                fish.state = FishState::Attacking {
+                    timer: timer + TICK,
+                };
+
+                if timer + TICK >= cooldown {
+                    fish.state = FishState::Normal;
+
+                    let limited = def.map_or(false, |def| def.ammo > 0)
+                        && fish.power_up != Some(PowerUp::InfiniteAmmo);
+                    if let (true, Some(weapon)) = (limited, fish.weapon.as_mut()) {
+                        weapon.ammo -= 1;
+                        if weapon.ammo <= 0 {
+                            events.push(Event::OutOfAmmo { fish: i });
+                        }
+                    }
+                }
+            }
+            FishState::Knocked { timer } => {
+                fish.state = FishState::Knocked {
+                    timer: timer + TICK,
+                };
+                if timer + TICK >= Fish::KNOCK_TIME {
+                    fish.state = FishState::Normal;
+                }
+            }
+            FishState::WallJumping { timer } => {
+                fish.state = FishState::WallJumping {
+                    timer: timer + TICK,
+                };
+                if timer + TICK >= consts::WALL_JUMP_TIME {
+                    fish.state = FishState::Normal;
+                }
+            }
+            FishState::Dashing { timer } => {
+                fish.speed = vec2(consts::DASH_SPEED * fish.facing_dir(), 0.);
+                fish.state = FishState::Dashing {
+                    timer: timer + TICK,
+                };
+                if timer + TICK >= consts::DASH_TIME {
+                    fish.speed.x = consts::RUN_SPEED * fish.facing_dir();
+                    fish.state = FishState::Normal;
+                }
+            }
+            FishState::Dying { timer } => {
+                fish.state = FishState::Dying {
+                    timer: timer + TICK,
+                };
+
+                // give some time for a dead fish to take off the ground
+                if timer + TICK > 0.1 && fish.on_ground {
+                    fish.speed = vec2(0., 0.);
+                    fish.state = FishState::Dead { timer: 0. };
+                }
+            }
+            FishState::Dead { timer } => {
+                fish.state = FishState::Dead {
+                    timer: timer + TICK,
+                };
+
+                if timer < Self::DEAD_EXPLODE_TIME && timer + TICK >= Self::DEAD_EXPLODE_TIME {
+                    events.push(Event::Explode { fish: i });
+                }
+                respawn = timer + TICK >= Self::DEAD_RESPAWN_TIME;
+            }
+            FishState::Eliminated => return Step::default(),
+        }
+
+        fish.invulnerable = (fish.invulnerable - TICK).max(0.);
+        fish.dash_cooldown = (fish.dash_cooldown - TICK).max(0.);
+        if fish.power_up.is_some() {
+            fish.power_up_time -= TICK;
+            if fish.power_up_time <= 0. {
+                fish.power_up = None;
+                fish.power_up_time = 0.;
+            }
+        }
+        let platforms = input.down == false && fish.climbing == false;
+        fish.on_ground = fish
+            .body
+            .collide_check(map, &solids, fish.pos() + vec2(0., 1.))
+            || (platforms && fish.speed.y >= 0. && fish.body.on_platform(map));
+        let dashing = matches!(fish.state, FishState::Dashing { .. });
+        if fish.climbing {
+            fish.speed.y = climb_speed(input);
+        } else if fish.on_ground == false && dashing == false {
+            fish.speed.y = match fish.in_water {
+                true => swim_speed(fish.speed.y, TICK),
+                false => fish.speed.y + consts::GRAVITY * TICK,
+            };
+        }
+
+        // jumps off ladders
+        if fish.on_ground || fish.climbing {
+            fish.jump_grace_timer = consts::JUMP_GRACE_TIME;
+            fish.double_jumped = false;
+        } else if fish.jump_grace_timer > 0. {
+            fish.jump_grace_timer -= TICK;
+        }
+
+        // falling while pushing into a wall
+        let pushing = match (input.left, input.right) {
+            (false, true) => Some(true),
+            (true, false) => Some(false),
+            _ => None,
+        };
+        fish.wall = pushing.filter(|wall| {
+            let dir = if *wall { 1. } else { -1. };
+            movement.wall_jump
+                && fish.state == FishState::Normal
+                && fish.on_ground == false
+                && fish.speed.y > 0.
+                && fish
+                    .body
+                    .collide_check(map, &solids, fish.pos() + vec2(dir, 0.))
+        });
+        if fish.wall.is_some() {
+            fish.speed.y = fish.speed.y.min(consts::WALL_SLIDE_SPEED);
+            fish.double_jumped = false;
+        }
+
+        fish.body.move_h(map, &solids, fish.speed.x * TICK);
+        if fish
+            .body
+            .move_v(map, &solids, fish.speed.y * TICK, platforms)
+            == false
+        {
+            fish.speed.y = 0.;
+        }
+
+        // spikes go through armor and shields
+        if fish.is_dead() == false
+            && fish.invulnerable <= 0.
+            && map.deadly(fish.pos(), fish.body.width, fish.body.height, time)
+        {
+            fish.kill(!fish.facing);
+            events.push(Event::Death {
+                fish: i,
+                killer: None,
+            });
+        }
+
+        let mut attack = None;
+        if let Some(weapon) = fire {
+            // an empty weapon is still good for throwing
+            let empty = weapons
+                .get(weapon.id)
+                .map_or(true, |def| weapon.is_empty(def));
+            if empty {
+                throw = true;
+            } else {
+                attack = Some(weapon.id);
+            }
+        }
+        Step {
+            attack,
+            throw,
+            respawn,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bullet {
+    pub pos: Vec2,
+    pub speed: Vec2,
+    pub lived: f32,
+    pub projectile: Projectile,
+    pub owner: usize,
+}
+
+/// Weapon flying after being thrown, see `THROW`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thrown {
+    pub pos: Vec2,
+    pub speed: Vec2,
+    pub lived: f32,
+    pub weapon: Weapon,
+    pub owner: usize,
+    /// Hits only one fish, then bounces off it
+    pub hit: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pickup {
+    pub id: u32,
+    pub pos: Vec2,
+    pub item: Item,
+    /// Ammo left in a weapon that was thrown, `None` for a full one
+    pub ammo: Option<i32>,
+    pub lived: f32,
+}
+
+/// Something the presentation layer may want to play a sound or an effect for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Jump {
+        fish: usize,
+    },
+    Attack {
+        fish: usize,
+        weapon: WeaponId,
+    },
+    BulletHit {
+        pos: Vec2,
+    },
+    Blast {
+        pos: Vec2,
+    },
+    /// A bullet was sent back by a sword swing, `fish` owns it now
+    Deflect {
+        fish: usize,
+        pos: Vec2,
+    },
+    /// Two swings met, both fish are knocked back
+    Clash {
+        pos: Vec2,
+    },
+    Death {
+        fish: usize,
+        killer: Option<usize>,
+    },
+    Explode {
+        fish: usize,
+    },
+    Respawn {
+        fish: usize,
+    },
+    Dash {
+        fish: usize,
+    },
+    /// The destructible tile `tile`, centered on `pos`, took all the damage it could
+    TileBroken {
+        tile: usize,
+        pos: Vec2,
+    },
+    /// A fish went in or out of the water
+    Splash {
+        pos: Vec2,
+    },
+    /// The weapon ran out of ammo, it can only be thrown now
+    OutOfAmmo {
+        fish: usize,
+    },
+    Throw {
+        fish: usize,
+        weapon: WeaponId,
+    },
+    PickupSpawned {
+        id: u32,
+    },
+    PickupTaken {
+        id: u32,
+        fish: usize,
+    },
+    PickupExpired {
+        id: u32,
+    },
+    /// Fish `fish` grabbed the flag of team `flag`
+    FlagTaken {
+        fish: usize,
+        flag: Team,
+    },
+    /// The carrier of the `flag` team flag died
+    FlagDropped {
+        flag: Team,
+    },
+    /// Touched by its own team or lying around for too long
+    FlagReturned {
+        flag: Team,
+    },
+    /// Fish `fish` brought the `flag` team flag to its own base
+    FlagCaptured {
+        fish: usize,
+        flag: Team,
+    },
+    HillTaken {
+        team: Team,
+    },
+    MatchOver {
+        winner: Option<usize>,
+    },
+}
+
+/// Deterministic splitmix64, so every client rolls the same pickups.
+#[derive(Debug, Clone, PartialEq)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 32) as u32
+    }
+
+    fn gen_range(&mut self, low: i32, high: i32) -> i32 {
+        if high <= low {
+            return low;
+        }
+        low + (self.next_u32() % (high - low) as u32) as i32
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Simulation {
+    pub game_type: GameType,
+    pub tick: u64,
+    pub fish: Vec<Fish>,
+    pub bullets: Vec<Bullet>,
+    pub thrown: Vec<Thrown>,
+    pub pickups: Vec<Pickup>,
+    /// `Some` once the match is over, with the winner, if any
+    pub winner: Option<Option<usize>>,
+    pub movement: Movement,
+    pub friendly_fire: bool,
+    /// Deaths of the other team, or objective points in the objective modes,
+    /// indexed by `Team::index`
+    pub scores: [u32; 2],
+    pub objectives: ObjectiveState,
+    /// Destructible tiles broken so far, in the order they broke
+    pub broken: Vec<usize>,
+
+    weapons: Rc<Weapons>,
+    rng: Rng,
+    pickup_timer: f32,
+    next_pickup_id: u32,
+    // damage taken by the destructible tiles still standing
+    tile_damage: TileDamage,
+    // the map played on without the `broken` tiles, `None` until one breaks
+    terrain: Option<Rc<Map>>,
+}
+
+impl Simulation {
+    pub fn new(
+        map: &Map,
+        weapons: Rc<Weapons>,
+        players: usize,
+        game_type: GameType,
+        seed: u64,
+    ) -> Simulation {
+        let mut rng = Rng::new(seed);
+        let fish = spawn::spread_spawns(map, players, &mut rng)
+            .into_iter()
+            .map(Fish::new)
+            .collect();
+
+        Simulation {
+            game_type,
+            tick: 0,
+            fish,
+            bullets: vec![],
+            thrown: vec![],
+            pickups: vec![],
+            winner: None,
+            movement: Movement::default(),
+            friendly_fire: false,
+            scores: [0; 2],
+            objectives: ObjectiveState::default(),
+            broken: vec![],
+            weapons,
+            rng,
+            pickup_timer: 0.,
+            next_pickup_id: 0,
+            tile_damage: TileDamage::default(),
+            terrain: None,
+        }
+    }
+
+    /// Lives every fish starts a `LastFishStanding` match with, 1 by default.
+    pub fn set_lives(&mut self, lives: u8) {
+        for fish in &mut self.fish {
+            fish.lives = lives;
+        }
+    }
+
+    pub fn set_rules(&mut self, rules: &Rules) {
+        self.movement = rules.movement;
+        self.friendly_fire = rules.friendly_fire;
+        self.set_lives(rules.lives);
+        for (fish, team) in self.fish.iter_mut().zip(&rules.teams) {
+            fish.team = *team;
+        }
+    }
+
+    /// `fish` or a teammate of it won the match.
+    pub fn is_winner(&self, fish: usize) -> bool {
+        match self.winner {
+            Some(Some(winner)) => {
+                winner == fish
+                    || (self.fish[fish].team.is_some()
+                        && self.fish[fish].team == self.fish[winner].team)
+            }
+            _ => false,
+        }
+    }
+
+    /// A hit by fish `attacker` is ignored by fish `target`.
+    fn spares(&self, attacker: usize, target: usize) -> bool {
+        spares(
+            self.friendly_fire,
+            self.fish[attacker].team,
+            self.fish[target].team,
+        )
+    }
+
+    /// The safest spawn for fish `i` from the others still in the match.
+    fn spawn_point(&mut self, map: &Map, i: usize) -> Vec2 {
+        let bystanders = (0..self.fish.len())
+            .filter(|n| *n != i && self.fish[*n].is_dead() == false)
+            .map(|n| Bystander {
+                pos: self.fish[n].pos(),
+                facing: self.fish[n].facing,
+                enemy: self.spares(n, i) == false,
+            })
+            .collect::<Vec<_>>();
+        let pick = self.rng.gen_range(0, map.spawns.len() as i32) as usize;
+
+        pick_spawn(map, &bystanders, pick).unwrap_or_default()
+    }
+
+    /// Advance the match by one `TICK`.
+    /// `inputs` has one entry per fish, missing entries mean no buttons pressed.
+    pub fn step(&mut self, map: &Map, inputs: &[Input]) -> Vec<Event> {
+        let terrain = self.terrain.clone();
+        let map = terrain.as_deref().unwrap_or(map);
+        let mut events = vec![];
+
+        self.tick += 1;
+
+        self.update_movers(map, &mut events);
+        for i in 0..self.fish.len() {
+            let input = inputs.get(i).copied().unwrap_or_default();
+            self.update_fish(map, i, input, &mut events);
+        }
+        self.update_swings(&mut events);
+        self.update_bullets(map, &mut events);
+        self.update_thrown(map, &mut events);
+        self.update_pickups(map, &mut events);
+
+        if self.game_type.is_objective() {
+            let contenders = self.contenders();
+            self.objectives.update(
+                self.game_type,
+                map,
+                &contenders,
+                &mut self.scores,
+                &mut events,
+            );
+        } else {
+            for event in &events {
+                if let Event::Death { fish, .. } = event {
+                    if let Some(team) = self.fish[*fish].team {
+                        self.scores[team.other().index()] += 1;
+                    }
+                }
+            }
+        }
+        self.check_winner(&mut events);
+
+        events
+    }
+
+    /// `map` as played on, without the tiles broken so far.
+    pub fn terrain<'a>(&'a self, map: &'a Map) -> &'a Map {
+        self.terrain.as_deref().unwrap_or(map)
+    }
+
+    /// Deal `damage` to the tile `n` of `map` if it is destructible, breaking it once
+    /// it took its durability.
+    fn damage_tile(&mut self, map: &Map, n: usize, damage: i32, events: &mut Vec<Event>) {
+        // `map` still has the tiles broken earlier in this tick
+        let durability = match self.terrain(map).durability.get(&n) {
+            Some(durability) => *durability,
+            None => return,
+        };
+        if self.tile_damage.deal(n, damage, durability) == false {
+            return;
+        }
+
+        self.broken.push(n);
+        Rc::make_mut(self.terrain.get_or_insert_with(|| Rc::new(map.clone()))).break_tile(n);
+        events.push(Event::TileBroken {
+            tile: n,
+            pos: map.tile_center(n),
+        });
+    }
+
+    /// Seconds since the start of the match, the movers of the map are driven by it.
+    pub fn time(&self) -> f32 {
+        self.tick as f32 * TICK
+    }
+
+    /// Solid movers carry the fish riding them and push the ones in their way,
+    /// fish pushed into a wall are crushed.
+    fn update_movers(&mut self, map: &Map, events: &mut Vec<Event>) {
+        let (before, now) = (self.time() - TICK, self.time());
+
+        for (i, fish) in self.fish.iter_mut().enumerate() {
+            if fish.carry(map, before, now) {
+                events.push(Event::Death {
+                    fish: i,
+                    killer: None,
+                });
+            }
+        }
+    }
+
+    pub fn contenders(&self) -> Vec<Contender> {
+        self.fish
+            .iter()
+            .map(|fish| Contender {
+                pos: fish.pos(),
+                team: fish.team,
+                alive: fish.is_dead() == false,
+            })
+            .collect()
+    }
+
+    fn update_fish(&mut self, map: &Map, i: usize, input: Input, events: &mut Vec<Event>) {
+        let weapons = self.weapons.clone();
+        let arena = Arena {
+            map,
+            time: self.time(),
+            movement: self.movement,
+            weapons: &weapons,
+        };
+        let step = self.fish[i].step(&arena, i, input, events);
+
+        if let Some(id) = step.attack {
+            self.attack(i, id, events);
+        }
+        if step.throw {
+            self.throw(i, events);
+        }
+        if step.respawn {
+            let pos = self.spawn_point(map, i);
+            if self.fish[i].respawn(pos, self.game_type.has_lives()) {
+                events.push(Event::Respawn { fish: i });
+            }
+        }
+    }
+
+    fn attack(&mut self, i: usize, id: WeaponId, events: &mut Vec<Event>) {
+        let weapons = self.weapons.clone();
+        let def = match weapons.get(id) {
+            Some(def) => def,
+            None => return,
+        };
+        let fish = &mut self.fish[i];
+        let (pos, facing) = (fish.pos(), fish.facing);
+        fish.attack(def, i, events);
+
+        if let Some(projectile) = def.projectile {
+            for (pos, speed) in projectile.spawn(pos, facing) {
+                self.bullets.push(Bullet {
+                    pos,
+                    speed,
+                    lived: 0.,
+                    projectile,
+                    owner: i,
+                });
+            }
+        }
+    }
+
+    /// Melee swings started this tick either clash with another swing still in
+    /// its `PARRY_TIME`, or hurt everyone in reach. Resolved after every fish
+    /// moved, so it doesn't matter which one swung first in a tick.
+    fn update_swings(&mut self, events: &mut Vec<Event>) {
+        let weapons = self.weapons.clone();
+
+        for i in 0..self.fish.len() {
+            let fish = &self.fish[i];
+            let melee = match fish.state {
+                FishState::Attacking { timer } if timer == 0. => fish
+                    .weapon
+                    .and_then(|weapon| weapons.get(weapon.id))
+                    .and_then(|def| def.melee),
+                _ => None,
+            };
+            let melee = match melee {
+                Some(melee) => melee,
+                None => continue,
+            };
+            let (pos, facing) = (fish.pos(), fish.facing);
+            let hit_box = melee.hit_box.facing(pos, facing);
+
+            let clash = (0..self.fish.len()).find(|n| {
+                *n != i
+                    && self.fish[*n]
+                        .parry_box(&weapons)
+                        .map_or(false, |other| other.overlaps(&hit_box))
+            });
+            if let Some(n) = clash {
+                let other = self.fish[n].pos();
+                self.fish[i].clash();
+                self.fish[n].clash();
+                events.push(Event::Clash {
+                    pos: (fish_hit_box(pos).center() + fish_hit_box(other).center()) / 2.,
+                });
+                continue;
+            }
+
+            for n in 0..self.fish.len() {
+                let spared = self.spares(i, n);
+                let other = &mut self.fish[n];
+
+                if n != i
+                    && spared == false
+                    && fish_hit_box(other.pos()).overlaps(&hit_box)
+                    && other.hurt(melee.damage, melee.knockback, !facing)
+                {
+                    events.push(Event::Death {
+                        fish: n,
+                        killer: Some(i),
+                    });
+                }
+            }
+        }
+    }
+
+    fn throw(&mut self, i: usize, events: &mut Vec<Event>) {
+        let fish = &mut self.fish[i];
+        let weapon = match fish.weapon.take() {
+            Some(weapon) => weapon,
+            None => return,
+        };
+
+        for (pos, speed) in THROW.spawn(fish.pos(), fish.facing) {
+            self.thrown.push(Thrown {
+                pos,
+                speed,
+                lived: 0.,
+                weapon,
+                owner: i,
+                hit: false,
+            });
+        }
+        events.push(Event::Throw {
+            fish: i,
+            weapon: weapon.id,
+        });
+    }
+
+    fn update_bullets(&mut self, map: &Map, events: &mut Vec<Event>) {
+        let weapons = self.weapons.clone();
+        let mut blasts = vec![];
+        let time = self.time();
+
+        for mut bullet in std::mem::take(&mut self.bullets) {
+            let projectile = bullet.projectile;
+            let flying = fly_bullet(&projectile, &mut bullet.pos, &mut bullet.speed, map, time);
+            bullet.lived += TICK;
+
+            // a swing sends the bullet back, as if fired by the swinging fish
+            let parry = self.fish.iter().enumerate().position(|(n, fish)| {
+                n != bullet.owner
+                    && self.spares(bullet.owner, n) == false
+                    && fish
+                        .parry_box(&weapons)
+                        .map_or(false, |parry| parry.contains(bullet.pos))
+            });
+            if let (Some(n), true) = (parry, flying) {
+                bullet.speed = self.fish[n].deflect(bullet.speed);
+                bullet.owner = n;
+                bullet.lived = 0.;
+                events.push(Event::Deflect {
+                    fish: n,
+                    pos: bullet.pos,
+                });
+                self.bullets.push(bullet);
+                continue;
+            }
+
+            let target = self.fish.iter().enumerate().position(|(n, fish)| {
+                self.spares(bullet.owner, n) == false
+                    && fish.is_dead() == false
+                    && fish_hit_box(fish.pos()).contains(bullet.pos)
+            });
+            let expired = bullet.lived >= projectile.lifetime;
+
+            // explosive projectiles only hurt with the blast
+            if let (Some(target), 0.) = (target, projectile.explosion_radius) {
+                if self.fish[target].hit_by(&projectile, bullet.pos, bullet.speed) {
+                    events.push(Event::Death {
+                        fish: target,
+                        killer: Some(bullet.owner),
+                    });
+                }
+            }
+
+            if projectile.explosion_radius != 0. && (target.is_some() || expired || !flying) {
+                blasts.push(bullet);
+                continue;
+            }
+            if target.is_some() || flying == false {
+                if let (None, Some(tile)) = (target, map.tile_index(bullet.pos)) {
+                    self.damage_tile(map, tile, projectile.damage, events);
+                }
+                events.push(Event::BulletHit { pos: bullet.pos });
+                continue;
+            }
+            if expired == false {
+                self.bullets.push(bullet);
+            }
+        }
+
+        for blast in blasts {
+            events.push(Event::Blast { pos: blast.pos });
+            let projectile = blast.projectile;
+
+            for n in 0..self.fish.len() {
+                if self.spares(blast.owner, n) == false
+                    && self.fish[n].blasted(&projectile, blast.pos)
+                {
+                    events.push(Event::Death {
+                        fish: n,
+                        killer: Some(blast.owner),
+                    });
+                }
+            }
+            for tile in map.destructible_in(blast.pos, projectile.explosion_radius) {
+                self.damage_tile(map, tile, projectile.damage, events);
+            }
+        }
+    }
+
+    fn update_thrown(&mut self, map: &Map, events: &mut Vec<Event>) {
+        let time = self.time();
+
+        for mut thrown in std::mem::take(&mut self.thrown) {
+            let falling = thrown.speed.y > 0.;
+            THROW.fly(&mut thrown.pos, &mut thrown.speed, TICK, |pos| {
+                map.blocked_at(pos, time)
+            });
+            thrown.lived += TICK;
+
+            let target = self.fish.iter().enumerate().position(|(n, fish)| {
+                n != thrown.owner
+                    && self.spares(thrown.owner, n) == false
+                    && fish.is_dead() == false
+                    && fish_hit_box(fish.pos()).contains(thrown.pos)
+            });
+            if let (Some(target), false) = (target, thrown.hit) {
+                let fish = &mut self.fish[target];
+                let hit = fish.hit_by(&THROW, thrown.pos, thrown.speed);
+
+                thrown.hit = true;
+                thrown.speed.x *= -THROW.bounce;
+                events.push(Event::BulletHit { pos: thrown.pos });
+
+                if hit {
+                    events.push(Event::Death {
+                        fish: target,
+                        killer: Some(thrown.owner),
+                    });
+                }
+            }
+
+            // bounced off the floor
+            let landed = falling && thrown.speed.y < 0.;
+            if landed || thrown.lived >= THROW.lifetime {
+                let id = self.next_pickup_id;
+                self.next_pickup_id += 1;
+
+                self.pickups.push(Pickup {
+                    id,
+                    pos: thrown.pos - vec2(16., 24.),
+                    item: Item::Weapon(thrown.weapon.id),
+                    ammo: Some(thrown.weapon.ammo),
+                    lived: 0.,
+                });
+                events.push(Event::PickupSpawned { id });
+            } else {
+                self.thrown.push(thrown);
+            }
+        }
+    }
+
+    fn update_pickups(&mut self, map: &Map, events: &mut Vec<Event>) {
+        for pickup in &mut self.pickups {
+            pickup.lived += TICK;
+        }
+        self.pickups.retain(|pickup| {
+            if pickup.lived >= PICKUP_LIFETIME {
+                events.push(Event::PickupExpired { id: pickup.id });
+                return false;
+            }
+            true
+        });
+
+        self.pickup_timer += TICK;
+        if self.pickup_timer >= PICKUP_SPAWN_INTERVAL && self.pickups.len() < MAX_PICKUPS {
+            self.pickup_timer = 0.;
+
+            let (rng, weapons) = (&mut self.rng, &self.weapons);
+            let spot = map.pickup_spot(|low, high| rng.gen_range(low, high));
+            let item = spot.and_then(|(_, zone)| {
+                pickup_item(zone, weapons, |low, high| rng.gen_range(low, high))
+            });
+
+            if let (Some((pos, _)), Some(item)) = (spot, item) {
+                let id = self.next_pickup_id;
+                self.next_pickup_id += 1;
+
+                self.pickups.push(Pickup {
+                    id,
+                    pos,
+                    item,
+                    ammo: None,
+                    lived: 0.,
+                });
+                events.push(Event::PickupSpawned { id });
+            }
+        }
+
+        let (fish, weapons) = (&mut self.fish, &self.weapons);
+        self.pickups.retain(|pickup| {
+            let taker = fish.iter().position(|fish| fish.can_take(pickup.pos));
+
+            if let Some(taker) = taker {
+                fish[taker].take(pickup.item, pickup.ammo, weapons);
+                events.push(Event::PickupTaken {
+                    id: pickup.id,
+                    fish: taker,
+                });
+                return false;
+            }
+            true
+        });
+    }
+
+    fn check_winner(&mut self, events: &mut Vec<Event>) {
+        if self.winner.is_some() || self.game_type == GameType::Deathmatch {
+            return;
+        }
+
+        if self.game_type.is_objective() {
+            let team = match objective_winner(self.game_type, self.scores) {
+                Some(team) => team,
+                None => return,
+            };
+            let winner = self.fish.iter().position(|fish| fish.team == Some(team));
+
+            self.winner = Some(winner);
+            events.push(Event::MatchOver { winner });
+            return;
+        }
+
+        let mut alive = self
+            .fish
+            .iter()
+            .enumerate()
+            .filter(|(_, fish)| fish.in_match());
+
+        // the last ones left are all on the same team
+        let winner = match alive.next() {
+            None => None,
+            Some((n, fish)) => {
+                if alive.all(|(_, other)| fish.team.is_some() && other.team == fish.team) {
+                    Some(n)
+                } else {
+                    return;
+                }
+            }
+        };
+
+        self.winner = Some(winner);
+        events.push(Event::MatchOver { winner });
+    }
+}
@@ -0,0 +1,633 @@
+use std::collections::BTreeMap;
+
+use macroquad::math::{vec2, Rect, Vec2};
+
+use super::Team;
+use crate::GameType;
+
+/// How a tile stops whatever moves through it, set with the `collision` property
+/// of the Tiled tileset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collision {
+    Empty,
+    /// Blocks fish and bullets from every side
+    Solid,
+    /// Only stops fish falling on it from above, they drop through holding down
+    Platform,
+    /// Kills fish touching it
+    Spikes,
+    /// Climbed holding up or down, the top of a ladder is a platform
+    Ladder,
+    /// Fish sink slowly and swim up with jumps
+    Water,
+}
+
+impl Collision {
+    /// Value of the Tiled `collision` property.
+    pub fn from_name(name: &str) -> Option<Collision> {
+        match name {
+            "none" => Some(Collision::Empty),
+            "solid" => Some(Collision::Solid),
+            "platform" => Some(Collision::Platform),
+            "spikes" => Some(Collision::Spikes),
+            "ladder" => Some(Collision::Ladder),
+            "water" => Some(Collision::Water),
+            _ => None,
+        }
+    }
+}
+
+/// What a mover does, besides pushing and carrying fish around when it is solid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MoverKind {
+    /// Goes back and forth between its start and the end of its path
+    Platform,
+    /// Waits, slams down its path and slowly rises back
+    Crusher,
+    /// Stays in place, kills fish touching it while it is out
+    SpikeTrap,
+}
+
+/// Part of a level moving on its own, where it is only depends on the time
+/// since the start of the match so every client agrees on it without syncing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mover {
+    pub kind: MoverKind,
+    /// Where it is at the start of its cycle
+    pub rect: Rect,
+    /// Offset of the far end of its path
+    pub path: Vec2,
+    /// Seconds of a full cycle
+    pub period: f32,
+    /// Seconds into its cycle at the start of the match
+    pub phase: f32,
+    /// Part of the cycle a spike trap is out, 0..1
+    pub active: f32,
+}
+
+impl Mover {
+    /// Parts of the crusher cycle: waiting up, slamming, waiting down, the rest rising.
+    const CRUSHER_WAIT: f32 = 0.5;
+    const CRUSHER_SLAM: f32 = 0.1;
+    const CRUSHER_DOWN: f32 = 0.1;
+    // no dividing by zero for cycles set to last no time at all
+    const MIN_PERIOD: f32 = 0.1;
+
+    /// Moving along `path` at `speed` pixels per second.
+    pub fn platform(rect: Rect, path: Vec2, speed: f32, phase: f32) -> Mover {
+        let period = match path.length() * 2. / speed {
+            period if period > 0. && period.is_finite() => period,
+            _ => 1.,
+        };
+        Mover {
+            kind: MoverKind::Platform,
+            rect,
+            path,
+            period,
+            phase,
+            active: 0.,
+        }
+    }
+
+    /// Falling by `drop` pixels every `period` seconds.
+    pub fn crusher(rect: Rect, drop: f32, period: f32, phase: f32) -> Mover {
+        Mover {
+            kind: MoverKind::Crusher,
+            rect,
+            path: vec2(0., drop),
+            period: period.max(Self::MIN_PERIOD),
+            phase,
+            active: 0.,
+        }
+    }
+
+    /// Out for `active` seconds every `period` seconds.
+    pub fn spike_trap(rect: Rect, period: f32, active: f32, phase: f32) -> Mover {
+        let period = period.max(Self::MIN_PERIOD);
+        Mover {
+            kind: MoverKind::SpikeTrap,
+            rect,
+            path: vec2(0., 0.),
+            period,
+            phase,
+            active: (active / period).clamp(0., 1.),
+        }
+    }
+
+    pub fn solid(&self) -> bool {
+        self.kind != MoverKind::SpikeTrap
+    }
+
+    /// How far into its cycle it is at `time`, 0..1
+    fn cycle(&self, time: f32) -> f32 {
+        ((time + self.phase) / self.period).rem_euclid(1.)
+    }
+
+    /// How far along its path it is at `time`, 0..1
+    fn progress(&self, time: f32) -> f32 {
+        let t = self.cycle(time);
+
+        match self.kind {
+            MoverKind::Platform => 1. - (t * 2. - 1.).abs(),
+            MoverKind::Crusher => {
+                let slam = Self::CRUSHER_WAIT + Self::CRUSHER_SLAM;
+                let down = slam + Self::CRUSHER_DOWN;
+                if t < Self::CRUSHER_WAIT {
+                    0.
+                } else if t < slam {
+                    (t - Self::CRUSHER_WAIT) / Self::CRUSHER_SLAM
+                } else if t < down {
+                    1.
+                } else {
+                    1. - (t - down) / (1. - down)
+                }
+            }
+            MoverKind::SpikeTrap => 0.,
+        }
+    }
+
+    /// Where it is at `time` seconds into the match, on whole pixels.
+    pub fn rect_at(&self, time: f32) -> Rect {
+        let offset = self.path * self.progress(time);
+        Rect {
+            x: self.rect.x + offset.x.round(),
+            y: self.rect.y + offset.y.round(),
+            ..self.rect
+        }
+    }
+
+    /// A spike trap that is out at `time`.
+    pub fn deadly(&self, time: f32) -> bool {
+        self.kind == MoverKind::SpikeTrap && self.cycle(time) < self.active
+    }
+}
+
+/// Where pickups show up, with how often each item does there.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PickupZone {
+    pub rect: Rect,
+    /// Weights of the items named by the zone: weapon names, `armor` or `PowerUp::name`
+    pub weights: Vec<(String, u32)>,
+    /// Weight of every item `weights` does not name
+    pub others: u32,
+}
+
+impl PickupZone {
+    /// All the items are as likely in it.
+    pub fn new(rect: Rect) -> PickupZone {
+        PickupZone {
+            rect,
+            weights: vec![],
+            others: 1,
+        }
+    }
+
+    pub fn weight(&self, name: &str) -> u32 {
+        self.weights
+            .iter()
+            .find(|(item, _)| item == name)
+            .map_or(self.others, |(_, weight)| *weight)
+    }
+}
+
+/// Damage taken by the destructible tiles still standing, by index into `Map::tiles`.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct TileDamage(BTreeMap<usize, i32>);
+
+impl TileDamage {
+    /// Deal `damage` to the destructible tile `n`, see `Map::durability`.
+    /// Returns true once it took `durability`, it is up to the caller to break it.
+    pub fn deal(&mut self, n: usize, damage: i32, durability: i32) -> bool {
+        let taken = self.0.entry(n).or_insert(0);
+        *taken += damage;
+        if *taken < durability {
+            return false;
+        }
+        self.0.remove(&n);
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// The `width` by `height` rectangle at `pos` shares some pixels with `rect`,
+/// unlike `Rect::overlaps` touching edges don't count.
+pub fn overlaps(pos: Vec2, width: i32, height: i32, rect: &Rect) -> bool {
+    pos.x < rect.x + rect.w
+        && pos.x + width as f32 > rect.x
+        && pos.y < rect.y + rect.h
+        && pos.y + height as f32 > rect.y
+}
+
+/// Collision grid, spawn points and objectives of a level, without any textures attached.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Map {
+    pub width: usize,
+    pub height: usize,
+    pub tile_width: f32,
+    pub tile_height: f32,
+    /// `width * height` tiles, row by row
+    pub tiles: Vec<Collision>,
+    pub spawns: Vec<Vec2>,
+    /// Where the flag of each team stands in capture the flag, by `Team::index`
+    pub flag_bases: [Option<Vec2>; 2],
+    /// Zone to hold in king of the hill
+    pub hill: Option<Rect>,
+    pub movers: Vec<Mover>,
+    /// Water volumes of the `logic` layer, fish swim in them like in water tiles
+    pub water: Vec<Rect>,
+    /// Pickups only show up in these when there are any, anywhere on the ground otherwise
+    pub pickup_zones: Vec<PickupZone>,
+    /// No pickups show up in these
+    pub pickup_exclusions: Vec<Rect>,
+    /// Damage the destructible tiles take before breaking, by index into `tiles`
+    pub durability: BTreeMap<usize, i32>,
+}
+
+impl Map {
+    // random tries before going through the area tile by tile
+    const PICKUP_ATTEMPTS: usize = 100;
+
+    pub fn new(
+        width: usize,
+        height: usize,
+        tile_width: f32,
+        tile_height: f32,
+        tiles: Vec<Collision>,
+        spawns: Vec<Vec2>,
+    ) -> Map {
+        assert_eq!(tiles.len(), width * height);
+
+        Map {
+            width,
+            height,
+            tile_width,
+            tile_height,
+            tiles,
+            spawns,
+            flag_bases: [None; 2],
+            hill: None,
+            movers: vec![],
+            water: vec![],
+            pickup_zones: vec![],
+            pickup_exclusions: vec![],
+            durability: BTreeMap::new(),
+        }
+    }
+
+    /// Take an object of the Tiled `logic` layer, unknown names are ignored.
+    /// Points are objects with an empty `rect`.
+    pub fn add_object(&mut self, name: &str, rect: Rect) {
+        match name {
+            "spawn" => self.spawns.push(rect.point()),
+            "flag_blue" => self.flag_bases[Team::Blue.index()] = Some(rect.point()),
+            "flag_green" => self.flag_bases[Team::Green.index()] = Some(rect.point()),
+            "hill" => self.hill = Some(rect),
+            "water" => self.water.push(rect),
+            "no_pickups" => self.pickup_exclusions.push(rect),
+            _ => {}
+        }
+    }
+
+    /// Both flag bases are there to play capture the flag.
+    pub fn has_flags(&self) -> bool {
+        self.flag_bases.iter().all(Option::is_some)
+    }
+
+    /// Has the objects `game_type` is played around.
+    pub fn supports(&self, game_type: GameType) -> bool {
+        match game_type {
+            GameType::CaptureTheFlag { .. } => self.has_flags(),
+            GameType::KingOfTheHill { .. } => self.hill.is_some(),
+            _ => true,
+        }
+    }
+
+    /// Everything outside of the map is empty space.
+    pub fn tile(&self, x: i32, y: i32) -> Collision {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return Collision::Empty;
+        }
+        self.tiles[y as usize * self.width + x as usize]
+    }
+
+    /// Index into `tiles` of the tile under `pos`, `None` outside of the map.
+    pub fn tile_index(&self, pos: Vec2) -> Option<usize> {
+        let x = (pos.x / self.tile_width).floor() as i32;
+        let y = (pos.y / self.tile_height).floor() as i32;
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return None;
+        }
+        Some(y as usize * self.width + x as usize)
+    }
+
+    pub fn tile_center(&self, n: usize) -> Vec2 {
+        vec2(
+            ((n % self.width) as f32 + 0.5) * self.tile_width,
+            ((n / self.width) as f32 + 0.5) * self.tile_height,
+        )
+    }
+
+    /// Destructible tiles with their center less than `radius` away from `pos`.
+    pub fn destructible_in(&self, pos: Vec2, radius: f32) -> Vec<usize> {
+        self.durability
+            .keys()
+            .copied()
+            .filter(|n| self.tile_center(*n).distance(pos) < radius)
+            .collect()
+    }
+
+    /// The tile `n` is gone for good, whatever it was.
+    pub fn break_tile(&mut self, n: usize) {
+        self.tiles[n] = Collision::Empty;
+        self.durability.remove(&n);
+    }
+
+    pub fn tile_solid(&self, x: i32, y: i32) -> bool {
+        self.tile(x, y) == Collision::Solid
+    }
+
+    /// Fish land on platforms and on the top of ladders.
+    fn platform_top(&self, x: i32, y: i32) -> bool {
+        match self.tile(x, y) {
+            Collision::Platform => true,
+            Collision::Ladder => self.tile(x, y - 1) != Collision::Ladder,
+            _ => false,
+        }
+    }
+
+    pub fn solid_at(&self, pos: Vec2) -> bool {
+        self.tile_solid(
+            (pos.x / self.tile_width).floor() as i32,
+            (pos.y / self.tile_height).floor() as i32,
+        )
+    }
+
+    pub fn collide_solids(&self, pos: Vec2, width: i32, height: i32) -> bool {
+        self.touches(pos, width, height, Collision::Solid)
+    }
+
+    /// Where the solid movers are at `time` seconds into the match.
+    pub fn solids(&self, time: f32) -> Vec<Rect> {
+        self.movers
+            .iter()
+            .filter(|mover| mover.solid())
+            .map(|mover| mover.rect_at(time))
+            .collect()
+    }
+
+    /// A solid tile or a solid mover at `time`, what projectiles stop on.
+    pub fn blocked_at(&self, pos: Vec2, time: f32) -> bool {
+        self.solid_at(pos)
+            || self
+                .movers
+                .iter()
+                .any(|mover| mover.solid() && mover.rect_at(time).contains(pos))
+    }
+
+    /// Touching spikes or a spike trap that is out at `time`.
+    pub fn deadly(&self, pos: Vec2, width: i32, height: i32, time: f32) -> bool {
+        self.touches(pos, width, height, Collision::Spikes)
+            || self.movers.iter().any(|mover| {
+                mover.deadly(time) && overlaps(pos, width, height, &mover.rect_at(time))
+            })
+    }
+
+    /// Partly in a water tile or a water volume.
+    pub fn in_water(&self, pos: Vec2, width: i32, height: i32) -> bool {
+        self.touches(pos, width, height, Collision::Water)
+            || self
+                .water
+                .iter()
+                .any(|water| overlaps(pos, width, height, water))
+    }
+
+    /// The point is in a water tile or a water volume.
+    pub fn water_at(&self, pos: Vec2) -> bool {
+        let x = (pos.x / self.tile_width).floor() as i32;
+        let y = (pos.y / self.tile_height).floor() as i32;
+        self.tile(x, y) == Collision::Water || self.water.iter().any(|water| water.contains(pos))
+    }
+
+    /// Any of the tiles under the rectangle is of the `kind` collision.
+    pub fn touches(&self, pos: Vec2, width: i32, height: i32, kind: Collision) -> bool {
+        let x0 = (pos.x / self.tile_width).floor() as i32;
+        let y0 = (pos.y / self.tile_height).floor() as i32;
+        let x1 = ((pos.x + width as f32 - 1.) / self.tile_width).floor() as i32;
+        let y1 = ((pos.y + height as f32 - 1.) / self.tile_height).floor() as i32;
+
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                if self.tile(x, y) == kind {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// How far the rectangle falls before its bottom lands on the top of a platform,
+    /// `None` if there is none within `dy` pixels.
+    pub fn platform_below(&self, pos: Vec2, width: i32, height: i32, dy: f32) -> Option<f32> {
+        let bottom = pos.y + height as f32;
+        let x0 = (pos.x / self.tile_width).floor() as i32;
+        let x1 = ((pos.x + width as f32 - 1.) / self.tile_width).floor() as i32;
+        let y0 = (bottom / self.tile_height).ceil() as i32;
+        let y1 = ((bottom + dy) / self.tile_height).floor() as i32;
+
+        (y0..=y1)
+            .find(|y| (x0..=x1).any(|x| self.platform_top(x, *y)))
+            .map(|y| y as f32 * self.tile_height - bottom)
+    }
+
+    /// Right on top of a platform, with nothing left to fall.
+    pub fn on_platform(&self, pos: Vec2, width: i32, height: i32) -> bool {
+        self.platform_below(pos, width, height, 0.) == Some(0.)
+    }
+
+    /// Look for a free tile standing on the ground to put a pickup on, in one of the
+    /// pickup zones, and give it back with the zone.
+    /// `gen_range` is expected to behave like `rand::gen_range` for integers.
+    pub fn pickup_spot(
+        &self,
+        mut gen_range: impl FnMut(i32, i32) -> i32,
+    ) -> Option<(Vec2, Option<&PickupZone>)> {
+        let zone = match self.pickup_zones.len() {
+            0 => None,
+            zones => Some(&self.pickup_zones[gen_range(0, zones as i32) as usize]),
+        };
+        let area = zone.map_or_else(|| self.pickup_area(), |zone| zone.rect);
+        let (columns, rows) = self.tiles_under(area);
+
+        for _ in 0..Self::PICKUP_ATTEMPTS {
+            let x = gen_range(columns.0, columns.1);
+            let y = gen_range(rows.0, rows.1);
+            let pos = self.pickup_pos(x, y);
+
+            if self.pickup_fits(pos, area) {
+                return Some((pos, zone));
+            }
+        }
+
+        // few spots in a large area, no need to be lucky to find them
+        let spots = self.pickup_spots(area);
+        match spots.len() {
+            0 => None,
+            len => Some((spots[gen_range(0, len as i32) as usize], zone)),
+        }
+    }
+
+    /// Every spot of `area` a pickup can go to.
+    pub fn pickup_spots(&self, area: Rect) -> Vec<Vec2> {
+        let (columns, rows) = self.tiles_under(area);
+
+        (rows.0..rows.1)
+            .flat_map(|y| (columns.0..columns.1).map(move |x| (x, y)))
+            .map(|(x, y)| self.pickup_pos(x, y))
+            .filter(|pos| self.pickup_fits(*pos, area))
+            .collect()
+    }
+
+    /// Where pickups go without pickup zones, above the bottom rows of the map.
+    pub fn pickup_area(&self) -> Rect {
+        Rect::new(
+            0.,
+            0.,
+            self.width as f32 * self.tile_width,
+            self.height.saturating_sub(6) as f32 * self.tile_height,
+        )
+    }
+
+    // where the pickup for the tile `x`, `y` goes, off by half a tile right and up
+    fn pickup_pos(&self, x: i32, y: i32) -> Vec2 {
+        vec2(
+            (x as f32 + 0.5) * self.tile_width,
+            (y as f32 - 0.5) * self.tile_height,
+        )
+    }
+
+    // the tiles `pickup_pos` may put a pickup in `area` for, as ranges
+    fn tiles_under(&self, area: Rect) -> ((i32, i32), (i32, i32)) {
+        let (tw, th) = (self.tile_width, self.tile_height);
+        (
+            (
+                (area.x / tw).floor() as i32,
+                (area.right() / tw).ceil() as i32,
+            ),
+            (
+                (area.y / th).floor() as i32,
+                (area.bottom() / th).ceil() as i32 + 1,
+            ),
+        )
+    }
+
+    // a free tile with ground right below, in `area` and out of the exclusions
+    fn pickup_fits(&self, pos: Vec2, area: Rect) -> bool {
+        let (tw, th) = (self.tile_width, self.tile_height);
+
+        area.contains(pos)
+            && self.collide_solids(pos, tw as _, th as _) == false
+            && self.collide_solids(pos, tw as _, th as i32 * 3)
+            && self
+                .pickup_exclusions
+                .iter()
+                .any(|exclusion| exclusion.contains(pos))
+                == false
+    }
+}
+
+/// Pixel-perfect movement through the tiles, same as `macroquad_platformer` does it
+/// for its actors: whole pixels are applied one by one, the fraction is kept for later.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Body {
+    pub pos: Vec2,
+    pub width: i32,
+    pub height: i32,
+    remainder: Vec2,
+}
+
+impl Body {
+    pub fn new(pos: Vec2, width: i32, height: i32) -> Body {
+        Body {
+            pos,
+            width,
+            height,
+            remainder: vec2(0., 0.),
+        }
+    }
+
+    pub fn set_pos(&mut self, pos: Vec2) {
+        self.pos = pos;
+        self.remainder = vec2(0., 0.);
+    }
+
+    /// Hits a solid tile or one of the `solids` movers at `pos`.
+    pub fn collide_check(&self, map: &Map, solids: &[Rect], pos: Vec2) -> bool {
+        map.collide_solids(pos, self.width, self.height)
+            || solids
+                .iter()
+                .any(|solid| overlaps(pos, self.width, self.height, solid))
+    }
+
+    pub fn overlaps(&self, rect: &Rect) -> bool {
+        overlaps(self.pos, self.width, self.height, rect)
+    }
+
+    /// Standing right on top of `rect`.
+    pub fn rides(&self, rect: &Rect) -> bool {
+        self.pos.y + self.height as f32 == rect.y
+            && self.pos.x < rect.x + rect.w
+            && self.pos.x + self.width as f32 > rect.x
+    }
+
+    pub fn touches(&self, map: &Map, kind: Collision) -> bool {
+        map.touches(self.pos, self.width, self.height, kind)
+    }
+
+    pub fn on_platform(&self, map: &Map) -> bool {
+        map.on_platform(self.pos, self.width, self.height)
+    }
+
+    /// Returns false if the body hit a wall.
+    pub fn move_h(&mut self, map: &Map, solids: &[Rect], dx: f32) -> bool {
+        self.remainder.x += dx;
+        let mut amount = self.remainder.x.round() as i32;
+        self.remainder.x -= amount as f32;
+
+        let sign = amount.signum();
+        while amount != 0 {
+            let next = self.pos + vec2(sign as f32, 0.);
+            if self.collide_check(map, solids, next) {
+                self.remainder.x = 0.;
+                return false;
+            }
+            self.pos = next;
+            amount -= sign;
+        }
+        true
+    }
+
+    /// Returns false if the body hit a floor or a ceiling.
+    /// Falling stops on platforms too, unless `platforms` is false to drop through them.
+    pub fn move_v(&mut self, map: &Map, solids: &[Rect], dy: f32, platforms: bool) -> bool {
+        self.remainder.y += dy;
+        let mut amount = self.remainder.y.round() as i32;
+        self.remainder.y -= amount as f32;
+
+        let sign = amount.signum();
+        while amount != 0 {
+            let next = self.pos + vec2(0., sign as f32);
+            if self.collide_check(map, solids, next)
+                || (platforms && sign > 0 && self.on_platform(map))
+            {
+                self.remainder.y = 0.;
+                return false;
+            }
+            self.pos = next;
+            amount -= sign;
+        }
+        true
+    }
+}
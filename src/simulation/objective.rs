@@ -0,0 +1,244 @@
+use macroquad::math::{vec2, Rect, Vec2};
+
+use super::{fish_hit_box, Event, Map, Team, FISH_HEIGHT, TICK};
+use crate::GameType;
+
+/// Seconds a dropped flag lies around before going back to its base.
+pub const FLAG_RETURN_TIME: f32 = 10.0;
+pub const CAPTURES_TO_WIN: u32 = 3;
+/// Seconds for a team alone on the hill to take it from nobody,
+/// twice that to take it from the other team.
+pub const HILL_CAPTURE_TIME: f32 = 3.0;
+/// One point per second of holding the hill.
+pub const HILL_POINTS_TO_WIN: u32 = 60;
+
+/// What the objectives need to know of a fish.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contender {
+    pub pos: Vec2,
+    pub team: Option<Team>,
+    /// Dead fish drop their flag and don't count on the hill
+    pub alive: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Flag {
+    AtBase,
+    /// In the fins of fish `n`
+    Carried(usize),
+    /// Lost by a dying carrier `timer` seconds ago
+    Dropped {
+        pos: Vec2,
+        timer: f32,
+    },
+}
+
+/// Flags of capture the flag and the hill of king of the hill.
+/// Scores go to the `[u32; 2]` passed in, indexed by `Team::index`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectiveState {
+    /// Flag of each team, by `Team::index`
+    pub flags: [Flag; 2],
+    /// Who holds the hill, from -1 for all blue to 1 for all green
+    pub hill: f32,
+    hill_timer: f32,
+}
+
+impl Default for ObjectiveState {
+    fn default() -> ObjectiveState {
+        ObjectiveState {
+            flags: [Flag::AtBase; 2],
+            hill: 0.,
+            hill_timer: 0.,
+        }
+    }
+}
+
+/// Team whose score reached the limit of the mode.
+pub fn objective_winner(game_type: GameType, scores: [u32; 2]) -> Option<Team> {
+    let limit = match game_type {
+        GameType::CaptureTheFlag { .. } => CAPTURES_TO_WIN,
+        GameType::KingOfTheHill { .. } => HILL_POINTS_TO_WIN,
+        _ => return None,
+    };
+    Team::ALL
+        .iter()
+        .copied()
+        .find(|team| scores[team.index()] >= limit)
+}
+
+/// Whether a fish at `fish` reaches a flag standing at `flag`.
+pub fn touches_flag(fish: Vec2, flag: Vec2) -> bool {
+    fish_hit_box(fish).overlaps(&Rect::new(flag.x - 12., flag.y - 48., 24., 48.))
+}
+
+impl ObjectiveState {
+    /// The team fully holding the hill.
+    pub fn hill_owner(&self) -> Option<Team> {
+        if self.hill <= -1. {
+            Some(Team::Blue)
+        } else if self.hill >= 1. {
+            Some(Team::Green)
+        } else {
+            None
+        }
+    }
+
+    /// Foot of the flag pole, `None` on a map without that base.
+    pub fn flag_pos(&self, team: Team, map: &Map, fish: &[Vec2]) -> Option<Vec2> {
+        match self.flags[team.index()] {
+            Flag::AtBase => map.flag_bases[team.index()],
+            Flag::Carried(n) => fish.get(n).map(|pos| *pos + vec2(10., 30.)),
+            Flag::Dropped { pos, .. } => Some(pos),
+        }
+    }
+
+    /// Fish `n` left the match from `pos`. Its flag is dropped there and the carriers
+    /// after it move down one place, like the fish themselves.
+    pub fn fish_left(&mut self, n: usize, pos: Vec2) {
+        for flag in &mut self.flags {
+            match *flag {
+                Flag::Carried(carrier) if carrier == n => {
+                    *flag = Flag::Dropped {
+                        pos: pos + vec2(10., FISH_HEIGHT as f32),
+                        timer: 0.,
+                    }
+                }
+                Flag::Carried(carrier) if carrier > n => *flag = Flag::Carried(carrier - 1),
+                _ => {}
+            }
+        }
+    }
+
+    /// Advance the objectives of `game_type` by one `TICK`.
+    pub fn update(
+        &mut self,
+        game_type: GameType,
+        map: &Map,
+        fish: &[Contender],
+        scores: &mut [u32; 2],
+        events: &mut Vec<Event>,
+    ) {
+        match game_type {
+            GameType::CaptureTheFlag { .. } => self.update_flags(map, fish, scores, events),
+            GameType::KingOfTheHill { .. } => self.update_hill(map, fish, scores, events),
+            _ => {}
+        }
+    }
+
+    fn update_flags(
+        &mut self,
+        map: &Map,
+        fish: &[Contender],
+        scores: &mut [u32; 2],
+        events: &mut Vec<Event>,
+    ) {
+        if map.has_flags() == false {
+            return;
+        }
+        // first alive fish of `team` reaching `pos`
+        let toucher = |pos: Vec2, team: Team| {
+            fish.iter()
+                .position(|f| f.alive && f.team == Some(team) && touches_flag(f.pos, pos))
+        };
+
+        for &flag in &Team::ALL {
+            self.flags[flag.index()] = match self.flags[flag.index()] {
+                Flag::AtBase => {
+                    let base = map.flag_bases[flag.index()].unwrap();
+                    match toucher(base, flag.other()) {
+                        Some(n) => {
+                            events.push(Event::FlagTaken { fish: n, flag });
+                            Flag::Carried(n)
+                        }
+                        None => Flag::AtBase,
+                    }
+                }
+                Flag::Carried(n) => {
+                    // the carrier left the match
+                    let carrier = fish.get(n).copied().unwrap_or(Contender {
+                        pos: map.flag_bases[flag.index()].unwrap(),
+                        team: None,
+                        alive: false,
+                    });
+                    // only with the own flag safe at home
+                    let home = flag.other();
+                    let captured = self.flags[home.index()] == Flag::AtBase
+                        && touches_flag(carrier.pos, map.flag_bases[home.index()].unwrap());
+
+                    if carrier.alive == false {
+                        events.push(Event::FlagDropped { flag });
+                        Flag::Dropped {
+                            pos: carrier.pos + vec2(10., FISH_HEIGHT as f32),
+                            timer: 0.,
+                        }
+                    } else if captured {
+                        scores[home.index()] += 1;
+                        events.push(Event::FlagCaptured { fish: n, flag });
+                        Flag::AtBase
+                    } else {
+                        Flag::Carried(n)
+                    }
+                }
+                Flag::Dropped { pos, timer } => {
+                    if timer + TICK >= FLAG_RETURN_TIME || toucher(pos, flag).is_some() {
+                        events.push(Event::FlagReturned { flag });
+                        Flag::AtBase
+                    } else if let Some(n) = toucher(pos, flag.other()) {
+                        events.push(Event::FlagTaken { fish: n, flag });
+                        Flag::Carried(n)
+                    } else {
+                        Flag::Dropped {
+                            pos,
+                            timer: timer + TICK,
+                        }
+                    }
+                }
+            };
+        }
+    }
+
+    fn update_hill(
+        &mut self,
+        map: &Map,
+        fish: &[Contender],
+        scores: &mut [u32; 2],
+        events: &mut Vec<Event>,
+    ) {
+        let hill = match map.hill {
+            Some(hill) => hill,
+            None => return,
+        };
+        let mut present = [false; 2];
+        for f in fish.iter().filter(|f| f.alive) {
+            if let (Some(team), true) = (f.team, fish_hit_box(f.pos).overlaps(&hill)) {
+                present[team.index()] = true;
+            }
+        }
+
+        let owner = self.hill_owner();
+        let step = TICK / HILL_CAPTURE_TIME;
+        match present {
+            [true, false] => self.hill = (self.hill - step).max(-1.),
+            [false, true] => self.hill = (self.hill + step).min(1.),
+            // contested or empty, the meter stays where it is
+            _ => {}
+        }
+
+        if self.hill_owner() != owner {
+            self.hill_timer = 0.;
+            if let Some(team) = self.hill_owner() {
+                events.push(Event::HillTaken { team });
+            }
+        }
+
+        let contested = present[0] && present[1];
+        if let (Some(team), false) = (self.hill_owner(), contested) {
+            self.hill_timer += TICK;
+            if self.hill_timer >= 1. {
+                self.hill_timer -= 1.;
+                scores[team.index()] += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    rc::Rc,
+};
+
+use super::{Event, Input, Map, Rules, Simulation};
+use crate::{weapons::Weapons, GameType};
+
+/// Local inputs are applied this many ticks after they were pressed,
+/// which hides a bit of latency and makes rollbacks less frequent.
+pub const INPUT_DELAY: u64 = 2;
+
+/// How far the simulation is allowed to run ahead of the last tick
+/// with everyone's inputs known. After that the session waits for the network.
+pub const MAX_PREDICTION: u64 = 12;
+
+struct PlayerInputs {
+    /// Inputs received for the ticks that may still be re-simulated
+    confirmed: BTreeMap<u64, Input>,
+    /// The last tick with a known input, inputs arrive in order
+    last_confirmed: u64,
+    /// Guesses the current state was simulated with, for ticks not confirmed yet
+    predicted: BTreeMap<u64, Input>,
+    disconnected: bool,
+}
+
+/// GGPO-style rollback on top of `Simulation`: missing remote inputs are
+/// predicted, and when the real ones arrive and differ, the state is restored from
+/// a snapshot and the ticks since are simulated again.
+pub struct Session {
+    local: usize,
+    sim: Simulation,
+    /// States after each tick from the last confirmed one to the current one
+    snapshots: VecDeque<Simulation>,
+    players: Vec<PlayerInputs>,
+    next_local_tick: u64,
+    rollback_to: Option<u64>,
+    #[cfg(test)]
+    rollbacks: usize,
+}
+
+impl Session {
+    pub fn new(
+        map: &Map,
+        weapons: Rc<Weapons>,
+        players: usize,
+        local: usize,
+        game_type: GameType,
+        rules: &Rules,
+        seed: u64,
+    ) -> Session {
+        let mut sim = Simulation::new(map, weapons, players, game_type, seed);
+        sim.set_rules(rules);
+
+        // nobody can press anything during the first `INPUT_DELAY` ticks
+        let players = (0..players)
+            .map(|_| PlayerInputs {
+                confirmed: (1..=INPUT_DELAY)
+                    .map(|tick| (tick, Input::default()))
+                    .collect(),
+                last_confirmed: INPUT_DELAY,
+                predicted: BTreeMap::new(),
+                disconnected: false,
+            })
+            .collect();
+
+        Session {
+            local,
+            snapshots: vec![sim.clone()].into(),
+            sim,
+            players,
+            next_local_tick: INPUT_DELAY + 1,
+            rollback_to: None,
+            #[cfg(test)]
+            rollbacks: 0,
+        }
+    }
+
+    pub fn local(&self) -> usize {
+        self.local
+    }
+
+    /// The predicted state, what should be drawn.
+    pub fn sim(&self) -> &Simulation {
+        &self.sim
+    }
+
+    /// The newest state that no late input can change anymore.
+    pub fn confirmed_sim(&self) -> &Simulation {
+        let tick = self.confirmed_tick().min(self.sim.tick);
+
+        self.snapshots
+            .iter()
+            .find(|snapshot| snapshot.tick == tick)
+            .unwrap_or(&self.sim)
+    }
+
+    /// Amount of times the state was restored and re-simulated.
+    #[cfg(test)]
+    pub fn rollbacks(&self) -> usize {
+        self.rollbacks
+    }
+
+    /// The last tick with inputs of every connected player known.
+    pub fn confirmed_tick(&self) -> u64 {
+        self.players
+            .iter()
+            .filter(|player| player.disconnected == false)
+            .map(|player| player.last_confirmed)
+            .min()
+            .unwrap_or(u64::MAX)
+    }
+
+    /// False when remote inputs are too far behind and the session should wait for them.
+    pub fn can_advance(&self) -> bool {
+        self.sim.tick < self.confirmed_tick().saturating_add(MAX_PREDICTION)
+    }
+
+    /// Schedule this tick's local input, returns the tick it will be applied on,
+    /// the one to tell the other players about.
+    pub fn add_local_input(&mut self, input: Input) -> u64 {
+        let tick = self.next_local_tick;
+        self.next_local_tick += 1;
+
+        self.add_input(self.local, tick, input);
+        tick
+    }
+
+    pub fn add_remote_input(&mut self, player: usize, tick: u64, input: Input) {
+        if player == self.local || player >= self.players.len() {
+            return;
+        }
+        self.add_input(player, tick, input);
+    }
+
+    /// The player left the match, from now on it is standing still.
+    pub fn disconnect(&mut self, player: usize) {
+        let inputs = &mut self.players[player];
+        let from = inputs.last_confirmed + 1;
+
+        inputs.disconnected = true;
+        for (tick, guess) in inputs.predicted.split_off(&from) {
+            if guess != Input::default() {
+                self.schedule_rollback(tick);
+                break;
+            }
+        }
+    }
+
+    fn add_input(&mut self, player: usize, tick: u64, input: Input) {
+        let inputs = &mut self.players[player];
+
+        if inputs.disconnected || tick != inputs.last_confirmed + 1 {
+            return;
+        }
+        inputs.confirmed.insert(tick, input);
+        inputs.last_confirmed = tick;
+
+        if let Some(guess) = inputs.predicted.remove(&tick) {
+            if guess != input {
+                self.schedule_rollback(tick);
+            }
+        }
+    }
+
+    fn schedule_rollback(&mut self, tick: u64) {
+        self.rollback_to = Some(self.rollback_to.map_or(tick, |t| t.min(tick)));
+    }
+
+    /// Known input of the player for the tick, or a guess: the buttons held
+    /// stay held, the presses are not repeated.
+    fn input(&self, player: usize, tick: u64) -> Input {
+        let inputs = &self.players[player];
+
+        if let Some(input) = inputs.confirmed.get(&tick) {
+            return *input;
+        }
+        if inputs.disconnected {
+            return Input::default();
+        }
+        inputs
+            .confirmed
+            .range(..tick)
+            .next_back()
+            .map(|(_, last)| Input {
+                left: last.left,
+                right: last.right,
+                up: last.up,
+                down: last.down,
+                ..Input::default()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Re-simulate mispredicted ticks, if any, then simulate the next one.
+    /// Only the events of the new tick are returned, the re-simulated ones were
+    /// already played, right or wrong.
+    pub fn advance(&mut self, map: &Map) -> Vec<Event> {
+        if let Some(tick) = self.rollback_to.take() {
+            let current = self.sim.tick;
+
+            // only mispredicted ticks are rolled back to, they come after the confirmed
+            // one and no snapshot from the confirmed tick on is dropped
+            let n = self.snapshots.iter().position(|s| s.tick == tick - 1);
+            debug_assert!(n.is_some(), "no snapshot to roll back to tick {}", tick);
+
+            if let Some(n) = n {
+                self.snapshots.truncate(n + 1);
+                self.sim = self.snapshots[n].clone();
+                #[cfg(test)]
+                {
+                    self.rollbacks += 1;
+                }
+
+                while self.sim.tick < current {
+                    self.step(map);
+                }
+            }
+        }
+
+        let events = self.step(map);
+
+        // nothing before the confirmed tick may be mispredicted anymore
+        let confirmed = self.confirmed_tick().min(self.sim.tick);
+        while self.snapshots.front().map_or(false, |s| s.tick < confirmed) {
+            self.snapshots.pop_front();
+        }
+        for player in &mut self.players {
+            // keep the last confirmed input, predictions are based on it
+            let keep = player.confirmed.split_off(&confirmed);
+            player.confirmed = keep;
+        }
+
+        events
+    }
+
+    fn step(&mut self, map: &Map) -> Vec<Event> {
+        let tick = self.sim.tick + 1;
+        let inputs: Vec<Input> = (0..self.players.len())
+            .map(|player| self.input(player, tick))
+            .collect();
+
+        for (player, input) in self.players.iter_mut().zip(&inputs) {
+            if player.disconnected == false && tick > player.last_confirmed {
+                player.predicted.insert(tick, *input);
+            }
+        }
+
+        let events = self.sim.step(map, &inputs);
+        self.snapshots.push_back(self.sim.clone());
+        events
+    }
+}
@@ -0,0 +1,127 @@
+use macroquad::math::{vec2, Vec2};
+
+use super::{Map, Rng, FISH_HEIGHT, FISH_WIDTH};
+
+/// Spawns within that many pixels of the safest one are as good as it.
+const SCORE_SLACK: f32 = 64.;
+/// A spawn is taken while a fish stands closer than that.
+const CLEARANCE: f32 = 64.;
+/// Being in the line of fire of an enemy is as bad as having it that much closer.
+const LINE_OF_FIRE_PENALTY: f32 = 320.;
+/// Score of every spawn without any enemy around.
+const NO_ENEMY_SCORE: f32 = 10_000.;
+
+/// What spawning needs to know of a fish already in the match.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bystander {
+    pub pos: Vec2,
+    pub facing: bool,
+    /// Can hurt the spawning fish
+    pub enemy: bool,
+}
+
+fn center(pos: Vec2) -> Vec2 {
+    pos + vec2(FISH_WIDTH as f32, FISH_HEIGHT as f32) / 2.
+}
+
+/// A fish at `from` looking towards `facing` would shoot a fish at `to`: about the
+/// same height, ahead of it, with no wall in between.
+pub fn in_line_of_fire(map: &Map, from: Vec2, facing: bool, to: Vec2) -> bool {
+    let (from, to) = (center(from), center(to));
+    let dir = if facing { 1. } else { -1. };
+    if (to.y - from.y).abs() >= FISH_HEIGHT as f32 || (to.x - from.x) * dir <= 0. {
+        return false;
+    }
+
+    let step = map.tile_width / 2.;
+    let steps = ((to.x - from.x).abs() / step) as i32;
+    (1..=steps).all(|n| map.solid_at(vec2(from.x + dir * step * n as f32, from.y)) == false)
+}
+
+/// How safe it is to come back at `spawn`: the distance to the closest enemy,
+/// less when one of them has it in its line of fire.
+pub fn spawn_score(map: &Map, spawn: Vec2, bystanders: &[Bystander]) -> f32 {
+    let enemies = bystanders.iter().filter(|fish| fish.enemy);
+    let closest = enemies
+        .clone()
+        .map(|fish| fish.pos.distance(spawn))
+        .fold(NO_ENEMY_SCORE, f32::min);
+    let aimed_at = enemies
+        .clone()
+        .any(|fish| in_line_of_fire(map, fish.pos, fish.facing, spawn));
+
+    match aimed_at {
+        true => closest - LINE_OF_FIRE_PENALTY,
+        false => closest,
+    }
+}
+
+/// One of the safest spawns of `map` for a fish coming back among `bystanders`,
+/// skipping those a fish stands on while there are others. `pick` chooses between
+/// the spawns about as safe, fish respawning at the same time with different picks
+/// end up apart. `None` for a map without spawns.
+pub fn pick_spawn(map: &Map, bystanders: &[Bystander], pick: usize) -> Option<Vec2> {
+    let free = map
+        .spawns
+        .iter()
+        .copied()
+        .filter(|spawn| {
+            bystanders
+                .iter()
+                .all(|fish| fish.pos.distance(*spawn) >= CLEARANCE)
+        })
+        .collect::<Vec<_>>();
+    let spawns = match free.is_empty() {
+        true => map.spawns.clone(),
+        false => free,
+    };
+
+    let scores = spawns
+        .iter()
+        .map(|spawn| spawn_score(map, *spawn, bystanders))
+        .collect::<Vec<_>>();
+    let best = scores.iter().copied().fold(f32::MIN, f32::max);
+    let safest = spawns
+        .iter()
+        .zip(&scores)
+        .filter(|(_, score)| **score >= best - SCORE_SLACK)
+        .map(|(spawn, _)| *spawn)
+        .collect::<Vec<_>>();
+
+    match safest.len() {
+        0 => None,
+        len => Some(safest[pick % len]),
+    }
+}
+
+/// Where `players` fish starting the match together go, spread out and each on its
+/// own spawn while there are enough. The same `seed` gives the same spawns.
+pub fn start_spawns(map: &Map, players: usize, seed: u64) -> Vec<Vec2> {
+    spread_spawns(map, players, &mut Rng::new(seed))
+}
+
+pub(super) fn spread_spawns(map: &Map, players: usize, rng: &mut Rng) -> Vec<Vec2> {
+    let mut placed = vec![];
+    for _ in 0..players {
+        let pick = rng.gen_range(0, map.spawns.len() as i32) as usize;
+        let pos = pick_spawn(map, &placed, pick).unwrap_or_default();
+        placed.push(Bystander {
+            pos,
+            facing: true,
+            enemy: true,
+        });
+    }
+    placed.into_iter().map(|fish| fish.pos).collect()
+}
+
+/// Everyone hashes the same sorted network ids to the same seed, for the spawns and
+/// pickups to agree without an extra message.
+pub fn match_seed<'a>(network_ids: impl IntoIterator<Item = &'a String>) -> u64 {
+    // FNV-1a
+    network_ids
+        .into_iter()
+        .flat_map(|id| id.bytes())
+        .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+}
@@ -0,0 +1,1945 @@
+use macroquad::math::vec2;
+use std::ops::Range;
+
+use super::*;
+
+const FLOOR: usize = 15;
+const GUN: WeaponId = 1;
+const SWORD: WeaponId = 2;
+const GRENADE_LAUNCHER: WeaponId = 3;
+const SHOTGUN: WeaponId = 4;
+const HARPOON: WeaponId = 5;
+
+fn weapons() -> Rc<Weapons> {
+    Rc::new(Weapons::from_json(include_str!("../../assets/weapons.json")).unwrap())
+}
+
+fn armed(id: WeaponId) -> Option<Weapon> {
+    weapons().get(id).map(Weapon::new)
+}
+
+#[test]
+fn test_broken_weapons() {
+    use crate::weapons::WeaponError;
+
+    let json = include_str!("../../assets/weapons.json");
+    let gun = |from: &str, to: &str| Weapons::from_json(&json.replacen(from, to, 1)).unwrap_err();
+
+    assert!(matches!(
+        Weapons::from_json("{}"),
+        Err(WeaponError::Json(_))
+    ));
+    for id in &[0, 0xf0] {
+        assert_eq!(
+            gun("\"id\": 1,", &format!("\"id\": {},", id)),
+            WeaponError::ReservedId {
+                weapon: "gun".to_string(),
+                id: *id
+            }
+        );
+    }
+    // the sword comes after the gun
+    assert_eq!(
+        gun("\"id\": 1,", "\"id\": 2,"),
+        WeaponError::DuplicateId {
+            weapon: "sword".to_string(),
+            id: 2
+        }
+    );
+    let no_frames = |animation| WeaponError::NoFrames {
+        weapon: "gun".to_string(),
+        animation,
+    };
+    assert_eq!(
+        gun("\"frames\": 1, \"fps\": 1 }", "\"frames\": 0, \"fps\": 1 }"),
+        no_frames("idle")
+    );
+    assert_eq!(
+        gun(
+            "\"frames\": 3, \"fps\": 15 },\n        \"effect\"",
+            "\"frames\": 0, \"fps\": 15 },\n        \"effect\""
+        ),
+        no_frames("attack")
+    );
+    assert_eq!(
+        gun(
+            "\"effect\": { \"row\": 2, \"frames\": 3",
+            "\"effect\": { \"row\": 2, \"frames\": 0"
+        ),
+        no_frames("effect")
+    );
+    assert_eq!(
+        gun("\"cooldown\": 0.24", "\"cooldown\": 0"),
+        WeaponError::NoCooldown {
+            weapon: "gun".to_string()
+        }
+    );
+}
+
+/// 30x20 tiles box: a floor on the 15th row and walls on both sides
+fn test_map() -> Map {
+    let (width, height) = (30, 20);
+    let mut tiles = vec![Collision::Empty; width * height];
+    for x in 0..width {
+        tiles[FLOOR * width + x] = Collision::Solid;
+    }
+    for y in 0..height {
+        tiles[y * width] = Collision::Solid;
+        tiles[y * width + width - 1] = Collision::Solid;
+    }
+
+    Map::new(
+        width,
+        height,
+        32.,
+        32.,
+        tiles,
+        vec![vec2(100., 300.), vec2(600., 300.)],
+    )
+}
+
+/// `test_map` with the tiles in `columns` and `rows` set to `kind`
+fn test_map_with(kind: Collision, columns: Range<usize>, rows: Range<usize>) -> Map {
+    let mut map = test_map();
+    for y in rows {
+        for x in columns.clone() {
+            map.tiles[y * map.width + x] = kind;
+        }
+    }
+    map
+}
+
+fn floor_y() -> f32 {
+    FLOOR as f32 * 32. - FISH_HEIGHT as f32
+}
+
+fn run(sim: &mut Simulation, map: &Map, ticks: usize, inputs: &[Input]) -> Vec<Event> {
+    let mut events = vec![];
+    for _ in 0..ticks {
+        events.extend(sim.step(map, inputs));
+    }
+    events
+}
+
+/// Two fish standing on the floor, facing each other
+fn duel(game_type: GameType, distance: f32) -> (Map, Simulation) {
+    duel_on(test_map(), game_type, distance)
+}
+
+fn duel_on(map: Map, game_type: GameType, distance: f32) -> (Map, Simulation) {
+    let mut sim = Simulation::new(&map, weapons(), 2, game_type, 42);
+
+    sim.fish[0].body.set_pos(vec2(200., floor_y()));
+    sim.fish[1].body.set_pos(vec2(200. + distance, floor_y()));
+    sim.fish[1].facing = false;
+    run(&mut sim, &map, 1, &[]);
+
+    (map, sim)
+}
+
+const FIRE: Input = Input {
+    fire: true,
+    jump: false,
+    left: false,
+    right: false,
+    throw: false,
+    dash: false,
+    up: false,
+    down: false,
+};
+
+const THROW_INPUT: Input = Input {
+    throw: true,
+    jump: false,
+    fire: false,
+    left: false,
+    right: false,
+    dash: false,
+    up: false,
+    down: false,
+};
+
+#[test]
+fn test_map_collisions() {
+    let map = test_map();
+
+    assert!(map.solid_at(vec2(5., 5.)));
+    assert!(map.solid_at(vec2(100., FLOOR as f32 * 32. + 1.)));
+    assert!(map.solid_at(vec2(100., 100.)) == false);
+    // outside of the map is empty
+    assert!(map.solid_at(vec2(-100., -100.)) == false);
+
+    assert!(map.collide_solids(vec2(100., FLOOR as f32 * 32. - 10.), 30, 11));
+    assert!(map.collide_solids(vec2(100., FLOOR as f32 * 32. - 10.), 30, 10) == false);
+}
+
+#[test]
+fn test_pickup_spot() {
+    let map = test_map();
+    let mut rng = Rng::new(0);
+    let (pos, zone) = map
+        .pickup_spot(|low, high| rng.gen_range(low, high))
+        .unwrap();
+
+    // free tile right above the floor
+    assert!(map.collide_solids(pos, 32, 32) == false);
+    assert!(map.collide_solids(pos, 32, 96));
+    assert_eq!(zone, None);
+
+    // nowhere to stand - the search gives up instead of spinning forever
+    let empty = Map::new(10, 10, 32., 32., vec![Collision::Empty; 100], vec![]);
+    assert_eq!(empty.pickup_spot(|low, _| low), None);
+}
+
+#[test]
+fn test_pickup_zones() {
+    let mut map = test_map();
+    let zone = PickupZone::new(Rect::new(320., 0., 160., FLOOR as f32 * 32.));
+    map.pickup_zones.push(zone.clone());
+    map.pickup_exclusions
+        .push(Rect::new(320., 0., 64., FLOOR as f32 * 32.));
+
+    let spots = map.pickup_spots(zone.rect);
+    assert!(spots.is_empty() == false);
+    for pos in &spots {
+        assert!(pos.x >= 384. && pos.x < 480.);
+        assert!(map.collide_solids(*pos, 32, 96));
+    }
+
+    let mut rng = Rng::new(0);
+    for _ in 0..20 {
+        let (pos, spot_zone) = map
+            .pickup_spot(|low, high| rng.gen_range(low, high))
+            .unwrap();
+        assert!(spots.contains(&pos));
+        assert_eq!(spot_zone, Some(&zone));
+    }
+
+    // out of luck with the random tries, still found going through the zone
+    assert_eq!(map.pickup_spot(|low, _| low), Some((spots[0], Some(&zone))));
+
+    // a zone without ground gets no pickups
+    map.pickup_zones[0].rect = Rect::new(320., 0., 160., 64.);
+    assert_eq!(map.pickup_spot(|low, _| low), None);
+}
+
+#[test]
+fn test_pickup_item_weights() {
+    let weapons = weapons();
+    let items = Item::all(&weapons);
+    let roll_all = |zone: Option<&PickupZone>| {
+        // every roll as often for both tables
+        (0..items.len() as i32 * 4)
+            .filter_map(|roll| pickup_item(zone, &weapons, |_, high| roll % high))
+            .collect::<Vec<_>>()
+    };
+
+    // everything without a zone
+    let rolled = roll_all(None);
+    assert!(items.iter().all(|item| rolled.contains(item)));
+
+    // swords and armor only, armor three times as often
+    let mut zone = PickupZone::new(Rect::new(0., 0., 32., 32.));
+    zone.weights = vec![("sword".to_string(), 1), ("armor".to_string(), 3)];
+    zone.others = 0;
+    let rolled = roll_all(Some(&zone));
+    let sword = Item::Weapon(SWORD);
+    assert!(rolled
+        .iter()
+        .all(|item| *item == sword || *item == Item::Armor));
+    let armor = rolled.iter().filter(|item| **item == Item::Armor).count();
+    assert_eq!(armor, 3 * (rolled.len() - armor));
+
+    zone.weights.clear();
+    assert_eq!(pickup_item(Some(&zone), &weapons, |low, _| low), None);
+}
+
+#[test]
+fn test_fall_and_land() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+
+    assert!(sim.fish[0].on_ground == false);
+    run(&mut sim, &map, 120, &[]);
+
+    assert!(sim.fish[0].on_ground);
+    assert_eq!(sim.fish[0].pos().y, floor_y());
+    assert_eq!(sim.fish[0].speed.y, 0.);
+}
+
+#[test]
+fn test_run_into_wall() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    let left = Input {
+        left: true,
+        ..Default::default()
+    };
+
+    run(&mut sim, &map, 10, &[left]);
+    assert!(sim.fish[0].facing == false);
+    assert!(sim.fish[0].speed.x < 0.);
+
+    run(&mut sim, &map, 300, &[left]);
+    // stopped by the wall in the first column
+    assert_eq!(sim.fish[0].pos().x, 32.);
+}
+
+#[test]
+fn test_jump() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    let jump = Input {
+        jump: true,
+        ..Default::default()
+    };
+
+    // no double jumps in the air
+    let events = run(&mut sim, &map, 5, &[jump]);
+    assert!(events.contains(&Event::Jump { fish: 0 }) == false);
+
+    run(&mut sim, &map, 120, &[]);
+    let events = sim.step(&map, &[jump]);
+    assert_eq!(events, vec![Event::Jump { fish: 0 }]);
+
+    run(&mut sim, &map, 20, &[]);
+    assert!(sim.fish[0].pos().y < floor_y() - 50.);
+
+    run(&mut sim, &map, 120, &[]);
+    assert_eq!(sim.fish[0].pos().y, floor_y());
+}
+
+#[test]
+fn test_double_jump() {
+    let map = test_map();
+    let jump = Input {
+        jump: true,
+        ..Default::default()
+    };
+    let jumps = |movement: Movement| {
+        let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+        sim.movement = movement;
+        run(&mut sim, &map, 120, &[]);
+
+        let mut events = sim.step(&map, &[jump]);
+        for _ in 0..3 {
+            events.extend(run(&mut sim, &map, 15, &[]));
+            events.extend(sim.step(&map, &[jump]));
+        }
+        events
+            .iter()
+            .filter(|event| **event == Event::Jump { fish: 0 })
+            .count()
+    };
+
+    // only one more jump before landing
+    assert_eq!(jumps(Movement::ALL), 2);
+    assert_eq!(jumps(Movement::default()), 1);
+}
+
+#[test]
+fn test_wall_jump() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    sim.movement = Movement::ALL;
+    run(&mut sim, &map, 120, &[]);
+
+    let left = Input {
+        left: true,
+        ..Default::default()
+    };
+    sim.fish[0].body.set_pos(vec2(40., floor_y() - 250.));
+    run(&mut sim, &map, 30, &[left]);
+
+    assert_eq!(sim.fish[0].wall, Some(false));
+    assert_eq!(sim.fish[0].current_move(), Move::WallSlide);
+    assert!(sim.fish[0].speed.y <= consts::WALL_SLIDE_SPEED);
+
+    let events = sim.step(
+        &map,
+        &[Input {
+            jump: true,
+            left: true,
+            ..Default::default()
+        }],
+    );
+    assert_eq!(events, vec![Event::Jump { fish: 0 }]);
+    assert!(sim.fish[0].facing);
+    assert!(sim.fish[0].speed.x > 0.);
+
+    // pushing into the wall doesn't bring it right back
+    run(&mut sim, &map, 5, &[left]);
+    assert!(sim.fish[0].pos().x > 60.);
+}
+
+#[test]
+fn test_dash() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    sim.movement = Movement::ALL;
+    run(&mut sim, &map, 120, &[]);
+    sim.fish[0].body.set_pos(vec2(200., floor_y()));
+
+    let dash = Input {
+        dash: true,
+        ..Default::default()
+    };
+    assert!(sim.step(&map, &[dash]).contains(&Event::Dash { fish: 0 }));
+    assert_eq!(sim.fish[0].current_move(), Move::Dash);
+
+    run(&mut sim, &map, (consts::DASH_TIME / TICK) as usize, &[]);
+    assert!(sim.fish[0].pos().x > 280.);
+    assert_eq!(sim.fish[0].state, FishState::Normal);
+
+    // still cooling down
+    assert!(sim.step(&map, &[dash]).contains(&Event::Dash { fish: 0 }) == false);
+    run(&mut sim, &map, (consts::DASH_COOLDOWN / TICK) as usize, &[]);
+    assert!(sim.step(&map, &[dash]).contains(&Event::Dash { fish: 0 }));
+
+    sim.movement = Movement::default();
+    run(&mut sim, &map, 120, &[]);
+    assert!(sim.step(&map, &[dash]).contains(&Event::Dash { fish: 0 }) == false);
+}
+
+#[test]
+fn test_coyote_time() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    run(&mut sim, &map, 120, &[]);
+
+    // just walked off a ledge
+    sim.fish[0].body.set_pos(vec2(200., floor_y() - 40.));
+    sim.step(&map, &[]);
+    assert!(sim.fish[0].on_ground == false);
+
+    let jump = Input {
+        jump: true,
+        ..Default::default()
+    };
+    assert_eq!(sim.step(&map, &[jump]), vec![Event::Jump { fish: 0 }]);
+}
+
+#[test]
+fn test_platforms() {
+    // a platform 3 tiles above the floor
+    let map = test_map_with(Collision::Platform, 4..10, 12..13);
+    let top = 12. * 32. - FISH_HEIGHT as f32;
+    assert_eq!(
+        map.platform_below(vec2(200., top - 10.), 30, 54, 20.),
+        Some(10.)
+    );
+    assert_eq!(map.platform_below(vec2(200., top - 10.), 30, 54, 5.), None);
+    assert!(map.solid_at(vec2(200., 12. * 32. + 1.)) == false);
+
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    sim.fish[0].body.set_pos(vec2(200., 100.));
+    run(&mut sim, &map, 120, &[]);
+    assert!(sim.fish[0].on_ground);
+    assert_eq!(sim.fish[0].pos().y, top);
+
+    // holding down drops through it
+    let down = Input {
+        down: true,
+        ..Default::default()
+    };
+    run(&mut sim, &map, 60, &[down]);
+    assert_eq!(sim.fish[0].pos().y, floor_y());
+
+    // and jumping goes up through it
+    let jump = Input {
+        jump: true,
+        ..Default::default()
+    };
+    sim.step(&map, &[jump]);
+    run(&mut sim, &map, 120, &[]);
+    assert_eq!(sim.fish[0].pos().y, top);
+}
+
+#[test]
+fn test_spikes_kill() {
+    let map = test_map_with(Collision::Spikes, 8..10, FLOOR - 1..FLOOR);
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    sim.fish[0].body.set_pos(vec2(100., floor_y()));
+    sim.fish[0].invulnerable = 0.;
+
+    // walks on top of the floor into them
+    let right = Input {
+        right: true,
+        ..Default::default()
+    };
+    let events = run(&mut sim, &map, 60, &[right]);
+    assert!(events.contains(&Event::Death {
+        fish: 0,
+        killer: None
+    }));
+    assert!(sim.fish[0].is_dead());
+}
+
+#[test]
+fn test_ladder() {
+    // from the floor up to 5 tiles above it
+    let map = test_map_with(Collision::Ladder, 6..7, FLOOR - 5..FLOOR);
+    let top = (FLOOR - 5) as f32 * 32. - FISH_HEIGHT as f32;
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    sim.fish[0].body.set_pos(vec2(190., floor_y()));
+    sim.step(&map, &[]);
+
+    let up = Input {
+        up: true,
+        jump: true,
+        ..Default::default()
+    };
+    // up is also a jump, it climbs instead on a ladder
+    assert_eq!(sim.step(&map, &[up]), vec![]);
+    assert!(sim.fish[0].climbing);
+
+    // holds on without pressing anything
+    run(&mut sim, &map, 30, &[Input { up: true, ..up }]);
+    let pos = sim.fish[0].pos();
+    run(&mut sim, &map, 30, &[]);
+    assert_eq!(sim.fish[0].pos(), pos);
+
+    // the top of the ladder is a platform to stand on
+    let up = Input { jump: false, ..up };
+    run(&mut sim, &map, 120, &[up]);
+    run(&mut sim, &map, 60, &[]);
+    assert!(sim.fish[0].climbing == false);
+    assert_eq!(sim.fish[0].pos().y, top);
+
+    // back down and jump off half way
+    let down = Input {
+        down: true,
+        ..Default::default()
+    };
+    run(&mut sim, &map, 30, &[down]);
+    assert!(sim.fish[0].climbing);
+    let jump = Input {
+        jump: true,
+        ..Default::default()
+    };
+    assert_eq!(sim.step(&map, &[jump]), vec![Event::Jump { fish: 0 }]);
+    assert!(sim.fish[0].climbing == false);
+}
+
+#[test]
+fn test_water() {
+    let map = test_map_with(Collision::Water, 1..29, 2..FLOOR);
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    sim.fish[0].body.set_pos(vec2(200., 70.));
+
+    run(&mut sim, &map, 60, &[]);
+    assert_eq!(sim.fish[0].speed.y, consts::WATER_SINK_SPEED);
+
+    // swims up with jumps, without touching the ground
+    let jump = Input {
+        jump: true,
+        ..Default::default()
+    };
+    let y = sim.fish[0].pos().y;
+    for _ in 0..3 {
+        assert_eq!(sim.step(&map, &[jump]), vec![Event::Jump { fish: 0 }]);
+        run(&mut sim, &map, 10, &[]);
+    }
+    assert!(sim.fish[0].pos().y < y);
+}
+
+#[test]
+fn test_water_volume() {
+    let mut map = test_map();
+    let surface = 320.;
+    map.add_object(
+        "water",
+        Rect::new(32., surface, 28. * 32., FLOOR as f32 * 32. - surface),
+    );
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    sim.fish[0].body.set_pos(vec2(200., 200.));
+    let splash = |event: &Event| matches!(event, Event::Splash { .. });
+
+    // dives in and sinks slower than it falls
+    let events = run(&mut sim, &map, 60, &[]);
+    assert_eq!(events.iter().filter(|event| splash(event)).count(), 1);
+    assert!(sim.fish[0].in_water);
+    assert!(sim.fish[0].on_ground == false);
+    assert!(sim.fish[0].speed.y <= consts::WATER_SINK_SPEED);
+
+    // jumps are swim strokes, up to the surface and out
+    let jump = Input {
+        jump: true,
+        ..Default::default()
+    };
+    assert_eq!(sim.step(&map, &[jump]), vec![Event::Jump { fish: 0 }]);
+    assert!(sim.fish[0].speed.y < 0.);
+    let mut events = run(&mut sim, &map, 10, &[]);
+    for _ in 0..10 {
+        if sim.fish[0].in_water == false {
+            break;
+        }
+        events.extend(sim.step(&map, &[jump]));
+        events.extend(run(&mut sim, &map, 10, &[]));
+    }
+    assert!(sim.fish[0].in_water == false);
+    assert!(sim.fish[0].pos().y + (FISH_HEIGHT as f32) <= surface);
+    assert_eq!(events.iter().filter(|event| splash(event)).count(), 1);
+}
+
+#[test]
+fn test_bullets_slow_in_water() {
+    let bullet_speed = |map: &Map| {
+        let mut sim = Simulation::new(map, weapons(), 1, GameType::Deathmatch, 0);
+        sim.fish[0].weapon = armed(GUN);
+        sim.step(map, &[FIRE]);
+        run(&mut sim, map, 5, &[]);
+        sim.bullets[0].speed.x.abs()
+    };
+
+    let dry = bullet_speed(&test_map());
+    let wet = bullet_speed(&test_map_with(Collision::Water, 1..29, 2..FLOOR));
+    assert!(wet < dry);
+}
+
+#[test]
+fn test_moving_platform_carries() {
+    let mut map = test_map();
+    let platform = Rect::new(200., 320., 96., 16.);
+    // there and back in 4 seconds
+    map.movers
+        .push(Mover::platform(platform, vec2(160., 0.), 80., 0.));
+    assert_eq!(map.movers[0].rect_at(1.).x, 280.);
+    assert_eq!(map.movers[0].rect_at(2.).x, 360.);
+    assert_eq!(map.movers[0].rect_at(4.).x, 200.);
+
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    sim.fish[0]
+        .body
+        .set_pos(vec2(220., 320. - FISH_HEIGHT as f32));
+    run(&mut sim, &map, 60, &[]);
+
+    assert!(sim.fish[0].on_ground);
+    assert_eq!(sim.fish[0].pos(), vec2(300., 320. - FISH_HEIGHT as f32));
+}
+
+#[test]
+fn test_crusher_kills() {
+    let mut map = test_map();
+    // slams down to the floor a second into its 2 seconds cycle
+    let crusher = Rect::new(200., 160., 96., 64.);
+    map.movers
+        .push(Mover::crusher(crusher, FLOOR as f32 * 32. - 224., 2., 0.));
+
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    sim.fish[0].body.set_pos(vec2(230., floor_y()));
+    let events = run(&mut sim, &map, 59, &[]);
+    assert!(events.is_empty());
+
+    let events = run(&mut sim, &map, 15, &[]);
+    assert_eq!(
+        events,
+        vec![Event::Death {
+            fish: 0,
+            killer: None
+        }]
+    );
+
+    // bullets stop on it
+    assert!(map.blocked_at(vec2(240., 200.), 0.));
+    assert!(map.blocked_at(vec2(240., 200.), 1.2) == false);
+}
+
+#[test]
+fn test_spike_trap() {
+    let mut map = test_map();
+    // out for the first second of every two
+    let trap = Rect::new(200., FLOOR as f32 * 32. - 32., 96., 32.);
+    map.movers.push(Mover::spike_trap(trap, 2., 1., 0.));
+
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 0);
+    run(&mut sim, &map, 72, &[]);
+    sim.fish[0].body.set_pos(vec2(220., floor_y()));
+    sim.fish[0].invulnerable = 0.;
+
+    let death = Event::Death {
+        fish: 0,
+        killer: None,
+    };
+    assert!(run(&mut sim, &map, 40, &[]).contains(&death) == false);
+    assert!(run(&mut sim, &map, 20, &[]).contains(&death));
+}
+
+#[test]
+fn test_gun_kills() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 200.);
+    sim.fish[0].weapon = armed(GUN);
+
+    let events = sim.step(&map, &[FIRE]);
+    assert_eq!(
+        events,
+        vec![Event::Attack {
+            fish: 0,
+            weapon: GUN
+        }]
+    );
+    assert_eq!(sim.bullets.len(), 1);
+    // recoil
+    assert!(sim.fish[0].speed.x < 0.);
+
+    // the first bullet only hurts and pushes away
+    let damage = weapons().get(GUN).unwrap().projectile.unwrap().damage;
+    let events = run(&mut sim, &map, 60, &[]);
+    assert!(events
+        .iter()
+        .all(|event| matches!(event, Event::Death { .. }) == false));
+    assert_eq!(sim.fish[1].health, MAX_HEALTH - damage);
+    assert!(sim.fish[1].pos().x > 400.);
+    assert!(sim.bullets.is_empty());
+    assert_eq!(sim.fish[0].weapon, Some(Weapon { id: GUN, ammo: 2 }));
+
+    sim.step(&map, &[FIRE]);
+    let events = run(&mut sim, &map, 60, &[]);
+    assert!(events.contains(&Event::Death {
+        fish: 1,
+        killer: Some(0)
+    }));
+    assert!(sim.fish[1].is_dead());
+}
+
+#[test]
+fn test_armor_absorbs_damage() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 200.);
+    sim.fish[0].weapon = armed(GUN);
+    sim.fish[1].armor = MAX_ARMOR;
+
+    sim.step(&map, &[FIRE]);
+    run(&mut sim, &map, 60, &[]);
+    sim.step(&map, &[FIRE]);
+    run(&mut sim, &map, 60, &[]);
+
+    // two bullets: one taken by the armor, the other by the health
+    assert!(sim.fish[1].is_dead() == false);
+    assert_eq!(sim.fish[1].armor, 0);
+    assert_eq!(sim.fish[1].health, MAX_HEALTH - 2 * 50 + MAX_ARMOR);
+}
+
+#[test]
+fn test_respawn_invulnerability() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 50.);
+    sim.fish[1].kill(true);
+    while sim.step(&map, &[]).contains(&Event::Respawn { fish: 1 }) == false {}
+
+    assert_eq!(sim.fish[1].health, MAX_HEALTH);
+    assert!(sim.fish[1].invulnerable > 0.);
+
+    let pos = sim.fish[0].pos() + vec2(50., 0.);
+    sim.fish[1].body.set_pos(pos);
+    sim.fish[0].weapon = armed(SWORD);
+    sim.step(&map, &[FIRE]);
+    assert!(sim.fish[1].is_dead() == false);
+    assert_eq!(sim.fish[1].health, MAX_HEALTH);
+
+    run(
+        &mut sim,
+        &map,
+        (SPAWN_INVULNERABILITY / TICK) as usize + 1,
+        &[],
+    );
+    assert_eq!(sim.fish[1].invulnerable, 0.);
+    sim.step(&map, &[FIRE]);
+    assert!(sim.fish[1].is_dead());
+}
+
+#[test]
+fn test_respawn_away_from_enemies() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 50.);
+    let far = map.spawns[1];
+    sim.fish[0].body.set_pos(map.spawns[0] + vec2(60., 0.));
+    sim.fish[1].kill(true);
+    while sim.step(&map, &[]).contains(&Event::Respawn { fish: 1 }) == false {}
+
+    assert_eq!(sim.fish[1].pos(), far);
+}
+
+#[test]
+fn test_spawn_scores() {
+    let map = test_map();
+    let (left, right) = (map.spawns[0], map.spawns[1]);
+    let enemy = |pos: Vec2, facing: bool| Bystander {
+        pos,
+        facing,
+        enemy: true,
+    };
+
+    // as far from both, the one it faces is worse
+    let middle = (left + right) / 2.;
+    let facing_right = [enemy(middle, true)];
+    assert!(spawn::in_line_of_fire(&map, middle, true, right));
+    assert!(spawn::in_line_of_fire(&map, middle, true, left) == false);
+    assert!(
+        spawn::spawn_score(&map, left, &facing_right)
+            > spawn::spawn_score(&map, right, &facing_right)
+    );
+    for pick in 0..4 {
+        assert_eq!(pick_spawn(&map, &facing_right, pick), Some(left));
+    }
+
+    // a wall blocks the line of fire
+    let mut walled = map.clone();
+    let wall_x = (middle.x + right.x) as usize / 2 / 32;
+    for y in 0..FLOOR {
+        walled.tiles[y * walled.width + wall_x] = Collision::Solid;
+    }
+    assert!(spawn::in_line_of_fire(&walled, middle, true, right) == false);
+
+    // teammates don't count, standing on a spawn takes it
+    let teammate = Bystander {
+        enemy: false,
+        ..enemy(left, true)
+    };
+    assert_eq!(pick_spawn(&map, &[teammate], 0), Some(right));
+    assert_eq!(pick_spawn(&map, &[teammate], 1), Some(right));
+
+    let empty = Map::new(10, 10, 32., 32., vec![Collision::Empty; 100], vec![]);
+    assert_eq!(pick_spawn(&empty, &[], 0), None);
+}
+
+#[test]
+fn test_start_spawns() {
+    let map = test_map();
+
+    for seed in 0..10 {
+        let spawns = start_spawns(&map, 2, seed);
+        assert_eq!(spawns, start_spawns(&map, 2, seed));
+        assert!(spawns[0] != spawns[1]);
+
+        // the simulation starts the fish where the clients would
+        let sim = Simulation::new(&map, weapons(), 2, GameType::Deathmatch, seed);
+        let positions = sim.fish.iter().map(|fish| fish.pos()).collect::<Vec<_>>();
+        assert_eq!(positions, spawns);
+    }
+
+    // more fish than spawns share them
+    let spawns = start_spawns(&map, 3, 0);
+    assert!(map.spawns.iter().all(|spawn| spawns.contains(spawn)));
+
+    let ids = vec!["b".to_string(), "a".to_string()];
+    assert_eq!(match_seed(&ids), match_seed(&ids.clone()));
+    assert!(match_seed(&ids[..1]) != match_seed(&ids));
+}
+
+#[test]
+fn test_bullet_lifetime() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 200.);
+    sim.fish[1].weapon = armed(GUN);
+    // shooting away from the other fish
+    sim.fish[1].facing = true;
+
+    sim.step(&map, &[Input::default(), FIRE]);
+    let events = run(&mut sim, &map, 60, &[]);
+
+    assert!(sim.bullets.is_empty());
+    assert!(sim.fish.iter().all(|fish| fish.is_dead() == false));
+    assert!(events
+        .iter()
+        .all(|event| matches!(event, Event::Death { .. }) == false));
+}
+
+#[test]
+fn test_shotgun_spread() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 200.);
+    sim.fish[0].weapon = armed(SHOTGUN);
+
+    sim.step(&map, &[FIRE]);
+    let count = weapons().get(SHOTGUN).unwrap().projectile.unwrap().count;
+    assert_eq!(sim.bullets.len(), count as usize);
+
+    // fanned out evenly around the horizontal
+    let up = sim.bullets.iter().filter(|b| b.speed.y < -1.).count();
+    let down = sim.bullets.iter().filter(|b| b.speed.y > 1.).count();
+    assert_eq!(up, down);
+    assert!(up > 0);
+    assert!(sim.bullets.iter().all(|b| b.speed.x > 0.));
+
+    let events = run(&mut sim, &map, 60, &[]);
+    assert!(events.contains(&Event::Death {
+        fish: 1,
+        killer: Some(0)
+    }));
+}
+
+#[test]
+fn test_grenade_explodes() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 150.);
+    sim.fish[0].weapon = armed(GRENADE_LAUNCHER);
+
+    sim.step(&map, &[FIRE]);
+    // launched upwards, falls down
+    assert!(sim.bullets[0].speed.y < 0.);
+    let events = run(&mut sim, &map, 90, &[]);
+
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, Event::Blast { .. })));
+    assert!(events.contains(&Event::Death {
+        fish: 1,
+        killer: Some(0)
+    }));
+    assert!(sim.bullets.is_empty());
+}
+
+#[test]
+fn test_grenade_bounces() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 600.);
+    sim.fish[0].weapon = armed(GRENADE_LAUNCHER);
+    // towards the wall
+    sim.fish[0].facing = false;
+
+    sim.step(&map, &[FIRE]);
+    let events = run(&mut sim, &map, 40, &[]);
+    assert_eq!(sim.bullets.len(), 1);
+    assert!(sim.bullets[0].speed.x > 0.);
+    assert!(events
+        .iter()
+        .all(|event| matches!(event, Event::Blast { .. }) == false));
+
+    // explodes once its time is over, even without hitting anyone
+    let lifetime = weapons()
+        .get(GRENADE_LAUNCHER)
+        .unwrap()
+        .projectile
+        .unwrap()
+        .lifetime;
+    let events = run(&mut sim, &map, (lifetime / TICK) as usize, &[]);
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, Event::Blast { .. })));
+    assert!(sim.bullets.is_empty());
+}
+
+fn broken_tiles(events: &[Event]) -> Vec<usize> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            Event::TileBroken { tile, .. } => Some(*tile),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_bullets_break_tiles() {
+    // a destructible column between the fish
+    let mut map = test_map_with(Collision::Solid, 10..11, 0..FLOOR);
+    for y in 0..FLOOR {
+        map.durability.insert(y * map.width + 10, 100);
+    }
+    let (map, mut sim) = duel_on(map, GameType::Deathmatch, 200.);
+    sim.fish[0].weapon = armed(GUN);
+
+    // half of its durability
+    sim.step(&map, &[FIRE]);
+    let events = run(&mut sim, &map, 60, &[]);
+    assert!(broken_tiles(&events).is_empty());
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, Event::BulletHit { .. })));
+
+    sim.step(&map, &[FIRE]);
+    let broken = broken_tiles(&run(&mut sim, &map, 60, &[]));
+    assert_eq!(broken.len(), 1);
+    assert_eq!(sim.broken, broken);
+    assert_eq!(sim.terrain(&map).tiles[broken[0]], Collision::Empty);
+    // the tile is only gone from the match, not from the map given to it
+    assert_eq!(map.tiles[broken[0]], Collision::Solid);
+    assert_eq!(sim.fish[1].health, MAX_HEALTH);
+
+    // right through the hole
+    sim.step(&map, &[FIRE]);
+    run(&mut sim, &map, 60, &[]);
+    assert!(sim.fish[1].health < MAX_HEALTH);
+}
+
+#[test]
+fn test_blasts_break_tiles() {
+    let mut map = test_map();
+    for x in 0..map.width {
+        map.durability.insert(FLOOR * map.width + x, 100);
+    }
+    let (map, mut sim) = duel_on(map, GameType::Deathmatch, 150.);
+    sim.fish[0].weapon = armed(GRENADE_LAUNCHER);
+
+    sim.step(&map, &[FIRE]);
+    let events = run(&mut sim, &map, 90, &[]);
+    let blast = events
+        .iter()
+        .find_map(|event| match event {
+            Event::Blast { pos } => Some(*pos),
+            _ => None,
+        })
+        .unwrap();
+    let broken = broken_tiles(&events);
+
+    // the floor under the blast, and nothing further
+    assert!(broken.is_empty() == false);
+    assert_eq!(broken, map.destructible_in(blast, 70.));
+    for tile in &broken {
+        assert_eq!(sim.terrain(&map).tiles[*tile], Collision::Empty);
+    }
+    assert_eq!(sim.terrain(&map).durability.len(), map.width - broken.len());
+}
+
+#[test]
+fn test_harpoon_pulls() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 200.);
+    sim.fish[0].weapon = armed(HARPOON);
+    let start = sim.fish[1].pos();
+
+    sim.step(&map, &[FIRE]);
+    let mut pulled = false;
+    for _ in 0..60 {
+        sim.step(&map, &[]);
+        pulled |= matches!(sim.fish[1].state, FishState::Knocked { .. });
+    }
+
+    assert!(pulled);
+    assert!(sim.fish[1].is_dead() == false);
+    assert!(sim.fish[1].health < MAX_HEALTH);
+    assert!(sim.fish[1].pos().x < start.x - 50.);
+    assert_eq!(sim.fish[1].state, FishState::Normal);
+}
+
+#[test]
+fn test_gun_runs_dry() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 200.);
+    sim.fish[0].weapon = armed(GUN);
+    sim.fish[0].facing = false;
+
+    let ammo = weapons().get(GUN).unwrap().ammo;
+    let mut events = vec![];
+    for _ in 0..ammo {
+        events.extend(sim.step(&map, &[FIRE]));
+        events.extend(run(&mut sim, &map, 30, &[]));
+    }
+
+    let shots = events
+        .iter()
+        .filter(|event| {
+            **event
+                == Event::Attack {
+                    fish: 0,
+                    weapon: GUN,
+                }
+        })
+        .count();
+    assert_eq!(shots, ammo as usize);
+    assert!(events.contains(&Event::OutOfAmmo { fish: 0 }));
+    assert_eq!(sim.fish[0].weapon.map(|weapon| weapon.ammo), Some(0));
+
+    // an empty gun gets thrown instead
+    assert_eq!(
+        sim.step(&map, &[FIRE]),
+        vec![Event::Throw {
+            fish: 0,
+            weapon: GUN
+        }]
+    );
+    assert_eq!(sim.fish[0].weapon, None);
+    assert_eq!(sim.thrown.len(), 1);
+
+    // nothing to shoot with anymore
+    assert_eq!(sim.step(&map, &[FIRE]), vec![]);
+}
+
+#[test]
+fn test_throw_hits_and_lands() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 80.);
+    sim.fish[0].weapon = armed(SWORD);
+
+    let mut events = sim.step(&map, &[THROW_INPUT]);
+    assert_eq!(
+        events,
+        vec![Event::Throw {
+            fish: 0,
+            weapon: SWORD
+        }]
+    );
+    assert_eq!(sim.fish[0].weapon, None);
+
+    let mut knocked = false;
+    for _ in 0..(THROW.lifetime / TICK) as usize {
+        events.extend(sim.step(&map, &[]));
+        knocked |= matches!(sim.fish[1].state, FishState::Knocked { .. });
+    }
+
+    assert!(knocked);
+    assert_eq!(sim.fish[1].health, MAX_HEALTH - THROW.damage);
+    assert!(sim.thrown.is_empty());
+
+    // hits once, then lands for anyone to pick
+    let hits = events
+        .iter()
+        .filter(|event| matches!(event, Event::BulletHit { .. }))
+        .count();
+    assert_eq!(hits, 1);
+    assert!(events.iter().any(|event| matches!(
+        event,
+        Event::PickupSpawned { .. } | Event::PickupTaken { .. }
+    )));
+}
+
+#[test]
+fn test_thrown_weapon_keeps_ammo() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 7);
+    run(&mut sim, &map, 60, &[]);
+
+    sim.pickups.push(Pickup {
+        id: 100,
+        pos: sim.fish[0].pos() + vec2(30., 20.),
+        item: Item::Weapon(GUN),
+        ammo: Some(1),
+        lived: 0.,
+    });
+
+    sim.step(&map, &[]);
+    assert_eq!(sim.fish[0].weapon.map(|weapon| weapon.ammo), Some(1));
+}
+
+#[test]
+fn test_throw_lands_as_pickup() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 7);
+    run(&mut sim, &map, 60, &[]);
+    sim.fish[0].weapon = armed(GUN);
+
+    sim.step(&map, &[THROW_INPUT]);
+    let thrown = sim.thrown[0].clone();
+    assert_eq!(thrown.owner, 0);
+
+    let events = run(&mut sim, &map, (THROW.lifetime / TICK) as usize, &[]);
+    let id = events
+        .iter()
+        .find_map(|event| match event {
+            Event::PickupSpawned { id } => Some(*id),
+            _ => None,
+        })
+        .unwrap();
+
+    let pickup = sim.pickups.iter().find(|pickup| pickup.id == id).unwrap();
+    assert_eq!(pickup.item, Item::Weapon(GUN));
+    assert_eq!(pickup.ammo, Some(thrown.weapon.ammo));
+    assert!(pickup.pos.x > thrown.pos.x);
+}
+
+#[test]
+fn test_sword_kills() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 50.);
+    sim.fish[0].weapon = armed(SWORD);
+
+    let events = sim.step(&map, &[FIRE]);
+    assert_eq!(
+        events,
+        vec![
+            Event::Attack {
+                fish: 0,
+                weapon: SWORD
+            },
+            Event::Death {
+                fish: 1,
+                killer: Some(0)
+            }
+        ]
+    );
+    // knocked back away from the sword
+    assert!(sim.fish[1].speed.x > 0.);
+
+    // busy swinging
+    assert!(sim.fish[0].is_busy());
+    run(&mut sim, &map, 20, &[]);
+    assert!(sim.fish[0].is_busy() == false);
+}
+
+#[test]
+fn test_sword_misses_behind() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 50.);
+    sim.fish[0].weapon = armed(SWORD);
+    sim.fish[0].facing = false;
+
+    let events = sim.step(&map, &[FIRE]);
+    assert_eq!(
+        events,
+        vec![Event::Attack {
+            fish: 0,
+            weapon: SWORD
+        }]
+    );
+}
+
+#[test]
+fn test_sword_deflects_bullet() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 200.);
+    sim.fish[0].weapon = armed(GUN);
+    sim.fish[1].weapon = armed(SWORD);
+
+    sim.step(&map, &[FIRE]);
+    // swing just as the bullet gets in reach
+    let mut events = vec![];
+    for _ in 0..60 {
+        let reach = sim.bullets[0].pos.x > sim.fish[1].pos().x - 30.;
+        let inputs = if reach {
+            [Input::default(), FIRE]
+        } else {
+            [Input::default(); 2]
+        };
+        events.extend(sim.step(&map, &inputs));
+        if events
+            .iter()
+            .any(|event| matches!(event, Event::Deflect { .. }))
+        {
+            break;
+        }
+    }
+
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, Event::Deflect { fish: 1, .. })));
+    assert_eq!(sim.bullets[0].owner, 1);
+    assert!(sim.bullets[0].speed.x < 0.);
+    assert_eq!(sim.fish[1].health, MAX_HEALTH);
+
+    // the shooter gets its own bullet back
+    let events = run(&mut sim, &map, 60, &[]);
+    assert!(events
+        .iter()
+        .any(|event| matches!(event, Event::BulletHit { .. })));
+    assert!(sim.fish[0].health < MAX_HEALTH);
+}
+
+#[test]
+fn test_sword_clash() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 50.);
+    sim.fish[0].weapon = armed(SWORD);
+    sim.fish[1].weapon = armed(SWORD);
+
+    let events = sim.step(&map, &[FIRE, FIRE]);
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| matches!(event, Event::Clash { .. }))
+            .count(),
+        1
+    );
+    assert!(events
+        .iter()
+        .all(|event| matches!(event, Event::Death { .. }) == false));
+
+    for fish in &sim.fish {
+        assert_eq!(fish.health, MAX_HEALTH);
+        assert!(matches!(fish.state, FishState::Knocked { .. }));
+    }
+    // pushed apart
+    assert!(sim.fish[0].speed.x < 0.);
+    assert!(sim.fish[1].speed.x > 0.);
+}
+
+#[test]
+fn test_clash_window() {
+    let clashes = |delay: usize| {
+        // the swords reach each other, but not the other fish
+        let (map, mut sim) = duel(GameType::Deathmatch, 100.);
+        sim.fish[0].weapon = armed(SWORD);
+        sim.fish[1].weapon = armed(SWORD);
+
+        let mut events = sim.step(&map, &[FIRE]);
+        events.extend(run(&mut sim, &map, delay, &[]));
+        events.extend(sim.step(&map, &[Input::default(), FIRE]));
+        events
+            .iter()
+            .any(|event| matches!(event, Event::Clash { .. }))
+    };
+
+    assert!(clashes(3));
+    assert!(clashes((PARRY_TIME / TICK) as usize + 1) == false);
+}
+
+#[test]
+fn test_deathmatch_respawn() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 200.);
+    sim.fish[1].weapon = armed(SWORD);
+    sim.fish[1].kill(true);
+
+    let mut events = vec![];
+    while events.contains(&Event::Respawn { fish: 1 }) == false {
+        assert!(sim.tick < 180);
+        events.extend(sim.step(&map, &[]));
+    }
+    assert!(events.contains(&Event::Explode { fish: 1 }));
+
+    assert_eq!(sim.fish[1].state, FishState::Normal);
+    assert_eq!(sim.fish[1].weapon, None);
+    assert_eq!(sim.winner, None);
+}
+
+#[test]
+fn test_last_fish_standing() {
+    let (map, mut sim) = duel(GameType::LastFishStanding { private: false }, 50.);
+    sim.fish[0].weapon = armed(SWORD);
+
+    let events = sim.step(&map, &[FIRE]);
+    assert!(events.contains(&Event::MatchOver { winner: Some(0) }));
+    assert_eq!(sim.winner, Some(Some(0)));
+
+    let events = run(&mut sim, &map, 180, &[]);
+    assert!(events
+        .iter()
+        .all(|event| matches!(event, Event::MatchOver { .. } | Event::Respawn { .. }) == false));
+    assert_eq!(sim.fish[1].state, FishState::Eliminated);
+}
+
+#[test]
+fn test_bullets_pass_eliminated_fish() {
+    let (map, mut sim) = duel(GameType::LastFishStanding { private: false }, 100.);
+    sim.fish[0].weapon = armed(GUN);
+    sim.fish[1].state = FishState::Eliminated;
+    let pos = sim.fish[1].pos();
+
+    sim.step(&map, &[FIRE]);
+    let events = run(&mut sim, &map, 20, &[]);
+
+    // right through where it stood, still flying
+    assert!(events
+        .iter()
+        .all(|event| matches!(event, Event::BulletHit { .. }) == false));
+    assert_eq!(sim.bullets.len(), 1);
+    assert!(sim.bullets[0].pos.x > pos.x + FISH_WIDTH as f32);
+    assert_eq!(sim.fish[1].pos(), pos);
+}
+
+#[test]
+fn test_stock_lives() {
+    let (map, mut sim) = duel(GameType::LastFishStanding { private: false }, 50.);
+    sim.set_lives(2);
+    sim.fish[0].weapon = armed(SWORD);
+
+    // the first death takes a life and respawns
+    let events = sim.step(&map, &[FIRE]);
+    assert!(events.contains(&Event::Death {
+        fish: 1,
+        killer: Some(0)
+    }));
+    assert_eq!(sim.fish[1].lives, 1);
+    assert_eq!(sim.winner, None);
+
+    let events = run(&mut sim, &map, 180, &[]);
+    assert!(events.contains(&Event::Respawn { fish: 1 }));
+    assert_eq!(sim.fish[1].state, FishState::Normal);
+    assert_eq!(sim.winner, None);
+
+    // the last one is final
+    sim.fish[1].kill(true);
+    assert_eq!(sim.fish[1].lives, 0);
+    let events = run(&mut sim, &map, 180, &[]);
+    assert!(events.contains(&Event::MatchOver { winner: Some(0) }));
+    assert_eq!(sim.fish[1].state, FishState::Eliminated);
+    assert_eq!(sim.fish[0].lives, 2);
+}
+
+#[test]
+fn test_everyone_dead_is_a_draw() {
+    let (map, mut sim) = duel(GameType::LastFishStanding { private: false }, 200.);
+    sim.fish[0].kill(true);
+    sim.fish[1].kill(true);
+
+    assert_eq!(sim.step(&map, &[]), vec![Event::MatchOver { winner: None }]);
+}
+
+#[test]
+fn test_friendly_fire() {
+    let (map, mut sim) = duel(GameType::LastFishStanding { private: false }, 50.);
+    sim.set_rules(&Rules {
+        teams: vec![Some(Team::Blue), Some(Team::Blue)],
+        ..Default::default()
+    });
+    sim.fish[0].weapon = armed(SWORD);
+
+    sim.step(&map, &[FIRE]);
+    run(&mut sim, &map, 30, &[]);
+    assert_eq!(sim.fish[1].health, MAX_HEALTH);
+
+    sim.friendly_fire = true;
+    let events = sim.step(&map, &[FIRE]);
+    assert!(events.contains(&Event::Death {
+        fish: 1,
+        killer: Some(0)
+    }));
+}
+
+#[test]
+fn test_last_team_standing() {
+    let map = test_map();
+    let mut sim = Simulation::new(
+        &map,
+        weapons(),
+        3,
+        GameType::LastFishStanding { private: false },
+        42,
+    );
+    sim.set_rules(&Rules {
+        teams: vec![Some(Team::Blue), Some(Team::Green), Some(Team::Green)],
+        ..Default::default()
+    });
+    sim.fish[0].body.set_pos(vec2(200., floor_y()));
+    sim.fish[1].body.set_pos(vec2(250., floor_y()));
+    sim.fish[2].body.set_pos(vec2(600., floor_y()));
+    sim.fish[0].weapon = armed(SWORD);
+    run(&mut sim, &map, 1, &[]);
+
+    let events = sim.step(&map, &[FIRE]);
+    assert_eq!(sim.scores, [1, 0]);
+    assert!(events
+        .iter()
+        .all(|event| matches!(event, Event::MatchOver { .. }) == false));
+
+    // `kill` skips the `Death` event, only hits count
+    sim.fish[0].kill(true);
+    let events = sim.step(&map, &[]);
+    assert_eq!(sim.scores, [1, 0]);
+    assert!(events.contains(&Event::MatchOver { winner: Some(2) }));
+    assert!(sim.is_winner(1));
+    assert!(sim.is_winner(0) == false);
+
+    assert_eq!(
+        Team::from_u8(Team::to_u8(Some(Team::Green))),
+        Some(Team::Green)
+    );
+    assert_eq!(Team::from_u8(Team::to_u8(None)), None);
+}
+
+/// Blue fish 0 and green fish 1 on `test_map` with the flag bases at both ends
+/// of the floor and the hill in between
+fn team_match(game_type: GameType) -> (Map, Simulation) {
+    let mut map = test_map();
+    let ground = FLOOR as f32 * 32.;
+    map.add_object("flag_blue", Rect::new(100., ground, 0., 0.));
+    map.add_object("flag_green", Rect::new(800., ground, 0., 0.));
+    map.add_object("hill", Rect::new(400., ground - 96., 128., 96.));
+
+    let mut sim = Simulation::new(&map, weapons(), 2, game_type, 42);
+    sim.set_rules(&Rules {
+        teams: vec![Some(Team::Blue), Some(Team::Green)],
+        ..Default::default()
+    });
+    sim.fish[0].body.set_pos(vec2(200., floor_y()));
+    sim.fish[1].body.set_pos(vec2(700., floor_y()));
+    run(&mut sim, &map, 1, &[]);
+
+    (map, sim)
+}
+
+#[test]
+fn test_capture_the_flag() {
+    let (map, mut sim) = team_match(GameType::CaptureTheFlag { private: false });
+
+    sim.fish[0].body.set_pos(vec2(790., floor_y()));
+    let events = sim.step(&map, &[]);
+    assert!(events.contains(&Event::FlagTaken {
+        fish: 0,
+        flag: Team::Green
+    }));
+    assert_eq!(sim.objectives.flags[Team::Green.index()], Flag::Carried(0));
+
+    sim.fish[0].body.set_pos(vec2(95., floor_y()));
+    let events = sim.step(&map, &[]);
+    assert!(events.contains(&Event::FlagCaptured {
+        fish: 0,
+        flag: Team::Green
+    }));
+    assert_eq!(sim.scores, [1, 0]);
+    assert_eq!(sim.objectives.flags, [Flag::AtBase; 2]);
+
+    // the last capture wins the match for the whole team
+    sim.scores = [1, CAPTURES_TO_WIN - 1];
+    sim.fish[1].body.set_pos(vec2(95., floor_y()));
+    sim.step(&map, &[]);
+    sim.fish[1].body.set_pos(vec2(790., floor_y()));
+    let events = sim.step(&map, &[]);
+    assert!(events.contains(&Event::MatchOver { winner: Some(1) }));
+    assert!(sim.is_winner(1));
+    assert!(sim.is_winner(0) == false);
+}
+
+#[test]
+fn test_dropped_flag() {
+    let (map, mut sim) = team_match(GameType::CaptureTheFlag { private: false });
+    sim.fish[0].body.set_pos(vec2(790., floor_y()));
+    sim.step(&map, &[]);
+
+    sim.fish[0].kill(true);
+    let events = sim.step(&map, &[]);
+    assert!(events.contains(&Event::FlagDropped { flag: Team::Green }));
+    let dropped = sim.clone();
+
+    // lying around for too long
+    let ticks = (objective::FLAG_RETURN_TIME / TICK) as usize + 1;
+    let events = run(&mut sim, &map, ticks, &[]);
+    assert!(events.contains(&Event::FlagReturned { flag: Team::Green }));
+    assert_eq!(sim.objectives.flags[Team::Green.index()], Flag::AtBase);
+    // no lives to run out of in the objective modes
+    assert!(sim.fish[0].is_dead() == false);
+
+    // or picked up by its own team
+    let mut sim = dropped;
+    sim.fish[1].body.set_pos(vec2(790., floor_y()));
+    let events = sim.step(&map, &[]);
+    assert!(events.contains(&Event::FlagReturned { flag: Team::Green }));
+}
+
+#[test]
+fn test_flag_carrier_after_a_leaver() {
+    let (map, _) = team_match(GameType::CaptureTheFlag { private: false });
+    let contender = |x: f32, team: Team| Contender {
+        pos: vec2(x, floor_y()),
+        team: Some(team),
+        alive: true,
+    };
+    let mut objectives = ObjectiveState::default();
+    objectives.flags[Team::Green.index()] = Flag::Carried(2);
+
+    // fish 0 leaves, the blue carrier is fish 1 of the ones left
+    objectives.fish_left(0, vec2(300., floor_y()));
+    assert_eq!(objectives.flags[Team::Green.index()], Flag::Carried(1));
+
+    let fish = [contender(700., Team::Green), contender(95., Team::Blue)];
+    let mut scores = [0; 2];
+    let mut events = vec![];
+    objectives.update(
+        GameType::CaptureTheFlag { private: false },
+        &map,
+        &fish,
+        &mut scores,
+        &mut events,
+    );
+    assert!(events.contains(&Event::FlagCaptured {
+        fish: 1,
+        flag: Team::Green
+    }));
+    assert_eq!(scores, [1, 0]);
+
+    // the carrier leaving drops the flag where it was
+    objectives.flags[Team::Green.index()] = Flag::Carried(1);
+    objectives.fish_left(1, vec2(300., floor_y()));
+    assert!(matches!(
+        objectives.flags[Team::Green.index()],
+        Flag::Dropped { .. }
+    ));
+}
+
+#[test]
+fn test_king_of_the_hill() {
+    let (map, mut sim) = team_match(GameType::KingOfTheHill { private: false });
+
+    sim.fish[0].body.set_pos(vec2(450., floor_y()));
+    let ticks = (objective::HILL_CAPTURE_TIME / TICK) as usize + 2;
+    let events = run(&mut sim, &map, ticks, &[]);
+    assert!(events.contains(&Event::HillTaken { team: Team::Blue }));
+    assert_eq!(sim.objectives.hill_owner(), Some(Team::Blue));
+
+    run(&mut sim, &map, 60, &[]);
+    assert_eq!(sim.scores, [1, 0]);
+
+    // contested, nobody scores and the meter stays
+    sim.fish[1].body.set_pos(vec2(480., floor_y()));
+    run(&mut sim, &map, 120, &[]);
+    assert_eq!(sim.scores, [1, 0]);
+    assert_eq!(sim.objectives.hill_owner(), Some(Team::Blue));
+
+    // green alone takes it back, first down to nobody, then for itself
+    sim.fish[0].body.set_pos(vec2(200., floor_y()));
+    run(&mut sim, &map, 30, &[]);
+    assert_eq!(sim.objectives.hill_owner(), None);
+    let events = run(&mut sim, &map, ticks * 2, &[]);
+    assert!(events.contains(&Event::HillTaken { team: Team::Green }));
+}
+
+#[test]
+fn test_map_supports() {
+    let mut map = test_map();
+    let ctf = GameType::CaptureTheFlag { private: false };
+    let koth = GameType::KingOfTheHill { private: false };
+    assert!(map.supports(GameType::LastFishStanding { private: false }));
+    assert!(map.supports(ctf) == false);
+    assert!(map.supports(koth) == false);
+
+    map.add_object("flag_blue", Rect::new(100., 480., 0., 0.));
+    assert!(map.supports(ctf) == false);
+    map.add_object("flag_green", Rect::new(800., 480., 0., 0.));
+    map.add_object("hill", Rect::new(400., 384., 128., 96.));
+    assert!(map.supports(ctf));
+    assert!(map.supports(koth));
+}
+
+#[test]
+fn test_pickups() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 7);
+    // out of reach of any pickup
+    sim.fish[0].state = FishState::Eliminated;
+
+    let ticks = (PICKUP_SPAWN_INTERVAL / TICK).round() as usize + 1;
+    let events = run(&mut sim, &map, ticks, &[]);
+    assert_eq!(events, vec![Event::PickupSpawned { id: 0 }]);
+    assert_eq!(sim.pickups.len(), 1);
+
+    run(&mut sim, &map, ticks * 3, &[]);
+    assert_eq!(sim.pickups.len(), MAX_PICKUPS);
+
+    let events = run(&mut sim, &map, (PICKUP_LIFETIME / TICK) as usize, &[]);
+    assert!(events.contains(&Event::PickupExpired { id: 0 }));
+}
+
+#[test]
+fn test_pick_weapon() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 7);
+    run(&mut sim, &map, 60, &[]);
+
+    let pos = sim.fish[0].pos() + vec2(30., 20.);
+    sim.pickups.push(Pickup {
+        id: 100,
+        pos,
+        item: Item::Weapon(SWORD),
+        ammo: None,
+        lived: 0.,
+    });
+
+    let events = sim.step(&map, &[]);
+    assert!(events.contains(&Event::PickupTaken { id: 100, fish: 0 }));
+    assert_eq!(sim.fish[0].weapon, armed(SWORD));
+    assert!(sim.pickups.is_empty());
+}
+
+#[test]
+fn test_pick_armor() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 7);
+    run(&mut sim, &map, 60, &[]);
+    sim.fish[0].weapon = armed(SWORD);
+
+    let pos = sim.fish[0].pos() + vec2(30., 20.);
+    sim.pickups.push(Pickup {
+        id: 100,
+        pos,
+        item: Item::Armor,
+        ammo: None,
+        lived: 0.,
+    });
+
+    sim.step(&map, &[]);
+    assert_eq!(sim.fish[0].armor, MAX_ARMOR);
+    // the weapon is kept
+    assert_eq!(sim.fish[0].weapon, armed(SWORD));
+    assert_eq!(Item::from_u8(Item::Armor.to_u8()), Item::Armor);
+    assert_eq!(Item::from_u8(GUN), Item::Weapon(GUN));
+}
+
+#[test]
+fn test_pick_power_up() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 7);
+    run(&mut sim, &map, 60, &[]);
+
+    let pos = sim.fish[0].pos() + vec2(30., 20.);
+    sim.pickups.push(Pickup {
+        id: 100,
+        pos,
+        item: Item::PowerUp(PowerUp::Speed),
+        ammo: None,
+        lived: 0.,
+    });
+
+    let right = Input {
+        right: true,
+        ..Default::default()
+    };
+    sim.step(&map, &[right]);
+    assert_eq!(sim.fish[0].power_up, Some(PowerUp::Speed));
+    sim.step(&map, &[right]);
+    assert_eq!(sim.fish[0].speed.x, consts::RUN_SPEED * SPEED_BOOST);
+
+    // wears off
+    run(&mut sim, &map, (POWER_UP_TIME / TICK) as usize, &[right]);
+    assert_eq!(sim.fish[0].power_up, None);
+    sim.step(&map, &[right]);
+    assert_eq!(sim.fish[0].speed.x, consts::RUN_SPEED);
+
+    for power_up in PowerUp::ALL.iter().copied() {
+        let item = Item::PowerUp(power_up);
+        assert_eq!(Item::from_u8(item.to_u8()), item);
+    }
+}
+
+#[test]
+fn test_high_jump() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 2, GameType::Deathmatch, 7);
+    run(&mut sim, &map, 60, &[]);
+    sim.fish[1].power_up = Some(PowerUp::HighJump);
+    sim.fish[1].power_up_time = POWER_UP_TIME;
+
+    let jump = Input {
+        jump: true,
+        ..Default::default()
+    };
+    sim.step(&map, &[jump, jump]);
+    assert_eq!(sim.fish[0].speed.y, -consts::JUMP_SPEED);
+    assert!(sim.fish[1].speed.y < sim.fish[0].speed.y);
+}
+
+#[test]
+fn test_shield_blocks_damage() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 200.);
+    sim.fish[0].weapon = armed(SWORD);
+    sim.fish[0].power_up = Some(PowerUp::Shield);
+    sim.fish[0].power_up_time = POWER_UP_TIME;
+    sim.fish[1].weapon = armed(GUN);
+    sim.fish[1].facing = false;
+
+    sim.step(&map, &[Input::default(), FIRE]);
+    run(&mut sim, &map, 60, &[]);
+    assert_eq!(sim.fish[0].health, MAX_HEALTH);
+    assert!(sim.bullets.is_empty());
+}
+
+#[test]
+fn test_infinite_ammo() {
+    let (map, mut sim) = duel(GameType::Deathmatch, 200.);
+    sim.fish[0].weapon = armed(GUN);
+    sim.fish[0].facing = false;
+    sim.fish[0].power_up = Some(PowerUp::InfiniteAmmo);
+    sim.fish[0].power_up_time = POWER_UP_TIME;
+
+    let ammo = weapons().get(GUN).unwrap().ammo;
+    for _ in 0..ammo + 1 {
+        sim.step(&map, &[FIRE]);
+        run(&mut sim, &map, 30, &[]);
+    }
+    assert_eq!(sim.fish[0].weapon, armed(GUN));
+}
+
+#[test]
+fn test_dead_fish_cant_pick() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 1, GameType::Deathmatch, 7);
+    sim.fish[0].kill(true);
+
+    let pos = sim.fish[0].pos();
+    sim.pickups.push(Pickup {
+        id: 100,
+        pos,
+        item: Item::Weapon(GUN),
+        ammo: None,
+        lived: 0.,
+    });
+
+    sim.step(&map, &[]);
+    assert_eq!(sim.pickups.len(), 1);
+    assert_eq!(sim.fish[0].weapon, None);
+}
+
+#[test]
+fn test_weapons_file() {
+    let weapons = weapons();
+    let ids: Vec<WeaponId> = weapons.ids().collect();
+
+    for (n, id) in ids.iter().enumerate() {
+        assert!(
+            ids[n + 1..].contains(id) == false,
+            "duplicate weapon id {}",
+            id
+        );
+    }
+    assert!(weapons.get(GUN).unwrap().projectile.is_some());
+    assert!(weapons.get(SWORD).unwrap().melee.is_some());
+    assert_eq!(weapons.get(0), None);
+}
+
+/// Scripted inputs: the first fish runs around shooting, the second one jumps
+fn scripted_inputs(tick: usize) -> [Input; 2] {
+    [
+        Input {
+            left: tick % 200 < 100,
+            right: tick % 200 >= 100,
+            fire: tick % 45 == 0,
+            jump: tick % 70 == 0,
+            throw: tick % 300 == 250,
+            dash: tick % 150 == 75,
+            ..Default::default()
+        },
+        Input {
+            jump: tick % 30 == 0,
+            right: tick % 90 < 30,
+            ..Default::default()
+        },
+    ]
+}
+
+#[test]
+fn test_determinism() {
+    let map = test_map();
+    let mut a = Simulation::new(&map, weapons(), 2, GameType::Deathmatch, 1234);
+    let mut b = Simulation::new(&map, weapons(), 2, GameType::Deathmatch, 1234);
+    a.movement = Movement::ALL;
+    b.movement = Movement::ALL;
+
+    for tick in 0..2000 {
+        let inputs = scripted_inputs(tick);
+        assert_eq!(a.step(&map, &inputs), b.step(&map, &inputs));
+    }
+    assert_eq!(a, b);
+    assert!(a.pickups.len() + a.bullets.len() > 0 || a.fish[0].weapon.is_some());
+}
+
+#[test]
+fn test_snapshot_resimulation() {
+    let map = test_map();
+    let mut sim = Simulation::new(&map, weapons(), 2, GameType::Deathmatch, 99);
+
+    for tick in 0..500 {
+        sim.step(&map, &scripted_inputs(tick));
+    }
+    let snapshot = sim.clone();
+
+    for tick in 500..1000 {
+        sim.step(&map, &scripted_inputs(tick));
+    }
+
+    let mut replay = snapshot;
+    for tick in 500..1000 {
+        replay.step(&map, &scripted_inputs(tick));
+    }
+    assert_eq!(sim, replay);
+    assert_eq!(sim.tick, 1000);
+}
+
+#[test]
+fn test_input_bits() {
+    for bits in 0..=255 {
+        assert_eq!(Input::from_bits(bits).to_bits(), bits);
+    }
+    assert_eq!(Input::from_bits(FIRE.to_bits()), FIRE);
+
+    for bits in 0..8 {
+        assert_eq!(Movement::from_bits(bits).to_bits(), bits);
+    }
+}
+
+#[test]
+fn test_rollback_stalls_without_remote_inputs() {
+    let map = test_map();
+    let mut session = rollback::Session::new(
+        &map,
+        weapons(),
+        2,
+        0,
+        GameType::Deathmatch,
+        &Rules::default(),
+        7,
+    );
+
+    let mut ticks = 0;
+    while session.can_advance() {
+        session.add_local_input(Input::default());
+        session.advance(&map);
+        ticks += 1;
+    }
+    assert_eq!(ticks, rollback::INPUT_DELAY + rollback::MAX_PREDICTION);
+
+    session.disconnect(1);
+    assert!(session.can_advance());
+}
+
+#[test]
+fn test_rollback_matches_lockstep() {
+    const LATENCY: usize = 5;
+    const FRAMES: usize = 1200;
+    // the inputs the second player sends meanwhile only arrive at the end of it
+    const LAG_SPIKE: Range<usize> = 500..560;
+
+    let map = test_map();
+    let mut sessions = [
+        rollback::Session::new(
+            &map,
+            weapons(),
+            2,
+            0,
+            GameType::Deathmatch,
+            &Rules {
+                movement: Movement::ALL,
+                ..Default::default()
+            },
+            3,
+        ),
+        rollback::Session::new(
+            &map,
+            weapons(),
+            2,
+            1,
+            GameType::Deathmatch,
+            &Rules {
+                movement: Movement::ALL,
+                ..Default::default()
+            },
+            3,
+        ),
+    ];
+    // (frame to deliver at, receiver, sender, tick, input)
+    let mut in_flight = vec![];
+    let mut sent = vec![[Input::default(); 2]; rollback::INPUT_DELAY as usize + 1];
+    let mut stalls = [0; 2];
+
+    for frame in 0..FRAMES {
+        for n in 0..2 {
+            in_flight.retain(|&(at, to, from, tick, input)| {
+                if at <= frame && to == n {
+                    sessions[n].add_remote_input(from, tick, input);
+                    return false;
+                }
+                true
+            });
+
+            // a tick per frame, skipped when too far ahead, as `NakamaRollbackGame` does
+            if sessions[n].can_advance() == false {
+                stalls[n] += 1;
+                continue;
+            }
+            let session = &mut sessions[n];
+            let input = scripted_inputs(session.sim().tick as usize)[n];
+            let tick = session.add_local_input(input);
+
+            sent.resize(sent.len().max(tick as usize + 1), Default::default());
+            sent[tick as usize][n] = input;
+            let at = match n == 1 && LAG_SPIKE.contains(&frame) {
+                true => LAG_SPIKE.end,
+                false => frame + LATENCY,
+            };
+            in_flight.push((at, 1 - n, n, tick, input));
+            session.advance(&map);
+        }
+    }
+
+    // both waited for the other during the spike, then went on together
+    assert!(stalls.iter().all(|stalls| *stalls > 0));
+    let (first, second) = (sessions[0].sim().tick, sessions[1].sim().tick);
+    assert!(first.max(second) - first.min(second) <= rollback::MAX_PREDICTION);
+
+    for session in &sessions {
+        let confirmed = session.confirmed_sim();
+        assert!(session.rollbacks() > 0);
+        assert!(confirmed.tick > (FRAMES - LAG_SPIKE.len() * 2) as u64);
+
+        // the same match played with every input known in advance
+        let mut lockstep = Simulation::new(&map, weapons(), 2, GameType::Deathmatch, 3);
+        lockstep.movement = Movement::ALL;
+        for inputs in &sent[1..=confirmed.tick as usize] {
+            lockstep.step(&map, inputs);
+        }
+        assert_eq!(*confirmed, lockstep);
+    }
+}
@@ -0,0 +1,63 @@
+use crate::consts;
+
+/// Accumulates frame time and splits it into fixed simulation ticks,
+/// so jump height and knockback are the same on 30 and 144 fps clients.
+pub struct FixedTimestep {
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    pub const DT: f32 = 1. / consts::SIMULATION_FPS;
+
+    // after a long stall (tab in background, loading hiccup) drop the
+    // remaining time instead of trying to catch up all at once
+    const MAX_TICKS_PER_FRAME: usize = 8;
+
+    pub fn new() -> FixedTimestep {
+        FixedTimestep { accumulator: 0.0 }
+    }
+
+    /// Add this frame's time and return the amount of ticks to simulate.
+    pub fn advance(&mut self, frame_time: f32) -> usize {
+        self.accumulator += frame_time;
+
+        let ticks = (self.accumulator / Self::DT) as usize;
+        self.accumulator -= ticks as f32 * Self::DT;
+
+        if ticks > Self::MAX_TICKS_PER_FRAME {
+            self.accumulator = 0.0;
+            return Self::MAX_TICKS_PER_FRAME;
+        }
+        ticks
+    }
+
+    /// How far the renderer is between the last and the next tick, 0..1
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / Self::DT
+    }
+}
+
+/// Frame-rate independent version of `speed *= factor`,
+/// with `factor` tuned for a 60 fps frame.
+pub fn damping(factor: f32, dt: f32) -> f32 {
+    factor.powf(dt * 60.)
+}
+
+#[test]
+fn test_fixed_timestep() {
+    let mut timestep = FixedTimestep::new();
+
+    // 30 and 144 fps clients get the same amount of ticks over a second
+    let slow: usize = (0..30).map(|_| timestep.advance(1. / 30.)).sum();
+    let mut timestep = FixedTimestep::new();
+    let fast: usize = (0..144).map(|_| timestep.advance(1. / 144.)).sum();
+    assert!((slow as i32 - consts::SIMULATION_FPS as i32).abs() <= 1);
+    assert!((fast as i32 - consts::SIMULATION_FPS as i32).abs() <= 1);
+
+    let mut timestep = FixedTimestep::new();
+    assert_eq!(timestep.advance(10.0), 8);
+    assert_eq!(timestep.alpha(), 0.0);
+
+    assert!((damping(0.9, 1. / 60.) - 0.9).abs() < 0.0001);
+    assert!((damping(0.9, 1. / 30.) - 0.81).abs() < 0.0001);
+}
@@ -0,0 +1,351 @@
+//! Weapon definitions, loaded from `assets/weapons.json`.
+//!
+//! Everything is described for a fish facing right, for a fish facing left
+//! the offsets are mirrored around the middle of the fish.
+
+use macroquad::math::{vec2, Rect, Vec2};
+use nanoserde::DeJson;
+use std::fmt;
+
+use crate::{
+    consts,
+    simulation::{Item, FISH_WIDTH},
+};
+
+/// What pickups and the network protocol refer to a weapon by, never 0.
+pub type WeaponId = u8;
+
+#[derive(Debug, Clone, Copy, PartialEq, DeJson)]
+pub struct Offset {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Offset {
+    pub fn facing(&self, facing: bool) -> Vec2 {
+        if facing {
+            vec2(self.x, self.y)
+        } else {
+            vec2(FISH_WIDTH as f32 - self.x, self.y)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, DeJson)]
+pub struct Area {
+    pub x: f32,
+    pub y: f32,
+    pub w: f32,
+    pub h: f32,
+}
+
+impl Area {
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.w, self.h)
+    }
+
+    /// The area around a fish at `pos`.
+    pub fn facing(&self, pos: Vec2, facing: bool) -> Rect {
+        let x = if facing {
+            self.x
+        } else {
+            FISH_WIDTH as f32 - self.x - self.w
+        };
+        Rect::new(pos.x + x, pos.y + self.y, self.w, self.h)
+    }
+}
+
+/// Color multiplied with a texture, lets weapons share the same art.
+#[derive(Debug, Clone, Copy, PartialEq, DeJson)]
+pub struct Tint {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, DeJson)]
+pub struct SpriteAnimation {
+    pub row: u32,
+    pub frames: u32,
+    pub fps: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, DeJson)]
+pub struct WeaponSprite {
+    pub texture: String,
+    pub tint: Tint,
+    pub width: u32,
+    pub height: u32,
+    /// Top left corner of the sprite relative to the fish
+    pub mount: Offset,
+    pub idle: SpriteAnimation,
+    pub attack: SpriteAnimation,
+    /// Drawn over the weapon during an attack, like a muzzle flash
+    pub effect: Option<SpriteAnimation>,
+}
+
+impl WeaponSprite {
+    pub fn mount(&self, facing: bool) -> Vec2 {
+        if facing {
+            vec2(self.mount.x, self.mount.y)
+        } else {
+            vec2(
+                FISH_WIDTH as f32 - self.mount.x - self.width as f32,
+                self.mount.y,
+            )
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, DeJson)]
+pub struct PickupIcon {
+    /// Part of the weapon texture to draw
+    pub source: Area,
+    /// Where to draw it, relative to the pickup
+    pub dest: Area,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, DeJson)]
+pub struct Projectile {
+    pub speed: f32,
+    pub lifetime: f32,
+    /// Where projectiles appear, relative to the fish
+    pub muzzle: Offset,
+    /// Degrees above the horizontal the projectiles are launched at
+    pub angle: f32,
+    /// Projectiles fired at once, fanned out evenly over `spread` degrees
+    pub count: u32,
+    pub spread: f32,
+    /// Share of `consts::GRAVITY` pulling the projectile down
+    pub gravity: f32,
+    /// Share of the speed kept when bouncing off tiles, 0 to break on them
+    pub bounce: f32,
+    /// Blast reaching every fish this close when the projectile hits a fish, breaks on
+    /// a tile or its lifetime is over, 0 for no explosion. Only the blast deals
+    /// `damage` and `knockback` then, not the hit itself
+    pub explosion_radius: f32,
+    /// Speed a fish surviving the hit is pulled toward the shooter with, 0 for none
+    pub pull: f32,
+    pub damage: i32,
+    /// Speed a hit fish is pushed away with
+    pub knockback: f32,
+    /// Size of the dot the projectile is drawn as, in pixels
+    pub radius: f32,
+    /// Color of that dot
+    pub color: Tint,
+}
+
+impl Projectile {
+    /// Positions and speeds of the projectiles fired by a fish at `pos`.
+    pub fn spawn(&self, pos: Vec2, facing: bool) -> Vec<(Vec2, Vec2)> {
+        let dir = if facing { 1. } else { -1. };
+        let pos = pos + self.muzzle.facing(facing);
+
+        (0..self.count)
+            .map(|n| {
+                let spread = if self.count > 1 {
+                    self.spread * (n as f32 / (self.count - 1) as f32 - 0.5)
+                } else {
+                    0.
+                };
+                let angle = (self.angle + spread).to_radians();
+
+                (pos, vec2(dir * angle.cos(), -angle.sin()) * self.speed)
+            })
+            .collect()
+    }
+
+    /// Move a projectile for `dt`, returns false when it broke on a tile.
+    pub fn fly(
+        &self,
+        pos: &mut Vec2,
+        speed: &mut Vec2,
+        dt: f32,
+        solid_at: impl Fn(Vec2) -> bool,
+    ) -> bool {
+        speed.y += consts::GRAVITY * self.gravity * dt;
+
+        let next = *pos + *speed * dt;
+        if solid_at(next) == false {
+            *pos = next;
+            return true;
+        }
+        if self.bounce == 0. {
+            *pos = next;
+            return false;
+        }
+
+        // reflect off the side that was hit, both sides in a corner
+        let hit_x = solid_at(vec2(next.x, pos.y));
+        let hit_y = solid_at(vec2(pos.x, next.y));
+        if hit_x || hit_y == false {
+            speed.x = -speed.x * self.bounce;
+        }
+        if hit_y || hit_x == false {
+            speed.y = -speed.y * self.bounce;
+        }
+        true
+    }
+
+    /// Speed of a fish hit by a projectile flying with `speed`, toward where it came from.
+    pub fn pull_speed(&self, speed: Vec2) -> Vec2 {
+        let dir = if speed.x > 0. { -1. } else { 1. };
+
+        vec2(dir * self.pull, -150.)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, DeJson)]
+pub struct Melee {
+    /// Fish overlapping it when the attack starts are hit
+    pub hit_box: Area,
+    pub damage: i32,
+    /// Speed a hit fish is pushed away with
+    pub knockback: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, DeJson)]
+pub struct WeaponDef {
+    pub id: WeaponId,
+    pub name: String,
+    pub sprite: WeaponSprite,
+    pub pickup: PickupIcon,
+    /// Played on each attack
+    pub sound: String,
+    /// Attacks before the weapon is gone, 0 for unlimited
+    pub ammo: i32,
+    /// Speed the fish is pushed back with on each attack
+    pub recoil: f32,
+    /// How long an attack takes, the fish can't attack again or pick things up meanwhile
+    pub cooldown: f32,
+    pub projectile: Option<Projectile>,
+    pub melee: Option<Melee>,
+}
+
+impl WeaponDef {
+    /// Frame of the attack animation `timer` seconds into an attack.
+    pub fn attack_frame(&self, timer: f32) -> u32 {
+        let frames = self.sprite.attack.frames;
+
+        ((timer / self.cooldown * frames as f32) as u32).min(frames - 1)
+    }
+}
+
+/// Something wrong with `assets/weapons.json`, named after the weapon it is about.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WeaponError {
+    Json(String),
+    /// 0 and the ids from `Item::POWER_UPS` up stand for other items on the wire
+    ReservedId {
+        weapon: String,
+        id: WeaponId,
+    },
+    DuplicateId {
+        weapon: String,
+        id: WeaponId,
+    },
+    /// `animation` is the sprite's `idle`, `attack` or `effect`
+    NoFrames {
+        weapon: String,
+        animation: &'static str,
+    },
+    NoCooldown {
+        weapon: String,
+    },
+}
+
+impl fmt::Display for WeaponError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WeaponError::Json(error) => write!(f, "not a weapon list: {}", error),
+            WeaponError::ReservedId { weapon, id } => write!(
+                f,
+                "`{}` has id {}, expected 1 to {}",
+                weapon,
+                id,
+                Item::POWER_UPS - 1
+            ),
+            WeaponError::DuplicateId { weapon, id } => {
+                write!(
+                    f,
+                    "`{}` has id {}, already taken by another weapon",
+                    weapon, id
+                )
+            }
+            WeaponError::NoFrames { weapon, animation } => {
+                write!(
+                    f,
+                    "`{}` has no frames in its {} animation",
+                    weapon, animation
+                )
+            }
+            WeaponError::NoCooldown { weapon } => {
+                write!(
+                    f,
+                    "`{}` has no cooldown, attacks need to take some time",
+                    weapon
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, DeJson)]
+pub struct Weapons {
+    pub weapons: Vec<WeaponDef>,
+}
+
+impl Weapons {
+    /// The weapon list, as long as every weapon can be sent over the network and drawn.
+    pub fn from_json(json: &str) -> Result<Weapons, WeaponError> {
+        let weapons: Weapons =
+            DeJson::deserialize_json(json).map_err(|error| WeaponError::Json(error.to_string()))?;
+
+        for (n, weapon) in weapons.weapons.iter().enumerate() {
+            let name = || weapon.name.clone();
+
+            if weapon.id == 0 || weapon.id >= Item::POWER_UPS {
+                return Err(WeaponError::ReservedId {
+                    weapon: name(),
+                    id: weapon.id,
+                });
+            }
+            if weapons.weapons[..n]
+                .iter()
+                .any(|other| other.id == weapon.id)
+            {
+                return Err(WeaponError::DuplicateId {
+                    weapon: name(),
+                    id: weapon.id,
+                });
+            }
+            let sprite = &weapon.sprite;
+            let animations = [
+                ("idle", Some(sprite.idle)),
+                ("attack", Some(sprite.attack)),
+                ("effect", sprite.effect),
+            ];
+            for &(animation, sprite) in &animations {
+                if sprite.map_or(false, |sprite| sprite.frames == 0) {
+                    return Err(WeaponError::NoFrames {
+                        weapon: name(),
+                        animation,
+                    });
+                }
+            }
+            // NaN is turned down too
+            if (weapon.cooldown > 0.) == false {
+                return Err(WeaponError::NoCooldown { weapon: name() });
+            }
+        }
+        Ok(weapons)
+    }
+
+    pub fn get(&self, id: WeaponId) -> Option<&WeaponDef> {
+        self.weapons.iter().find(|weapon| weapon.id == id)
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = WeaponId> + '_ {
+        self.weapons.iter().map(|weapon| weapon.id)
+    }
+}